@@ -0,0 +1,461 @@
+//! 访问日志模块
+//!
+//! 每个请求结束时写出一条访问日志记录，与 RUST_LOG 控制的调试日志相互独立。
+//! 支持 JSON 与 Common Log Format 两种格式，输出到标准输出或按大小轮转的文件
+
+use crate::config::{AccessLogConfig, AccessLogFormat, AccessLogSink};
+use crate::secrets;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// 全局访问日志写入器
+static ACCESS_LOG: Lazy<Mutex<AccessLogWriter>> =
+    Lazy::new(|| Mutex::new(AccessLogWriter::default()));
+
+/// 单条访问日志记录
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AccessLogRecord {
+    /// RFC 3339 格式的 UTC 时间戳
+    pub timestamp: String,
+    /// 请求开始时间，用于 CLF 格式
+    #[serde(skip)]
+    pub time: Option<SystemTime>,
+    pub request_id: String,
+    pub client_ip: String,
+    /// Key 别名或脱敏后的 Key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub method: String,
+    pub route: String,
+    #[serde(skip_serializing)]
+    pub http_version: String,
+    /// 客户端请求的模型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested_model: Option<String>,
+    /// 映射后的上游模型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// 返回给客户端的状态码
+    pub status: u16,
+    /// 上游返回的状态码，未收到上游响应时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<u16>,
+    /// 请求字节数（含请求头）
+    pub bytes_in: u64,
+    /// 响应正文字节数
+    pub bytes_out: u64,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttfb_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u64>,
+    /// 错误分类（与 upstream_errors_total 的 error_type 一致）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 响应来自响应缓存
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cache_hit: bool,
+}
+
+impl AccessLogRecord {
+    /// 按指定格式渲染为单行文本（不含换行符）
+    pub fn render(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+            AccessLogFormat::Clf => self.to_common_log_format(),
+        }
+    }
+
+    /// Common Log Format：`host ident authuser [date] "request" status bytes`
+    fn to_common_log_format(&self) -> String {
+        let bytes = if self.bytes_out == 0 {
+            "-".to_string()
+        } else {
+            self.bytes_out.to_string()
+        };
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {}",
+            dash_if_empty(&self.client_ip),
+            dash_if_empty(self.key.as_deref().unwrap_or_default()),
+            format_clf_time(self.time.unwrap_or(UNIX_EPOCH)),
+            self.method,
+            self.route,
+            dash_if_empty(&self.http_version),
+            self.status,
+            bytes
+        )
+    }
+}
+
+fn dash_if_empty(value: &str) -> &str {
+    if value.is_empty() {
+        "-"
+    } else {
+        value
+    }
+}
+
+/// 请求级访问日志条目
+///
+/// 在请求处理过程中逐步填充，离开作用域时写出一次，保证每个请求（包括提前返回的分支）
+/// 恰好产生一条记录
+pub struct AccessLogEntry {
+    pub record: AccessLogRecord,
+    start: Instant,
+}
+
+impl AccessLogEntry {
+    pub fn new(request_id: &str, client_ip: &str, bytes_in: usize) -> Self {
+        let now = SystemTime::now();
+        Self {
+            record: AccessLogRecord {
+                timestamp: format_rfc3339(now),
+                time: Some(now),
+                request_id: request_id.to_string(),
+                client_ip: client_ip.to_string(),
+                bytes_in: bytes_in as u64,
+                ..AccessLogRecord::default()
+            },
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for AccessLogEntry {
+    fn drop(&mut self) {
+        self.record.latency_ms = self.start.elapsed().as_secs_f64() * 1000.0;
+        write_record(&self.record);
+    }
+}
+
+/// 应用访问日志配置
+///
+/// 配置未变化时不做任何事；变化后关闭旧文件并按新配置打开输出
+pub fn configure(config: Option<&AccessLogConfig>) {
+    if let Ok(mut writer) = ACCESS_LOG.lock() {
+        writer.configure(config);
+    }
+}
+
+/// 写出一条访问日志记录，未启用时忽略
+pub fn write_record(record: &AccessLogRecord) {
+    if let Ok(mut writer) = ACCESS_LOG.lock() {
+        if let Err(err) = writer.write(record) {
+            tracing::warn!("Failed to write access log: {}", err);
+        }
+    }
+}
+
+/// 将已写出的访问日志刷新到磁盘
+pub fn flush() {
+    if let Ok(mut writer) = ACCESS_LOG.lock() {
+        if let Err(err) = writer.flush() {
+            tracing::warn!("Failed to flush access log: {}", err);
+        }
+    }
+}
+
+/// 访问日志写入器
+#[derive(Default)]
+struct AccessLogWriter {
+    config: Option<AccessLogConfig>,
+    file: Option<RotatingFile>,
+}
+
+impl AccessLogWriter {
+    fn configure(&mut self, config: Option<&AccessLogConfig>) {
+        if self.config.as_ref() == config {
+            return;
+        }
+        self.config = config.cloned();
+        self.file = None;
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.file.sync_data(),
+            None => io::stdout().lock().flush(),
+        }
+    }
+
+    fn write(&mut self, record: &AccessLogRecord) -> io::Result<()> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        let mut line = secrets::redact(&record.render(config.format)).into_owned();
+        line.push('\n');
+
+        match config.sink {
+            AccessLogSink::Stdout => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(line.as_bytes())?;
+                stdout.flush()
+            }
+            AccessLogSink::File => {
+                if self.file.is_none() {
+                    self.file = Some(RotatingFile::open(
+                        PathBuf::from(&config.path),
+                        config.max_bytes,
+                        config.max_files,
+                    )?);
+                }
+                match self.file.as_mut() {
+                    Some(file) => file.write_line(line.as_bytes()),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// 按大小轮转的日志文件
+///
+/// 当前文件超过 `max_bytes` 时依次重命名为 `path.1`、`path.2`…，最多保留 `max_files` 个历史文件
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.max_bytes > 0 && self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.file.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated_path(&self.path, self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// 将 Unix 天数转换为公历日期 (year, month, day)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn split_time(time: SystemTime) -> (i64, u32, u32, u64, u64, u64, u32) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    (
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60,
+        since_epoch.subsec_millis(),
+    )
+}
+
+/// 格式化为 RFC 3339 UTC 时间（毫秒精度），例如 `2024-05-01T08:30:00.123Z`
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second, millis) = split_time(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, hour, minute, second, millis
+    )
+}
+
+/// 格式化为 Common Log Format 时间，例如 `01/May/2024:08:30:00 +0000`
+fn format_clf_time(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (year, month, day, hour, minute, second, _) = split_time(time);
+    format!(
+        "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn sample_record() -> AccessLogRecord {
+        let time = UNIX_EPOCH + Duration::from_millis(1_714_552_200_123);
+        AccessLogRecord {
+            timestamp: format_rfc3339(time),
+            time: Some(time),
+            request_id: "req-1".to_string(),
+            client_ip: "10.0.0.1".to_string(),
+            key: Some("team-a".to_string()),
+            method: "POST".to_string(),
+            route: "/v1/chat/completions".to_string(),
+            http_version: "HTTP/1.1".to_string(),
+            requested_model: Some("gpt-4".to_string()),
+            model: Some("qwen3-coder-max".to_string()),
+            provider: Some("qwen".to_string()),
+            status: 200,
+            upstream_status: Some(200),
+            bytes_in: 321,
+            bytes_out: 1024,
+            latency_ms: 12.5,
+            upstream_latency_ms: Some(10.0),
+            ttfb_ms: None,
+            prompt_tokens: Some(12),
+            completion_tokens: Some(34),
+            error: None,
+            cache_hit: false,
+        }
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_714_552_200_123);
+        assert_eq!(format_rfc3339(time), "2024-05-01T08:30:00.123Z");
+        assert_eq!(format_clf_time(time), "01/May/2024:08:30:00 +0000");
+        assert_eq!(format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            format_rfc3339(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00.000Z"
+        );
+    }
+
+    #[test]
+    fn renders_json_record() {
+        let line = sample_record().render(AccessLogFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["timestamp"], "2024-05-01T08:30:00.123Z");
+        assert_eq!(value["key"], "team-a");
+        assert_eq!(value["requested_model"], "gpt-4");
+        assert_eq!(value["model"], "qwen3-coder-max");
+        assert_eq!(value["status"], 200);
+        assert_eq!(value["upstream_status"], 200);
+        assert_eq!(value["prompt_tokens"], 12);
+        assert!(value.get("ttfb_ms").is_none());
+        assert!(value.get("error").is_none());
+        assert!(value.get("http_version").is_none());
+    }
+
+    #[test]
+    fn renders_common_log_format() {
+        let line = sample_record().render(AccessLogFormat::Clf);
+        assert_eq!(
+            line,
+            "10.0.0.1 - team-a [01/May/2024:08:30:00 +0000] \"POST /v1/chat/completions HTTP/1.1\" 200 1024"
+        );
+
+        let mut record = sample_record();
+        record.key = None;
+        record.bytes_out = 0;
+        assert!(record
+            .render(AccessLogFormat::Clf)
+            .starts_with("10.0.0.1 - - ["));
+        assert!(record.render(AccessLogFormat::Clf).ends_with(" 200 -"));
+    }
+
+    #[test]
+    fn rotating_file_keeps_bounded_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("access.log");
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+        for line in ["aaaaaa\n", "bbbbbb\n", "cccccc\n", "dddddd\n"] {
+            file.write_line(line.as_bytes()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "dddddd\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "cccccc\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "bbbbbb\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+    }
+
+    #[test]
+    fn writer_appends_to_configured_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let mut writer = AccessLogWriter::default();
+        writer.write(&sample_record()).unwrap();
+        assert!(!path.exists());
+
+        writer.configure(Some(&AccessLogConfig {
+            format: AccessLogFormat::Clf,
+            sink: AccessLogSink::File,
+            path: path.to_string_lossy().into_owned(),
+            ..AccessLogConfig::default()
+        }));
+        writer.write(&sample_record()).unwrap();
+        writer.write(&sample_record()).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.starts_with("10.0.0.1 - team-a ["));
+    }
+}
@@ -0,0 +1,366 @@
+//! 上游故障告警投递模块
+//!
+//! 定义告警事件、可插拔的投递接口 [`AlertSink`] 以及内置的 webhook 实现（通用 JSON 与
+//! Slack 兼容格式）。告警经有界队列交给后台线程投递，webhook 复用路由器自身的 HTTP 客户端
+
+use crate::access_log::format_rfc3339;
+use crate::config::{AlertingConfig, WebhookConfig, WebhookFormat};
+use crate::errors::RouterResult;
+use crate::http_client::send_http_request;
+use async_channel::{Receiver, Sender};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// 待投递告警队列上限，超过后丢弃新告警
+const MAX_QUEUED_ALERTS: usize = 64;
+
+/// 额外注册的告警投递目标
+static REGISTERED_SINKS: Lazy<RwLock<Vec<Arc<dyn AlertSink>>>> =
+    Lazy::new(|| RwLock::new(Vec::new()));
+
+/// 待投递的告警及其投递目标
+type Delivery = (Alert, Vec<Arc<dyn AlertSink>>);
+
+/// 后台投递队列
+static DISPATCHER: Lazy<Sender<Delivery>> = Lazy::new(|| {
+    let (sender, receiver) = async_channel::bounded(MAX_QUEUED_ALERTS);
+    spawn_worker(receiver);
+    sender
+});
+
+/// 单个提供商生效的告警规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlertRule {
+    /// 窗口内触发告警的失败次数
+    pub threshold: u64,
+    /// 失败计数窗口
+    pub window: Duration,
+    /// 两次告警的最小间隔
+    pub throttle: Duration,
+}
+
+impl Default for AlertRule {
+    fn default() -> Self {
+        Self::from_config(&AlertingConfig::default())
+    }
+}
+
+impl AlertRule {
+    fn from_config(config: &AlertingConfig) -> Self {
+        Self {
+            threshold: config.threshold,
+            window: Duration::from_secs(config.window_secs),
+            throttle: Duration::from_secs(config.throttle_secs),
+        }
+    }
+
+    /// 合并全局配置与提供商覆盖，未配置告警时使用默认规则
+    pub fn resolve(config: Option<&AlertingConfig>, provider: &str) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        let mut rule = Self::from_config(config);
+        if let Some(overrides) = config.providers.get(provider) {
+            if let Some(threshold) = overrides.threshold {
+                rule.threshold = threshold;
+            }
+            if let Some(window) = overrides.window_secs {
+                rule.window = Duration::from_secs(window);
+            }
+            if let Some(throttle) = overrides.throttle_secs {
+                rule.throttle = Duration::from_secs(throttle);
+            }
+        }
+        rule.threshold = rule.threshold.max(1);
+        rule
+    }
+}
+
+/// 告警状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    /// 窗口内失败次数达到阈值
+    Firing,
+    /// 告警后提供商重新成功响应
+    Recovered,
+}
+
+impl AlertState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertState::Firing => "firing",
+            AlertState::Recovered => "recovered",
+        }
+    }
+}
+
+/// 一次告警或恢复通知
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub state: AlertState,
+    pub provider: String,
+    /// 触发时窗口内的失败次数
+    pub failures: u64,
+    pub window: Duration,
+    /// 最近一次错误信息（恢复通知为 None）
+    pub error: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+impl Alert {
+    /// 人类可读的告警摘要
+    pub fn summary(&self) -> String {
+        match self.state {
+            AlertState::Firing => format!(
+                "Upstream provider {} failed {} times in {}s: {}",
+                self.provider,
+                self.failures,
+                self.window.as_secs(),
+                self.error.as_deref().unwrap_or("unknown error")
+            ),
+            AlertState::Recovered => {
+                format!("Upstream provider {} has recovered", self.provider)
+            }
+        }
+    }
+
+    /// 通用 webhook JSON 负载
+    pub fn generic_payload(&self) -> Value {
+        json!({
+            "status": self.state.as_str(),
+            "provider": self.provider,
+            "failures": self.failures,
+            "windowSecs": self.window.as_secs(),
+            "error": self.error,
+            "message": self.summary(),
+            "timestamp": format_rfc3339(self.timestamp),
+        })
+    }
+
+    /// Slack incoming webhook 兼容负载
+    pub fn slack_payload(&self) -> Value {
+        let icon = match self.state {
+            AlertState::Firing => ":rotating_light:",
+            AlertState::Recovered => ":white_check_mark:",
+        };
+        json!({ "text": format!("{} {}", icon, self.summary()) })
+    }
+}
+
+/// 告警投递目标
+///
+/// 在后台投递线程中调用，可以阻塞
+pub trait AlertSink: Send + Sync {
+    fn deliver(&self, alert: &Alert) -> RouterResult<()>;
+}
+
+/// 通过 HTTP POST 投递告警的 webhook
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    config: WebhookConfig,
+}
+
+impl WebhookSink {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self { config }
+    }
+
+    /// 按配置的格式生成请求体
+    pub fn payload(&self, alert: &Alert) -> Value {
+        match self.config.format {
+            WebhookFormat::Generic => alert.generic_payload(),
+            WebhookFormat::Slack => alert.slack_payload(),
+        }
+    }
+}
+
+impl AlertSink for WebhookSink {
+    fn deliver(&self, alert: &Alert) -> RouterResult<()> {
+        let body = serde_json::to_vec(&self.payload(alert))?;
+        let mut headers: HashMap<String, String> = self.config.headers.clone();
+        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        smol::block_on(send_http_request(
+            &self.config.url,
+            "POST",
+            &headers,
+            Some(&body),
+        ))?;
+        Ok(())
+    }
+}
+
+/// 注册额外的告警投递目标，与配置中的 webhook 一起接收所有告警
+pub fn register_sink(sink: Arc<dyn AlertSink>) {
+    if let Ok(mut sinks) = REGISTERED_SINKS.write() {
+        sinks.push(sink);
+    }
+}
+
+/// 将告警交给后台线程投递到配置的 webhook 与已注册的目标
+pub fn dispatch(alert: Alert, config: Option<&AlertingConfig>) {
+    let mut sinks: Vec<Arc<dyn AlertSink>> = config
+        .map(|config| {
+            config
+                .webhooks
+                .iter()
+                .map(|webhook| Arc::new(WebhookSink::new(webhook.clone())) as Arc<dyn AlertSink>)
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Ok(registered) = REGISTERED_SINKS.read() {
+        sinks.extend(registered.iter().cloned());
+    }
+    if sinks.is_empty() {
+        return;
+    }
+    if DISPATCHER.try_send((alert, sinks)).is_err() {
+        warn!("Alert queue is full, dropping alert");
+    }
+}
+
+fn spawn_worker(receiver: Receiver<Delivery>) {
+    let _ = thread::Builder::new()
+        .name("alert-dispatcher".to_string())
+        .spawn(move || {
+            while let Ok((alert, sinks)) = receiver.recv_blocking() {
+                for sink in sinks {
+                    if let Err(err) = sink.deliver(&alert) {
+                        warn!(provider = %alert.provider, "Alert delivery failed: {}", err);
+                    }
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AlertRuleConfig;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    fn alert(state: AlertState, provider: &str) -> Alert {
+        Alert {
+            state,
+            provider: provider.to_string(),
+            failures: 5,
+            window: Duration::from_secs(300),
+            error: (state == AlertState::Firing).then(|| "Upstream error: 502".to_string()),
+            timestamp: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    /// 本地 webhook 接收端：接收一个请求并返回原始请求文本
+    fn spawn_receiver() -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            while !String::from_utf8_lossy(&request).ends_with('}') {
+                let n = stream.read(&mut buffer).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (format!("http://{}/hook", addr), handle)
+    }
+
+    #[test]
+    fn resolves_rule_with_provider_overrides() {
+        assert_eq!(AlertRule::resolve(None, "openai"), AlertRule::default());
+        assert_eq!(AlertRule::default().threshold, 5);
+
+        let mut config = AlertingConfig {
+            threshold: 3,
+            ..AlertingConfig::default()
+        };
+        config.providers.insert(
+            "openai".to_string(),
+            AlertRuleConfig {
+                window_secs: Some(30),
+                threshold: Some(0),
+                ..AlertRuleConfig::default()
+            },
+        );
+        let openai = AlertRule::resolve(Some(&config), "openai");
+        assert_eq!(openai.threshold, 1);
+        assert_eq!(openai.window, Duration::from_secs(30));
+        assert_eq!(openai.throttle, Duration::from_secs(60));
+        assert_eq!(AlertRule::resolve(Some(&config), "anthropic").threshold, 3);
+    }
+
+    #[test]
+    fn builds_generic_and_slack_payloads() {
+        let firing = alert(AlertState::Firing, "openai");
+        let generic = firing.generic_payload();
+        assert_eq!(generic["status"], "firing");
+        assert_eq!(generic["provider"], "openai");
+        assert_eq!(generic["failures"], 5);
+        assert_eq!(generic["windowSecs"], 300);
+        assert_eq!(generic["timestamp"], "1970-01-01T00:00:00.000Z");
+
+        let slack = alert(AlertState::Recovered, "openai").slack_payload();
+        assert_eq!(
+            slack["text"],
+            ":white_check_mark: Upstream provider openai has recovered"
+        );
+        assert!(firing.slack_payload()["text"]
+            .as_str()
+            .unwrap()
+            .contains("failed 5 times in 300s"));
+    }
+
+    #[test]
+    fn webhook_sink_posts_payload_with_headers() {
+        let (url, handle) = spawn_receiver();
+        let sink = WebhookSink::new(WebhookConfig {
+            url,
+            format: WebhookFormat::Slack,
+            headers: HashMap::from([("X-Token".to_string(), "secret".to_string())]),
+        });
+        sink.deliver(&alert(AlertState::Firing, "openai")).unwrap();
+
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /hook HTTP/1.1"));
+        assert!(request.contains("X-Token: secret"));
+        assert!(request.contains("\"text\":\":rotating_light: Upstream provider openai"));
+    }
+
+    struct ChannelSink(std::sync::Mutex<mpsc::Sender<Alert>>);
+
+    impl AlertSink for ChannelSink {
+        fn deliver(&self, alert: &Alert) -> RouterResult<()> {
+            let _ = self.0.lock().unwrap().send(alert.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dispatch_delivers_to_registered_sinks() {
+        let (sender, receiver) = mpsc::channel();
+        register_sink(Arc::new(ChannelSink(std::sync::Mutex::new(sender))));
+        dispatch(alert(AlertState::Recovered, "dispatch-test"), None);
+
+        let delivered = loop {
+            let alert = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            if alert.provider == "dispatch-test" {
+                break alert;
+            }
+        };
+        assert_eq!(delivered.state, AlertState::Recovered);
+    }
+}
@@ -0,0 +1,429 @@
+//! 请求/响应录制模块
+//!
+//! 开启 `capture` 配置后，每个代理请求结束时以 JSONL 追加写出一条录制记录：
+//! 脱敏后的入站请求、转发计划（方法、URL、脱敏后的请求头与请求体）以及上游响应，
+//! 流式响应按到达顺序记录每个分块及其相对时间。录制文件可由 `replay` 子命令重放
+
+use crate::access_log::format_rfc3339;
+use crate::config::CaptureConfig;
+use crate::http_client::StreamObserver;
+use crate::secrets;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// 脱敏后的占位值
+pub const REDACTED: &str = "[REDACTED]";
+
+/// 需要脱敏的请求头（小写）
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "api-key",
+    "x-goog-api-key",
+    "cookie",
+    "set-cookie",
+];
+
+/// 需要脱敏的 URL 查询参数
+const SENSITIVE_QUERY_PARAMS: &[&str] = &["key", "api_key", "apikey", "access_token", "token"];
+
+/// 全局录制写入器
+static CAPTURE: Lazy<Mutex<CaptureWriter>> = Lazy::new(|| Mutex::new(CaptureWriter::default()));
+
+/// 单条录制记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// RFC 3339 格式的 UTC 时间戳
+    pub timestamp: String,
+    pub request_id: String,
+    pub route: String,
+    /// 客户端发来的请求
+    pub request: CapturedRequest,
+    /// 实际发往上游的请求
+    pub plan: CapturedPlan,
+    /// 上游响应
+    pub response: CapturedResponse,
+}
+
+/// 录制的入站请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedRequest {
+    pub method: String,
+    pub target: String,
+    pub headers: BTreeMap<String, String>,
+    /// 请求体，非 UTF-8 内容（如音频文件）不录制
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+impl CapturedRequest {
+    pub fn new(method: &str, target: &str, headers: &HashMap<String, String>, body: &[u8]) -> Self {
+        Self {
+            method: method.to_string(),
+            target: redact_url(target),
+            headers: redact_headers(headers),
+            body: text_body(body),
+        }
+    }
+}
+
+/// 录制的转发计划
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedPlan {
+    pub method: String,
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+    /// 转换后的上游请求体，非 UTF-8 内容不录制
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+impl CapturedPlan {
+    pub fn new(method: &str, url: &str, headers: &HashMap<String, String>, body: &[u8]) -> Self {
+        Self {
+            method: method.to_string(),
+            url: redact_url(url),
+            headers: redact_headers(headers),
+            body: text_body(body),
+        }
+    }
+}
+
+/// 录制的上游响应
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CapturedResponse {
+    /// 是否为流式响应
+    pub stream: bool,
+    /// 非流式响应的响应体
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// 流式响应的原始分块
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<CapturedChunk>,
+    /// 上游请求耗时（毫秒）
+    pub latency_ms: f64,
+    /// 上游请求失败时的错误信息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl CapturedResponse {
+    /// 流式响应按到达顺序拼接后的完整内容
+    pub fn stream_body(&self) -> String {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.data.as_str())
+            .collect()
+    }
+}
+
+/// 流式响应中的单个分块
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CapturedChunk {
+    /// 相对于上游请求开始的时间（毫秒）
+    pub offset_ms: f64,
+    pub data: String,
+}
+
+/// 单个请求的录制会话
+///
+/// 在转发计划确定后创建，上游响应结束后调用 [`CaptureSession::finish`] 写出记录
+#[derive(Debug)]
+pub struct CaptureSession {
+    timestamp: String,
+    request_id: String,
+    route: String,
+    request: CapturedRequest,
+    plan: CapturedPlan,
+}
+
+impl CaptureSession {
+    /// 当前配置需要录制该路由时返回录制会话
+    pub fn start(
+        request_id: &str,
+        route: &str,
+        request: impl FnOnce() -> CapturedRequest,
+        plan: impl FnOnce() -> CapturedPlan,
+    ) -> Option<Self> {
+        if !should_capture(route) {
+            return None;
+        }
+        Some(Self {
+            timestamp: format_rfc3339(SystemTime::now()),
+            request_id: request_id.to_string(),
+            route: route.to_string(),
+            request: request(),
+            plan: plan(),
+        })
+    }
+
+    /// 补全上游响应并写出录制记录
+    pub fn finish(self, response: CapturedResponse) {
+        let record = CaptureRecord {
+            timestamp: self.timestamp,
+            request_id: self.request_id,
+            route: self.route,
+            request: self.request,
+            plan: self.plan,
+            response,
+        };
+        if let Ok(mut writer) = CAPTURE.lock() {
+            if let Err(err) = writer.write(&record) {
+                tracing::warn!("Failed to write capture record: {}", err);
+            }
+        }
+    }
+}
+
+/// 流式分块录制器
+///
+/// 包装另一个观察者，在转发分块的同时记录分块内容与到达时间
+pub struct ChunkRecorder<'a> {
+    inner: &'a mut (dyn StreamObserver + Send),
+    start: Instant,
+    chunks: Option<Vec<CapturedChunk>>,
+}
+
+impl<'a> ChunkRecorder<'a> {
+    /// `enabled` 为 false 时仅转发分块，不做记录
+    pub fn new(inner: &'a mut (dyn StreamObserver + Send), enabled: bool) -> Self {
+        Self {
+            inner,
+            start: Instant::now(),
+            chunks: enabled.then(Vec::new),
+        }
+    }
+
+    /// 返回记录到的分块
+    pub fn into_chunks(self) -> Vec<CapturedChunk> {
+        self.chunks.unwrap_or_default()
+    }
+}
+
+impl StreamObserver for ChunkRecorder<'_> {
+    fn on_chunk(&mut self, chunk: &[u8]) {
+        self.inner.on_chunk(chunk);
+        if let Some(chunks) = self.chunks.as_mut() {
+            chunks.push(CapturedChunk {
+                offset_ms: duration_ms(self.start.elapsed()),
+                data: String::from_utf8_lossy(chunk).into_owned(),
+            });
+        }
+    }
+}
+
+/// 应用录制配置，配置变化时重新打开录制文件
+pub fn configure(config: Option<&CaptureConfig>) {
+    if let Ok(mut writer) = CAPTURE.lock() {
+        writer.configure(config);
+    }
+}
+
+/// 当前配置是否录制指定路由
+pub fn should_capture(route: &str) -> bool {
+    CAPTURE
+        .lock()
+        .map(|writer| writer.should_capture(route))
+        .unwrap_or(false)
+}
+
+/// 将 Duration 转换为毫秒浮点数
+pub fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// 脱敏请求头：敏感请求头的值替换为占位符，并按名称排序
+pub fn redact_headers(headers: &HashMap<String, String>) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if is_sensitive_header(name) {
+                REDACTED.to_string()
+            } else {
+                value.clone()
+            };
+            (name.to_ascii_lowercase(), value)
+        })
+        .collect()
+}
+
+/// 是否为需要脱敏的请求头
+pub fn is_sensitive_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SENSITIVE_HEADERS.contains(&name.as_str()) || name.contains("secret") || name.contains("token")
+}
+
+/// 脱敏 URL 中携带凭据的查询参数（如 Gemini 的 `?key=`）
+pub fn redact_url(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _))
+                if SENSITIVE_QUERY_PARAMS.contains(&name.to_ascii_lowercase().as_str()) =>
+            {
+                format!("{}={}", name, REDACTED)
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{}", base, query)
+}
+
+fn text_body(body: &[u8]) -> Option<String> {
+    std::str::from_utf8(body).ok().map(str::to_string)
+}
+
+/// 录制文件写入器
+#[derive(Default)]
+struct CaptureWriter {
+    config: Option<CaptureConfig>,
+    file: Option<File>,
+}
+
+impl CaptureWriter {
+    fn configure(&mut self, config: Option<&CaptureConfig>) {
+        if self.config.as_ref() == config {
+            return;
+        }
+        self.config = config.cloned();
+        self.file = None;
+    }
+
+    fn should_capture(&self, route: &str) -> bool {
+        self.config
+            .as_ref()
+            .map(|config| config.routes.is_empty() || config.routes.iter().any(|r| r == route))
+            .unwrap_or(false)
+    }
+
+    fn write(&mut self, record: &CaptureRecord) -> io::Result<()> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        if self.file.is_none() {
+            let path = std::path::Path::new(&config.path);
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        let mut line = secrets::redact(&serde_json::to_string(record)?).into_owned();
+        line.push('\n');
+        match self.file.as_mut() {
+            Some(file) => {
+                file.write_all(line.as_bytes())?;
+                file.flush()
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::UsageTracker;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn redacts_sensitive_headers() {
+        let redacted = redact_headers(&headers(&[
+            ("Authorization", "Bearer sk-live"),
+            ("x-api-key", "secret"),
+            ("x-session-token", "abc"),
+            ("content-type", "application/json"),
+        ]));
+        assert_eq!(redacted["authorization"], REDACTED);
+        assert_eq!(redacted["x-api-key"], REDACTED);
+        assert_eq!(redacted["x-session-token"], REDACTED);
+        assert_eq!(redacted["content-type"], "application/json");
+    }
+
+    #[test]
+    fn redacts_credential_query_params() {
+        assert_eq!(
+            redact_url("https://host/v1beta/models/x:generate?alt=sse&key=abc"),
+            "https://host/v1beta/models/x:generate?alt=sse&key=[REDACTED]"
+        );
+        assert_eq!(redact_url("/v1/chat/completions"), "/v1/chat/completions");
+    }
+
+    #[test]
+    fn recorder_forwards_and_records_chunks() {
+        let mut tracker = UsageTracker::new();
+        let mut recorder = ChunkRecorder::new(&mut tracker, true);
+        recorder.on_chunk(b"data: {\"usage\":{\"prompt_tokens\":1,");
+        recorder.on_chunk(b"\"completion_tokens\":2}}\n\n");
+        let chunks = recorder.into_chunks();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].offset_ms <= chunks[1].offset_ms);
+        assert_eq!(tracker.finish().unwrap().completion_tokens, 2);
+
+        let mut tracker = UsageTracker::new();
+        let mut recorder = ChunkRecorder::new(&mut tracker, false);
+        recorder.on_chunk(b"data: {}\n\n");
+        assert!(recorder.into_chunks().is_empty());
+    }
+
+    #[test]
+    fn writer_appends_jsonl_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/capture.jsonl");
+        let mut writer = CaptureWriter::default();
+        writer.configure(Some(&CaptureConfig {
+            path: path.to_string_lossy().into_owned(),
+            routes: vec!["/v1/chat/completions".to_string()],
+        }));
+        assert!(writer.should_capture("/v1/chat/completions"));
+        assert!(!writer.should_capture("/v1/embeddings"));
+
+        let record = CaptureRecord {
+            timestamp: "2024-01-01T00:00:00.000Z".to_string(),
+            request_id: "req-1".to_string(),
+            route: "/v1/chat/completions".to_string(),
+            request: CapturedRequest::new(
+                "POST",
+                "/v1/chat/completions",
+                &headers(&[("authorization", "Bearer sk")]),
+                br#"{"model":"gpt"}"#,
+            ),
+            plan: CapturedPlan::new(
+                "POST",
+                "https://api.example.com/v1/chat/completions",
+                &HashMap::new(),
+                br#"{"model":"upstream"}"#,
+            ),
+            response: CapturedResponse {
+                body: Some("{}".to_string()),
+                latency_ms: 12.5,
+                ..CapturedResponse::default()
+            },
+        };
+        writer.write(&record).unwrap();
+        writer.write(&record).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let parsed: CaptureRecord = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(parsed.request.headers["authorization"], REDACTED);
+        assert_eq!(parsed.plan.body.as_deref(), Some(r#"{"model":"upstream"}"#));
+        assert!(!contents.contains("sk\""));
+    }
+}
@@ -0,0 +1,972 @@
+//! 熔断器模块
+//!
+//! 按（上游，路由）维护 closed / open / half-open 三态熔断器，上游以主机名（及非默认端口）标识，
+//! 同一提供商的不同自定义上游互不影响：
+//! - closed：正常转发，在滑动窗口内统计失败率（上游错误与慢调用都计为失败）
+//! - open：失败率达到阈值后打开，在 `openSecs` 内直接拒绝请求或转发到备用上游
+//! - half-open：打开时长结束后放行少量探测请求，全部成功则关闭，任一失败则重新打开
+//!
+//! 通过管理接口停用的上游视同熔断打开（不受是否配置熔断影响），直到重新启用
+
+use crate::capture::is_sensitive_header;
+use crate::config::{ApiConfig, CircuitBreakerConfig, FallbackConfig};
+use crate::errors::{RouterError, RouterResult};
+use crate::metrics::{record_circuit_breaker_rejection, set_circuit_breaker_state};
+use crate::tracing_util::extract_provider;
+use crate::url_parser::Url;
+use dashmap::{DashMap, DashSet};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// 单个熔断器窗口内最多保留的请求结果数
+const MAX_WINDOW_SAMPLES: usize = 1024;
+
+/// 全局熔断器注册表
+pub static CIRCUIT_BREAKERS: Lazy<CircuitBreakerRegistry> = Lazy::new(CircuitBreakerRegistry::new);
+
+/// 熔断器状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// 指标中使用的数值：0 = closed，1 = open，2 = half_open
+    pub fn as_gauge(self) -> u64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::Open => 1,
+            CircuitState::HalfOpen => 2,
+        }
+    }
+}
+
+/// 一次上游调用的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallOutcome {
+    /// 上游成功响应
+    Success,
+    /// 上游失败（连接、TLS、超时、上游错误）
+    Failure,
+    /// 与上游健康无关的结果（如客户端请求错误），只释放探测名额
+    Ignored,
+}
+
+impl CallOutcome {
+    /// 根据路由结果与上游状态码判定调用结果：上游返回 5xx 或 429 的响应同样计为失败
+    pub fn from_result<T>(result: &RouterResult<T>, status: Option<u16>) -> Self {
+        match result {
+            Ok(_) if status.is_some_and(is_failure_status) => CallOutcome::Failure,
+            Ok(_) => CallOutcome::Success,
+            Err(
+                RouterError::Upstream(_)
+                | RouterError::Tls(_)
+                | RouterError::Io(_)
+                | RouterError::Url(_),
+            ) => CallOutcome::Failure,
+            Err(err) if err.is_timeout() => CallOutcome::Failure,
+            Err(_) => CallOutcome::Ignored,
+        }
+    }
+}
+
+/// 上游状态码是否表示上游不可用（5xx，含 529 过载，以及 429 限流）
+pub fn is_failure_status(status: u16) -> bool {
+    status >= 500 || status == 429
+}
+
+/// 熔断器的准入结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// 正常放行
+    Allowed,
+    /// 半开状态下的探测请求
+    Probe,
+    /// 熔断打开，需要等待的时间
+    Rejected { retry_after: Duration },
+}
+
+/// 单个熔断器
+#[derive(Debug)]
+struct Breaker {
+    state: CircuitState,
+    /// 窗口内的请求结果（时间，是否失败）
+    outcomes: VecDeque<(Instant, bool)>,
+    opened_at: Option<Instant>,
+    probes_in_flight: u32,
+    probe_successes: u32,
+}
+
+impl Breaker {
+    fn new() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            outcomes: VecDeque::new(),
+            opened_at: None,
+            probes_in_flight: 0,
+            probe_successes: 0,
+        }
+    }
+
+    fn admit(&mut self, config: &CircuitBreakerConfig, now: Instant) -> Admission {
+        if self.state == CircuitState::Open {
+            let open_for = Duration::from_secs(config.open_secs);
+            let elapsed = self
+                .opened_at
+                .map(|opened| now.duration_since(opened))
+                .unwrap_or(open_for);
+            if elapsed < open_for {
+                return Admission::Rejected {
+                    retry_after: open_for - elapsed,
+                };
+            }
+            self.state = CircuitState::HalfOpen;
+            self.probes_in_flight = 0;
+            self.probe_successes = 0;
+        }
+
+        match self.state {
+            CircuitState::HalfOpen => {
+                if self.probes_in_flight + self.probe_successes >= config.half_open_requests.max(1)
+                {
+                    Admission::Rejected {
+                        retry_after: Duration::from_secs(1),
+                    }
+                } else {
+                    self.probes_in_flight += 1;
+                    Admission::Probe
+                }
+            }
+            _ => Admission::Allowed,
+        }
+    }
+
+    fn record(
+        &mut self,
+        config: &CircuitBreakerConfig,
+        probe: bool,
+        outcome: CallOutcome,
+        latency: Option<Duration>,
+        now: Instant,
+    ) {
+        let slow = match (config.slow_call_ms, latency) {
+            (Some(limit), Some(latency)) => latency > Duration::from_millis(limit),
+            _ => false,
+        };
+        let failed = match outcome {
+            CallOutcome::Success => slow,
+            CallOutcome::Failure => true,
+            CallOutcome::Ignored => {
+                if probe {
+                    self.probes_in_flight = self.probes_in_flight.saturating_sub(1);
+                }
+                return;
+            }
+        };
+
+        if probe && self.state == CircuitState::HalfOpen {
+            self.probes_in_flight = self.probes_in_flight.saturating_sub(1);
+            if failed {
+                self.open(now);
+            } else {
+                self.probe_successes += 1;
+                if self.probe_successes >= config.half_open_requests.max(1) {
+                    self.state = CircuitState::Closed;
+                    self.outcomes.clear();
+                }
+            }
+            return;
+        }
+
+        if self.state != CircuitState::Closed {
+            return;
+        }
+        self.outcomes.push_back((now, failed));
+        let window = Duration::from_secs(config.window_secs);
+        while let Some((at, _)) = self.outcomes.front() {
+            if now.duration_since(*at) > window || self.outcomes.len() > MAX_WINDOW_SAMPLES {
+                self.outcomes.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if self.outcomes.len() >= config.minimum_requests.max(1) as usize
+            && self.failure_rate() >= config.failure_rate_threshold
+        {
+            self.open(now);
+        }
+    }
+
+    fn open(&mut self, now: Instant) {
+        self.state = CircuitState::Open;
+        self.opened_at = Some(now);
+        self.outcomes.clear();
+        self.probes_in_flight = 0;
+        self.probe_successes = 0;
+    }
+
+    fn failure_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+        let failures = self.outcomes.iter().filter(|(_, failed)| *failed).count();
+        failures as f64 / self.outcomes.len() as f64
+    }
+}
+
+/// 熔断器状态快照，用于 /health
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakerSnapshot {
+    /// 上游标识，见 [`upstream_id`]
+    pub upstream: String,
+    pub provider: String,
+    pub route: String,
+    pub state: CircuitState,
+    /// 当前窗口内的失败率
+    pub failure_rate: f64,
+    /// 当前窗口内的请求数
+    pub requests: usize,
+}
+
+/// 本次请求选定的上游
+///
+/// 半开探测名额在记录调用结果时释放；未记录结果就被丢弃（如请求 future 被取消）时按
+/// `CallOutcome::Ignored` 释放，避免熔断器一直停留在半开状态
+#[derive(Debug)]
+pub struct UpstreamSelection<'a> {
+    /// 转发使用的配置（使用备用上游时为替换了 baseUrl 与请求头、去掉主上游鉴权头与模型映射的副本）
+    pub config: Cow<'a, ApiConfig>,
+    /// 选定上游的标识，熔断与停用按该标识区分
+    pub upstream: String,
+    /// 选定上游的提供商名称
+    pub provider: String,
+    /// 是否使用了备用上游
+    pub fallback: bool,
+    probe: bool,
+    settings: Option<CircuitBreakerConfig>,
+    registry: &'a CircuitBreakerRegistry,
+    route: String,
+    /// 占用的探测名额尚未释放
+    probe_pending: AtomicBool,
+}
+
+impl<'a> UpstreamSelection<'a> {
+    fn new(
+        registry: &'a CircuitBreakerRegistry,
+        route: &str,
+        config: Cow<'a, ApiConfig>,
+        upstream: String,
+        fallback: bool,
+        probe: bool,
+        settings: Option<CircuitBreakerConfig>,
+    ) -> Self {
+        Self {
+            config,
+            provider: extract_provider(&upstream).to_string(),
+            upstream,
+            fallback,
+            probe,
+            settings,
+            registry,
+            route: route.to_string(),
+            probe_pending: AtomicBool::new(probe),
+        }
+    }
+}
+
+impl Drop for UpstreamSelection<'_> {
+    fn drop(&mut self) {
+        if *self.probe_pending.get_mut() {
+            let route = std::mem::take(&mut self.route);
+            self.registry
+                .record(self, &route, CallOutcome::Ignored, None);
+        }
+    }
+}
+
+/// 熔断器注册表
+#[derive(Debug)]
+pub struct CircuitBreakerRegistry {
+    breakers: DashMap<(String, String), Breaker>,
+    /// 手动停用的上游
+    disabled: DashSet<String>,
+}
+
+impl Default for CircuitBreakerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreakerRegistry {
+    pub fn new() -> Self {
+        Self {
+            breakers: DashMap::new(),
+            disabled: DashSet::new(),
+        }
+    }
+
+    /// 为路由选择上游：主上游熔断打开或被停用时尝试备用上游，都不可用时返回 `CircuitOpen`
+    pub fn select<'a>(
+        &'a self,
+        route: &str,
+        config: &'a ApiConfig,
+    ) -> RouterResult<UpstreamSelection<'a>> {
+        let upstream = upstream_id(&config.base_url);
+        let enabled = self.is_enabled(&upstream);
+        let Some(settings) = resolve_circuit_breaker(route, config) else {
+            if !enabled {
+                return Err(disabled_error(&upstream));
+            }
+            return Ok(UpstreamSelection::new(
+                self,
+                route,
+                Cow::Borrowed(config),
+                upstream,
+                false,
+                false,
+                None,
+            ));
+        };
+
+        let admission = if enabled {
+            self.admit(&upstream, route, &settings)
+        } else {
+            // 停用期间不参与熔断统计，只用于决定是否切换到备用上游
+            Admission::Rejected {
+                retry_after: Duration::ZERO,
+            }
+        };
+        let retry_after = match admission {
+            Admission::Allowed | Admission::Probe => {
+                return Ok(UpstreamSelection::new(
+                    self,
+                    route,
+                    Cow::Borrowed(config),
+                    upstream,
+                    false,
+                    admission == Admission::Probe,
+                    Some(settings),
+                ))
+            }
+            Admission::Rejected { retry_after } => retry_after,
+        };
+
+        if let Some((fallback, fallback_upstream)) = settings
+            .fallback
+            .as_ref()
+            .map(|fallback| (fallback, upstream_id(&fallback.base_url)))
+            .filter(|(_, fallback_upstream)| self.is_enabled(fallback_upstream))
+        {
+            let admission = self.admit(&fallback_upstream, route, &settings);
+            if !matches!(admission, Admission::Rejected { .. }) {
+                // 主上游的鉴权头与模型映射只对主上游有效，不能带到备用上游
+                let mut fallback_config = config.clone();
+                fallback_config.base_url = fallback.base_url.clone();
+                fallback_config.model_mapping = None;
+                fallback.rebase_headers(&mut fallback_config.headers);
+                for endpoint in fallback_config.endpoints.values_mut() {
+                    endpoint
+                        .headers
+                        .retain(|name, _| !is_sensitive_header(name));
+                }
+                return Ok(UpstreamSelection::new(
+                    self,
+                    route,
+                    Cow::Owned(fallback_config),
+                    fallback_upstream,
+                    true,
+                    admission == Admission::Probe,
+                    Some(settings),
+                ));
+            }
+        }
+
+        if !enabled {
+            return Err(disabled_error(&upstream));
+        }
+        Err(RouterError::CircuitOpen(format!(
+            "Circuit breaker open for {} {}, retry after {}s",
+            upstream,
+            route,
+            (retry_after.as_secs_f64().ceil() as u64).max(1)
+        )))
+    }
+
+    /// 为对冲请求准入备用上游：备用上游被停用或熔断未放行时返回 None
+    ///
+    /// 返回的选择只用于记录对冲请求的结果，转发仍使用主上游计划改写后的请求
+    pub fn select_alternate<'a>(
+        &'a self,
+        route: &str,
+        config: &'a ApiConfig,
+        alternate: &FallbackConfig,
+    ) -> Option<UpstreamSelection<'a>> {
+        let upstream = upstream_id(&alternate.base_url);
+        if !self.is_enabled(&upstream) {
+            return None;
+        }
+        let settings = resolve_circuit_breaker(route, config);
+        let admission = match &settings {
+            Some(settings) => self.admit(&upstream, route, settings),
+            None => Admission::Allowed,
+        };
+        if matches!(admission, Admission::Rejected { .. }) {
+            return None;
+        }
+        Some(UpstreamSelection::new(
+            self,
+            route,
+            Cow::Borrowed(config),
+            upstream,
+            true,
+            admission == Admission::Probe,
+            settings,
+        ))
+    }
+
+    /// 记录选定上游的调用结果，同一选择的探测名额只释放一次
+    pub fn record(
+        &self,
+        selection: &UpstreamSelection<'_>,
+        route: &str,
+        outcome: CallOutcome,
+        latency: Option<Duration>,
+    ) {
+        let Some(settings) = &selection.settings else {
+            return;
+        };
+        let probe = selection.probe && selection.probe_pending.swap(false, Ordering::SeqCst);
+        let key = (selection.upstream.clone(), route.to_string());
+        let mut breaker = self.breakers.entry(key).or_insert_with(Breaker::new);
+        let before = breaker.state;
+        breaker.record(settings, probe, outcome, latency, Instant::now());
+        let after = breaker.state;
+        drop(breaker);
+        if before != after {
+            log_transition(&selection.upstream, route, before, after);
+            set_circuit_breaker_state(&selection.upstream, route, after.as_gauge());
+        }
+    }
+
+    /// 手动设置上游的熔断器状态
+    pub fn force_state(&self, upstream: &str, route: &str, state: CircuitState) {
+        let key = (upstream.to_string(), route.to_string());
+        let mut breaker = self.breakers.entry(key).or_insert_with(Breaker::new);
+        match state {
+            CircuitState::Open => breaker.open(Instant::now()),
+            CircuitState::Closed => *breaker = Breaker::new(),
+            CircuitState::HalfOpen => {
+                breaker.state = CircuitState::HalfOpen;
+                breaker.probes_in_flight = 0;
+                breaker.probe_successes = 0;
+            }
+        }
+        drop(breaker);
+        set_circuit_breaker_state(upstream, route, state.as_gauge());
+    }
+
+    /// 启用或停用上游，返回状态是否发生变化
+    pub fn set_enabled(&self, upstream: &str, enabled: bool) -> bool {
+        let changed = if enabled {
+            self.disabled.remove(upstream).is_some()
+        } else {
+            self.disabled.insert(upstream.to_string())
+        };
+        if changed {
+            warn!(upstream = %upstream, enabled, "Upstream availability changed manually");
+        }
+        changed
+    }
+
+    /// 上游是否可用（未被手动停用）
+    pub fn is_enabled(&self, upstream: &str) -> bool {
+        !self.disabled.contains(upstream)
+    }
+
+    /// 被手动停用的上游，按标识排序
+    pub fn disabled_upstreams(&self) -> Vec<String> {
+        let mut upstreams: Vec<String> = self.disabled.iter().map(|u| u.key().clone()).collect();
+        upstreams.sort();
+        upstreams
+    }
+
+    /// 返回所有熔断器的状态快照
+    pub fn snapshot(&self) -> Vec<BreakerSnapshot> {
+        let mut snapshots: Vec<BreakerSnapshot> = self
+            .breakers
+            .iter()
+            .map(|entry| {
+                let (upstream, route) = entry.key();
+                BreakerSnapshot {
+                    upstream: upstream.clone(),
+                    provider: extract_provider(upstream).to_string(),
+                    route: route.clone(),
+                    state: entry.state,
+                    failure_rate: entry.failure_rate(),
+                    requests: entry.outcomes.len(),
+                }
+            })
+            .collect();
+        snapshots.sort_by(|a, b| (&a.upstream, &a.route).cmp(&(&b.upstream, &b.route)));
+        snapshots
+    }
+
+    fn admit(&self, upstream: &str, route: &str, settings: &CircuitBreakerConfig) -> Admission {
+        let key = (upstream.to_string(), route.to_string());
+        let mut breaker = self.breakers.entry(key).or_insert_with(Breaker::new);
+        let before = breaker.state;
+        let admission = breaker.admit(settings, Instant::now());
+        let after = breaker.state;
+        drop(breaker);
+        if before != after {
+            log_transition(upstream, route, before, after);
+            set_circuit_breaker_state(upstream, route, after.as_gauge());
+        }
+        if matches!(admission, Admission::Rejected { .. }) {
+            record_circuit_breaker_rejection(upstream, route);
+        }
+        admission
+    }
+}
+
+/// 解析路由生效的熔断配置：端点配置优先，其次为全局配置
+pub fn resolve_circuit_breaker(route: &str, config: &ApiConfig) -> Option<CircuitBreakerConfig> {
+    config
+        .endpoints
+        .get(route)
+        .and_then(|endpoint| endpoint.circuit_breaker.clone())
+        .or_else(|| config.circuit_breaker.clone())
+}
+
+/// 熔断与停用使用的上游标识：小写主机名，显式指定的端口附在其后（如 `backup.example.com:8443`）
+///
+/// 无法解析的 baseUrl 原样使用（去掉末尾的 `/`）
+pub fn upstream_id(base_url: &str) -> String {
+    match Url::parse(base_url) {
+        Ok(url) => {
+            let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+            match url.port() {
+                Some(port) if Some(port) != default_port(url.scheme()) => {
+                    format!("{}:{}", host, port)
+                }
+                _ => host,
+            }
+        }
+        Err(_) => base_url.trim().trim_end_matches('/').to_string(),
+    }
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    Some(if scheme == "https" { 443 } else { 80 })
+}
+
+fn disabled_error(upstream: &str) -> RouterError {
+    RouterError::CircuitOpen(format!("Upstream {} is disabled", upstream))
+}
+
+fn log_transition(upstream: &str, route: &str, before: CircuitState, after: CircuitState) {
+    if after == CircuitState::Open {
+        warn!(upstream = %upstream, route = %route, from = ?before, "Circuit breaker opened");
+    } else {
+        info!(upstream = %upstream, route = %route, from = ?before, to = ?after, "Circuit breaker state changed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const ROUTE: &str = "/v1/chat/completions";
+
+    fn settings() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_rate_threshold: 0.5,
+            minimum_requests: 4,
+            window_secs: 60,
+            slow_call_ms: Some(1000),
+            open_secs: 30,
+            half_open_requests: 1,
+            fallback: None,
+        }
+    }
+
+    fn config(base_url: &str, breaker: CircuitBreakerConfig) -> ApiConfig {
+        ApiConfig {
+            base_url: base_url.to_string(),
+            circuit_breaker: Some(breaker),
+            ..ApiConfig::default()
+        }
+    }
+
+    #[test]
+    fn upstream_error_status_counts_as_failure() {
+        let ok: RouterResult<()> = Ok(());
+        for status in [500, 502, 503, 529, 429] {
+            assert_eq!(
+                CallOutcome::from_result(&ok, Some(status)),
+                CallOutcome::Failure
+            );
+        }
+        assert_eq!(
+            CallOutcome::from_result(&ok, Some(200)),
+            CallOutcome::Success
+        );
+        assert_eq!(
+            CallOutcome::from_result(&ok, Some(400)),
+            CallOutcome::Success
+        );
+        assert_eq!(CallOutcome::from_result(&ok, None), CallOutcome::Success);
+        let bad_request: RouterResult<()> = Err(RouterError::BadRequest("bad".to_string()));
+        assert_eq!(
+            CallOutcome::from_result(&bad_request, Some(503)),
+            CallOutcome::Ignored
+        );
+    }
+
+    #[test]
+    fn opens_when_failure_rate_reaches_threshold() {
+        let settings = settings();
+        let now = Instant::now();
+        let mut breaker = Breaker::new();
+        for outcome in [
+            CallOutcome::Success,
+            CallOutcome::Failure,
+            CallOutcome::Success,
+        ] {
+            assert_eq!(breaker.admit(&settings, now), Admission::Allowed);
+            breaker.record(&settings, false, outcome, None, now);
+        }
+        assert_eq!(breaker.state, CircuitState::Closed);
+        breaker.record(&settings, false, CallOutcome::Failure, None, now);
+        assert_eq!(breaker.state, CircuitState::Open);
+        assert_eq!(
+            breaker.admit(&settings, now + Duration::from_secs(10)),
+            Admission::Rejected {
+                retry_after: Duration::from_secs(20)
+            }
+        );
+    }
+
+    #[test]
+    fn slow_calls_count_as_failures_and_ignored_calls_do_not_count() {
+        let settings = settings();
+        let now = Instant::now();
+        let mut breaker = Breaker::new();
+        let slow = Some(Duration::from_millis(1500));
+        let fast = Some(Duration::from_millis(10));
+        breaker.record(&settings, false, CallOutcome::Success, slow, now);
+        breaker.record(&settings, false, CallOutcome::Success, fast, now);
+        for _ in 0..10 {
+            breaker.record(&settings, false, CallOutcome::Ignored, None, now);
+        }
+        assert_eq!(breaker.outcomes.len(), 2);
+        breaker.record(&settings, false, CallOutcome::Success, fast, now);
+        breaker.record(&settings, false, CallOutcome::Success, slow, now);
+        assert_eq!(breaker.state, CircuitState::Open);
+    }
+
+    #[test]
+    fn half_open_probe_closes_or_reopens() {
+        let settings = settings();
+        let now = Instant::now();
+        let mut breaker = Breaker::new();
+        breaker.open(now);
+
+        let later = now + Duration::from_secs(31);
+        assert_eq!(breaker.admit(&settings, later), Admission::Probe);
+        assert_eq!(breaker.state, CircuitState::HalfOpen);
+        assert!(matches!(
+            breaker.admit(&settings, later),
+            Admission::Rejected { .. }
+        ));
+        breaker.record(&settings, true, CallOutcome::Failure, None, later);
+        assert_eq!(breaker.state, CircuitState::Open);
+
+        let much_later = later + Duration::from_secs(31);
+        assert_eq!(breaker.admit(&settings, much_later), Admission::Probe);
+        breaker.record(&settings, true, CallOutcome::Success, None, much_later);
+        assert_eq!(breaker.state, CircuitState::Closed);
+        assert_eq!(breaker.admit(&settings, much_later), Admission::Allowed);
+    }
+
+    #[test]
+    fn ignored_probe_releases_slot() {
+        let settings = settings();
+        let now = Instant::now();
+        let mut breaker = Breaker::new();
+        breaker.open(now - Duration::from_secs(31));
+        assert_eq!(breaker.admit(&settings, now), Admission::Probe);
+        breaker.record(&settings, true, CallOutcome::Ignored, None, now);
+        assert_eq!(breaker.admit(&settings, now), Admission::Probe);
+    }
+
+    #[test]
+    fn dropped_probe_selection_releases_slot() {
+        let registry = CircuitBreakerRegistry::new();
+        let config = config("https://api.openai.com", settings());
+        registry.force_state("api.openai.com", ROUTE, CircuitState::HalfOpen);
+
+        let probe = registry.select(ROUTE, &config).unwrap();
+        assert!(registry.select(ROUTE, &config).is_err());
+        // 请求被取消时没有记录结果，丢弃选择即释放探测名额
+        drop(probe);
+        let probe = registry.select(ROUTE, &config).unwrap();
+        registry.record(&probe, ROUTE, CallOutcome::Ignored, None);
+        drop(probe);
+        let probe = registry.select(ROUTE, &config).unwrap();
+        registry.record(&probe, ROUTE, CallOutcome::Success, None);
+        drop(probe);
+        assert_eq!(registry.snapshot()[0].state, CircuitState::Closed);
+    }
+
+    #[test]
+    fn registry_fails_fast_when_open() {
+        let registry = CircuitBreakerRegistry::new();
+        let config = config("https://api.openai.com", settings());
+        registry.force_state("api.openai.com", ROUTE, CircuitState::Open);
+
+        let err = registry.select(ROUTE, &config).unwrap_err();
+        assert!(matches!(err, RouterError::CircuitOpen(_)));
+        assert!(err.to_string().contains("retry after 30s"));
+
+        // 未配置熔断的路由不受影响
+        let mut plain = config.clone();
+        plain.circuit_breaker = None;
+        assert!(registry.select(ROUTE, &plain).is_ok());
+    }
+
+    #[test]
+    fn registry_uses_fallback_when_primary_open() {
+        let registry = CircuitBreakerRegistry::new();
+        let mut breaker = settings();
+        breaker.fallback = Some(FallbackConfig {
+            base_url: "https://api.anthropic.com".to_string(),
+            headers: [("x-api-key".to_string(), "fallback-key".to_string())].into(),
+        });
+        let config = config("https://api.openai.com", breaker);
+        registry.force_state("api.openai.com", ROUTE, CircuitState::Open);
+
+        let selection = registry.select(ROUTE, &config).unwrap();
+        assert!(selection.fallback);
+        assert_eq!(selection.provider, "anthropic");
+        assert_eq!(selection.config.base_url, "https://api.anthropic.com");
+        assert_eq!(selection.config.headers["x-api-key"], "fallback-key");
+
+        registry.record(&selection, ROUTE, CallOutcome::Success, None);
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].provider, "anthropic");
+        assert_eq!(snapshot[0].state, CircuitState::Closed);
+        assert_eq!(snapshot[0].requests, 1);
+        assert_eq!(snapshot[1].state, CircuitState::Open);
+    }
+
+    #[test]
+    fn fallback_never_receives_primary_credentials() {
+        let registry = CircuitBreakerRegistry::new();
+        let mut breaker = settings();
+        breaker.fallback = Some(FallbackConfig {
+            base_url: "https://api.anthropic.com".to_string(),
+            headers: [(
+                "authorization".to_string(),
+                "Bearer fallback-key".to_string(),
+            )]
+            .into(),
+        });
+        let mut config = config("https://api.openai.com", breaker);
+        config.headers = HashMap::from([
+            (
+                "Authorization".to_string(),
+                "Bearer primary-key".to_string(),
+            ),
+            ("X-Api-Key".to_string(), "primary-key".to_string()),
+            ("OpenAI-Beta".to_string(), "assistants=v2".to_string()),
+        ]);
+        config.model_mapping = Some(HashMap::from([(
+            "gpt-4".to_string(),
+            "primary-model".to_string(),
+        )]));
+        config.endpoints.insert(
+            ROUTE.to_string(),
+            crate::config::EndpointConfig {
+                headers: HashMap::from([("api-key".to_string(), "primary-key".to_string())]),
+                ..Default::default()
+            },
+        );
+        registry.force_state("api.openai.com", ROUTE, CircuitState::Open);
+
+        let selection = registry.select(ROUTE, &config).unwrap();
+        assert!(selection.fallback);
+        let fallback = &selection.config;
+        let all_headers = fallback
+            .headers
+            .iter()
+            .chain(fallback.endpoints.values().flat_map(|e| e.headers.iter()));
+        for (name, value) in all_headers {
+            assert!(!value.contains("primary-key"), "{} leaked", name);
+        }
+        assert_eq!(
+            fallback.headers,
+            HashMap::from([
+                (
+                    "authorization".to_string(),
+                    "Bearer fallback-key".to_string()
+                ),
+                ("OpenAI-Beta".to_string(), "assistants=v2".to_string()),
+            ])
+        );
+        assert!(fallback.model_mapping.is_none());
+    }
+
+    #[test]
+    fn failing_hedge_alternate_opens_its_circuit() {
+        let registry = CircuitBreakerRegistry::new();
+        let config = config("https://api.openai.com", settings());
+        let alternate = FallbackConfig {
+            base_url: "https://api.anthropic.com".to_string(),
+            headers: HashMap::new(),
+        };
+        for _ in 0..4 {
+            let selection = registry
+                .select_alternate(ROUTE, &config, &alternate)
+                .unwrap();
+            assert_eq!(selection.provider, "anthropic");
+            registry.record(&selection, ROUTE, CallOutcome::Failure, None);
+        }
+        assert!(registry
+            .select_alternate(ROUTE, &config, &alternate)
+            .is_none());
+        // 主上游的熔断器不受影响
+        assert!(!registry.select(ROUTE, &config).unwrap().fallback);
+
+        registry.set_enabled("api.anthropic.com", false);
+        let mut plain = config.clone();
+        plain.circuit_breaker = None;
+        assert!(registry
+            .select_alternate(ROUTE, &plain, &alternate)
+            .is_none());
+        registry.set_enabled("api.anthropic.com", true);
+        assert!(registry
+            .select_alternate(ROUTE, &plain, &alternate)
+            .is_some());
+    }
+
+    #[test]
+    fn disabled_provider_switches_to_fallback_or_fails() {
+        let registry = CircuitBreakerRegistry::new();
+        let mut plain = config("https://api.openai.com", settings());
+        plain.circuit_breaker = None;
+        assert!(registry.set_enabled("api.openai.com", false));
+        assert!(!registry.set_enabled("api.openai.com", false));
+        assert_eq!(
+            registry.disabled_upstreams(),
+            vec!["api.openai.com".to_string()]
+        );
+
+        let err = registry.select(ROUTE, &plain).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Circuit open: Upstream api.openai.com is disabled"
+        );
+
+        let mut breaker = settings();
+        breaker.fallback = Some(FallbackConfig {
+            base_url: "https://api.anthropic.com".to_string(),
+            headers: HashMap::new(),
+        });
+        let config = config("https://api.openai.com", breaker);
+        let selection = registry.select(ROUTE, &config).unwrap();
+        assert!(selection.fallback);
+        assert_eq!(selection.provider, "anthropic");
+
+        registry.set_enabled("api.anthropic.com", false);
+        assert!(registry.select(ROUTE, &config).is_err());
+
+        assert!(registry.set_enabled("api.openai.com", true));
+        let selection = registry.select(ROUTE, &config).unwrap();
+        assert!(!selection.fallback);
+        // 停用期间没有产生 openai 的熔断统计
+        assert!(registry
+            .snapshot()
+            .iter()
+            .all(|snapshot| snapshot.provider != "openai" || snapshot.requests == 0));
+    }
+
+    #[test]
+    fn custom_upstreams_have_separate_breakers() {
+        let registry = CircuitBreakerRegistry::new();
+        let mut breaker = settings();
+        breaker.fallback = Some(FallbackConfig {
+            base_url: "https://backup.example.com".to_string(),
+            headers: HashMap::new(),
+        });
+        let config = config("https://portal.qwen.ai/v1", breaker);
+        for _ in 0..4 {
+            let selection = registry.select(ROUTE, &config).unwrap();
+            assert!(!selection.fallback);
+            registry.record(&selection, ROUTE, CallOutcome::Failure, None);
+        }
+
+        let selection = registry.select(ROUTE, &config).unwrap();
+        assert!(selection.fallback);
+        assert_eq!(selection.upstream, "backup.example.com");
+        assert_eq!(selection.provider, "unknown");
+        drop(selection);
+
+        // 停用一个自定义上游不影响其他同为 unknown 提供商的上游
+        assert!(registry.set_enabled("backup.example.com", false));
+        assert!(registry.select(ROUTE, &config).is_err());
+        let mut other = config.clone();
+        other.base_url = "http://10.0.0.5:8080".to_string();
+        assert_eq!(
+            registry.select(ROUTE, &other).unwrap().upstream,
+            "10.0.0.5:8080"
+        );
+    }
+
+    #[test]
+    fn upstream_id_uses_host_and_explicit_port() {
+        assert_eq!(upstream_id("https://Portal.Qwen.ai/v1/"), "portal.qwen.ai");
+        assert_eq!(upstream_id("https://api.openai.com:443"), "api.openai.com");
+        assert_eq!(upstream_id("http://localhost:11434/v1"), "localhost:11434");
+        assert_eq!(upstream_id("not a url/"), "not a url");
+    }
+
+    #[test]
+    fn endpoint_config_overrides_global() {
+        let mut config = config("https://api.openai.com", settings());
+        let mut endpoint_breaker = settings();
+        endpoint_breaker.minimum_requests = 99;
+        config.endpoints.insert(
+            ROUTE.to_string(),
+            crate::config::EndpointConfig {
+                circuit_breaker: Some(endpoint_breaker),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            resolve_circuit_breaker(ROUTE, &config)
+                .unwrap()
+                .minimum_requests,
+            99
+        );
+        assert_eq!(
+            resolve_circuit_breaker("/v1/embeddings", &config)
+                .unwrap()
+                .minimum_requests,
+            4
+        );
+    }
+}
@@ -0,0 +1,674 @@
+//! 命令行模块
+//!
+//! 解析 `api-router` 的子命令与参数，并执行除 `serve` 之外的工具子命令：
+//! `validate-config`、`list-models`、`print-effective-config`、`print-config-schema`、`check-upstream`。
+//! 不带子命令时等同于 `serve`，仍兼容旧的 `api-router <配置名> [端口]` 位置参数写法。
+//!
+//! 退出码：`0` 成功；`1` 检查未通过（配置无效、上游不可达、启动失败）；`2` 命令行用法错误
+
+use crate::capture::redact_url;
+use crate::config::{
+    config_file_path, config_path_for, read_config_file, redacted_config_json, set_config_path,
+    ApiConfig, ListenConfig, UnixSocketConfig,
+};
+use crate::http_client::probe_connection;
+use crate::layering::read_document;
+use crate::proxy;
+use crate::secrets;
+use crate::timeout::Timeouts;
+use crate::tls;
+use crate::validation::{config_schema, parse_config_value};
+
+/// 成功
+pub const EXIT_SUCCESS: i32 = 0;
+/// 检查未通过或运行失败
+pub const EXIT_FAILURE: i32 = 1;
+/// 命令行用法错误
+pub const EXIT_USAGE: i32 = 2;
+
+/// 总体用法说明
+pub const USAGE: &str = "\
+用法: api-router [命令] [选项]
+
+命令:
+  serve                    启动转发服务（默认命令）
+  validate-config          校验配置文件
+  list-models              列出配置的模型映射
+  print-effective-config   输出补全默认值后的生效配置（敏感信息已脱敏）
+  print-config-schema      输出配置文件的 JSON Schema
+  check-upstream           探测到各上游的连通性（TCP、代理隧道与 TLS 握手）
+  replay                   重放录制文件并比较响应
+  help [命令]              显示帮助
+
+通用选项:
+  -c, --config <名称|路径>  配置名称（在 transformer/ 下查找 <名称>.json/.yaml/.yml/.toml）或配置文件路径，
+                            优先于 API_ROUTER_CONFIG_PATH，默认 transformer/qwen.json
+  -h, --help                显示帮助
+  -V, --version             显示版本
+
+退出码: 0 成功，1 检查未通过或启动失败，2 用法错误";
+
+const SERVE_USAGE: &str = "\
+用法: api-router serve [选项]
+      api-router [<配置名> [端口]]    （兼容旧写法）
+
+选项:
+  -c, --config <名称|路径>  配置名称或配置文件路径
+  -p, --port <端口>         覆盖配置中的 port（只作用于默认监听地址 0.0.0.0:<port>）
+  -l, --listen <地址>       监听地址，可重复；TCP 地址如 127.0.0.1:8000、[::]:8000，
+                            Unix 套接字写作 unix:/run/api-router.sock；覆盖配置中的 listen.tcp / listen.unix
+      --log-format <格式>   日志格式：text 或 json，默认读取 LOG_FORMAT 环境变量";
+
+const VALIDATE_CONFIG_USAGE: &str = "\
+用法: api-router validate-config [-c <名称|路径>]
+
+读取并校验配置文件：JSON 语法、未知字段（附拼写建议）、字段类型与取值范围、URL、路由、
+HTTP 方法以及字段之间的约束；校验的是合并覆盖层与 API_ROUTER__* 环境变量之后的配置。有效时退出码为 0；无效时每行输出一个问题（JSON 路径: 说明），以 1 退出";
+
+const PRINT_CONFIG_SCHEMA_USAGE: &str = "\
+用法: api-router print-config-schema
+
+输出配置文件的 JSON Schema（draft-07），可用于编辑器补全与校验；
+仓库中的 schema/config.schema.json 即由此命令生成";
+
+const LIST_MODELS_USAGE: &str = "\
+用法: api-router list-models [-c <名称|路径>]
+
+按客户端模型名排序列出 modelMapping（客户端模型名 -> 上游模型名）";
+
+const PRINT_EFFECTIVE_CONFIG_USAGE: &str = "\
+用法: api-router print-effective-config [-c <名称|路径>]
+
+以 JSON 输出补全默认值后的配置；敏感请求头、URL 中的密码与凭据参数被替换为 [REDACTED]，
+keys 中的 API Key 以掩码形式输出";
+
+const CHECK_UPSTREAM_USAGE: &str = "\
+用法: api-router check-upstream [-c <名称|路径>]
+
+按配置的出站代理、TLS 与超时设置，依次连接 baseUrl 以及熔断、对冲配置的备用上游，
+全部可达时退出码为 0，任一不可达时为 1";
+
+/// 日志格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// `serve` 子命令选项
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServeOptions {
+    /// 配置名称或路径
+    pub config: Option<String>,
+    /// 覆盖配置中的端口
+    pub port: Option<u16>,
+    /// 覆盖配置中的监听地址
+    pub listen: Vec<String>,
+    /// 日志格式，未指定时读取 LOG_FORMAT 环境变量
+    pub log_format: Option<LogFormat>,
+}
+
+impl ServeOptions {
+    /// 将 `--listen` 参数应用到监听配置：指定了任一地址时替换配置中的 TCP 地址与 Unix 套接字
+    pub fn apply_listen(&self, listen: &mut ListenConfig) {
+        if self.listen.is_empty() {
+            return;
+        }
+        listen.tcp.clear();
+        listen.unix.clear();
+        for address in &self.listen {
+            match address.strip_prefix("unix:") {
+                Some(path) => listen.unix.push(UnixSocketConfig {
+                    path: path.to_string(),
+                    mode: None,
+                }),
+                None => listen.tcp.push(address.clone()),
+            }
+        }
+    }
+}
+
+/// 解析后的命令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Serve(ServeOptions),
+    /// 工具子命令，携带可选的配置名称或路径
+    ValidateConfig(Option<String>),
+    ListModels(Option<String>),
+    PrintEffectiveConfig(Option<String>),
+    CheckUpstream(Option<String>),
+    PrintConfigSchema,
+    /// `replay` 子命令之后的原始参数
+    Replay(Vec<String>),
+    /// 显示帮助，可指定子命令
+    Help(Option<String>),
+    Version,
+}
+
+impl Command {
+    /// 命令指定的配置名称或路径
+    pub fn config(&self) -> Option<&str> {
+        match self {
+            Command::Serve(options) => options.config.as_deref(),
+            Command::ValidateConfig(config)
+            | Command::ListModels(config)
+            | Command::PrintEffectiveConfig(config)
+            | Command::CheckUpstream(config) => config.as_deref(),
+            Command::PrintConfigSchema
+            | Command::Replay(_)
+            | Command::Help(_)
+            | Command::Version => None,
+        }
+    }
+}
+
+/// 解析程序名之后的命令行参数
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let Some(first) = args.first() else {
+        return Ok(Command::Serve(ServeOptions::default()));
+    };
+    let rest = &args[1..];
+    match first.as_str() {
+        "-h" | "--help" => Ok(Command::Help(None)),
+        "-V" | "--version" => Ok(Command::Version),
+        "help" => match rest.first() {
+            Some(topic) if usage_for(topic).is_none() => Err(format!("未知命令 '{}'", topic)),
+            topic => Ok(Command::Help(topic.cloned())),
+        },
+        "serve" => parse_serve(rest, false),
+        "validate-config" | "list-models" | "print-effective-config" | "check-upstream" => {
+            if wants_help(rest) {
+                return Ok(Command::Help(Some(first.clone())));
+            }
+            let config = parse_config_option(rest)?;
+            Ok(match first.as_str() {
+                "validate-config" => Command::ValidateConfig(config),
+                "list-models" => Command::ListModels(config),
+                "print-effective-config" => Command::PrintEffectiveConfig(config),
+                _ => Command::CheckUpstream(config),
+            })
+        }
+        "print-config-schema" => match rest.first() {
+            _ if wants_help(rest) => Ok(Command::Help(Some(first.clone()))),
+            Some(extra) => Err(format!("多余的参数 '{}'", extra)),
+            None => Ok(Command::PrintConfigSchema),
+        },
+        "replay" => {
+            if wants_help(rest) {
+                Ok(Command::Help(Some("replay".to_string())))
+            } else {
+                Ok(Command::Replay(rest.to_vec()))
+            }
+        }
+        _ => parse_serve(args, true),
+    }
+}
+
+/// 子命令的用法说明
+pub fn usage_for(command: &str) -> Option<&'static str> {
+    match command {
+        "serve" => Some(SERVE_USAGE),
+        "validate-config" => Some(VALIDATE_CONFIG_USAGE),
+        "list-models" => Some(LIST_MODELS_USAGE),
+        "print-effective-config" => Some(PRINT_EFFECTIVE_CONFIG_USAGE),
+        "print-config-schema" => Some(PRINT_CONFIG_SCHEMA_USAGE),
+        "check-upstream" => Some(CHECK_UPSTREAM_USAGE),
+        "replay" => Some(crate::replay::USAGE),
+        _ => None,
+    }
+}
+
+fn wants_help(args: &[String]) -> bool {
+    args.iter().any(|arg| arg == "-h" || arg == "--help")
+}
+
+fn option_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a String, String> {
+    iter.next().ok_or_else(|| format!("{} 缺少参数", flag))
+}
+
+/// 解析 `serve` 参数；`legacy` 为未写子命令的旧写法，此时位置参数中的配置必须存在
+fn parse_serve(args: &[String], legacy: bool) -> Result<Command, String> {
+    if wants_help(args) {
+        return Ok(Command::Help(Some("serve".to_string())));
+    }
+    let mut options = ServeOptions::default();
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-c" | "--config" => options.config = Some(option_value(&mut iter, arg)?.clone()),
+            "-p" | "--port" => options.port = Some(parse_port(option_value(&mut iter, arg)?)?),
+            "-l" | "--listen" => options.listen.push(option_value(&mut iter, arg)?.clone()),
+            "--log-format" => {
+                options.log_format = Some(match option_value(&mut iter, arg)?.as_str() {
+                    "text" => LogFormat::Text,
+                    "json" => LogFormat::Json,
+                    other => return Err(format!("无效的日志格式 '{}'，应为 text 或 json", other)),
+                })
+            }
+            other if other.starts_with('-') => return Err(format!("未知参数 '{}'", other)),
+            other => positional.push(other),
+        }
+    }
+
+    match positional.as_slice() {
+        [] => {}
+        [config, rest @ ..] if rest.len() <= 1 && options.config.is_none() => {
+            // 旧写法中拼错的子命令不应被当作配置名静默启动
+            if legacy && !config_path_for(config).exists() {
+                return Err(format!("未知命令 '{}'", config));
+            }
+            options.config = Some(config.to_string());
+            if let [port] = rest {
+                if options.port.is_some() {
+                    return Err(format!("多余的参数 '{}'", port));
+                }
+                options.port = Some(parse_port(port)?);
+            }
+        }
+        [.., extra] => return Err(format!("多余的参数 '{}'", extra)),
+    }
+    Ok(Command::Serve(options))
+}
+
+fn parse_port(value: &str) -> Result<u16, String> {
+    value
+        .parse::<u16>()
+        .map_err(|_| format!("无效的端口 '{}'", value))
+}
+
+fn parse_config_option(args: &[String]) -> Result<Option<String>, String> {
+    let mut config = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-c" | "--config" => config = Some(option_value(&mut iter, arg)?.clone()),
+            other if other.starts_with('-') => return Err(format!("未知参数 '{}'", other)),
+            other => return Err(format!("多余的参数 '{}'", other)),
+        }
+    }
+    Ok(config)
+}
+
+/// 应用命令指定的配置文件路径，需在首次加载配置前调用
+pub fn apply_config_path(command: &Command) {
+    if let Some(config) = command.config() {
+        set_config_path(config_path_for(config));
+    }
+}
+
+/// 向 stdout 输出一行；stdout 已关闭（如输出被管道截断）时忽略错误
+macro_rules! outputln {
+    ($($arg:tt)*) => {
+        output(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+fn output(args: std::fmt::Arguments<'_>) {
+    use std::io::Write;
+    let _ = std::io::stdout().lock().write_fmt(args);
+}
+
+/// 执行工具子命令，返回进程退出码
+pub fn run(command: &Command) -> i32 {
+    match command {
+        Command::Help(topic) => {
+            outputln!("{}", topic.as_deref().and_then(usage_for).unwrap_or(USAGE));
+            EXIT_SUCCESS
+        }
+        Command::Version => {
+            outputln!("api-router {}", env!("CARGO_PKG_VERSION"));
+            EXIT_SUCCESS
+        }
+        Command::Replay(args) => crate::replay::run(args),
+        Command::ValidateConfig(_) => validate_config(),
+        Command::ListModels(_) => with_config(|config| {
+            output(format_args!("{}", format_models(config)));
+            EXIT_SUCCESS
+        }),
+        Command::PrintEffectiveConfig(_) => with_config(|config| {
+            let json = redacted_config_json(config);
+            outputln!(
+                "{}",
+                serde_json::to_string_pretty(&json).unwrap_or_else(|_| json.to_string())
+            );
+            EXIT_SUCCESS
+        }),
+        Command::PrintConfigSchema => {
+            let schema = config_schema();
+            outputln!(
+                "{}",
+                serde_json::to_string_pretty(&schema).unwrap_or_else(|_| schema.to_string())
+            );
+            EXIT_SUCCESS
+        }
+        Command::CheckUpstream(_) => with_config(|config| smol::block_on(check_upstream(config))),
+        Command::Serve(_) => {
+            eprintln!("serve 子命令由 main 处理");
+            EXIT_USAGE
+        }
+    }
+}
+
+/// 读取当前配置文件（不回退到默认配置）后执行 `action`，读取失败时以 1 退出
+fn with_config(action: impl FnOnce(&ApiConfig) -> i32) -> i32 {
+    let path = config_file_path();
+    match read_config_file(&path) {
+        Ok(config) => action(&config),
+        Err(err) => {
+            eprintln!("配置无效: {}", err);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// 校验配置文件，逐行输出全部问题
+fn validate_config() -> i32 {
+    let path = config_file_path();
+    let document = match read_document(&path) {
+        Ok(document) => document,
+        Err(err) => {
+            eprintln!("配置无效: {}", err);
+            return EXIT_FAILURE;
+        }
+    };
+    let overlays: Vec<String> = document.layers[1..]
+        .iter()
+        .map(|(layer, _)| layer.display().to_string())
+        .chain(document.env_overrides.iter().cloned())
+        .collect();
+    match parse_config_value(document.value) {
+        Ok(config) => {
+            outputln!(
+                "配置有效: {}（{} 个端点，{} 个 API Key）",
+                path.display(),
+                config.endpoints.len(),
+                config.keys.len()
+            );
+            if !overlays.is_empty() {
+                outputln!("覆盖: {}", overlays.join(", "));
+            }
+            EXIT_SUCCESS
+        }
+        Err(issues) => {
+            eprintln!("配置无效: {}（{} 个问题）", path.display(), issues.len());
+            for issue in &issues {
+                eprintln!("  {}", issue);
+            }
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// 按客户端模型名排序输出模型映射
+pub fn format_models(config: &ApiConfig) -> String {
+    let Some(mapping) = config.model_mapping.as_ref().filter(|m| !m.is_empty()) else {
+        return "未配置 modelMapping，模型名原样转发到上游\n".to_string();
+    };
+    let mut entries: Vec<_> = mapping.iter().collect();
+    entries.sort();
+    let width = entries
+        .iter()
+        .map(|(client, _)| client.len())
+        .max()
+        .unwrap_or(0);
+    entries
+        .into_iter()
+        .map(|(client, upstream)| format!("{:<width$}  ->  {}\n", client, upstream))
+        .collect()
+}
+
+/// 需要探测的上游：`(配置位置, baseUrl)`，同一 URL 只保留首次出现的位置
+pub fn upstream_targets(config: &ApiConfig) -> Vec<(String, String)> {
+    let mut targets: Vec<(String, String)> = Vec::new();
+    let mut push = |label: String, url: &str| {
+        if !url.is_empty() && !targets.iter().any(|(_, existing)| existing == url) {
+            targets.push((label, url.to_string()));
+        }
+    };
+    push("baseUrl".to_string(), &config.base_url);
+    if let Some(fallback) = config
+        .circuit_breaker
+        .as_ref()
+        .and_then(|breaker| breaker.fallback.as_ref())
+    {
+        push("circuitBreaker.fallback".to_string(), &fallback.base_url);
+    }
+    if let Some(alternate) = config.hedge.as_ref().and_then(|h| h.alternate.as_ref()) {
+        push("hedge.alternate".to_string(), &alternate.base_url);
+    }
+    let mut routes: Vec<_> = config.endpoints.iter().collect();
+    routes.sort_by(|a, b| a.0.cmp(b.0));
+    for (route, endpoint) in routes {
+        if let Some(fallback) = endpoint
+            .circuit_breaker
+            .as_ref()
+            .and_then(|breaker| breaker.fallback.as_ref())
+        {
+            push(
+                format!("endpoints.{}.circuitBreaker.fallback", route),
+                &fallback.base_url,
+            );
+        }
+        if let Some(alternate) = endpoint.hedge.as_ref().and_then(|h| h.alternate.as_ref()) {
+            push(
+                format!("endpoints.{}.hedge.alternate", route),
+                &alternate.base_url,
+            );
+        }
+    }
+    targets
+}
+
+/// 依次探测各上游，全部可达时返回 0
+pub async fn check_upstream(config: &ApiConfig) -> i32 {
+    proxy::configure(config.proxy.as_ref());
+    tls::configure(config.tls.as_ref());
+    let timeouts = config
+        .timeouts
+        .as_ref()
+        .map(Timeouts::from_config)
+        .unwrap_or_default();
+
+    let targets = upstream_targets(config);
+    if targets.is_empty() {
+        eprintln!("配置中没有上游 baseUrl");
+        return EXIT_FAILURE;
+    }
+    let mut failed = 0;
+    for (label, url) in &targets {
+        let shown = secrets::redact(&redact_url(url)).into_owned();
+        match probe_connection(url, &timeouts).await {
+            Ok(probe) => {
+                let mut detail = format!("{} ms", probe.elapsed.as_millis());
+                if probe.tls {
+                    detail.push_str(", TLS");
+                }
+                if let Some(proxy) = probe.proxy {
+                    detail.push_str(&format!(", via proxy {}", proxy));
+                }
+                outputln!("[OK]   {} {} ({})", label, shown, detail);
+            }
+            Err(err) => {
+                outputln!(
+                    "[FAIL] {} {}: {}",
+                    label,
+                    shown,
+                    secrets::redact(&err.to_string())
+                );
+                failed += 1;
+            }
+        }
+    }
+    outputln!("{} reachable, {} failed", targets.len() - failed, failed);
+    if failed > 0 {
+        EXIT_FAILURE
+    } else {
+        EXIT_SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CircuitBreakerConfig, FallbackConfig};
+    use std::collections::HashMap;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_subcommands_and_flags() {
+        assert_eq!(parse(&[]).unwrap(), Command::Serve(ServeOptions::default()));
+        assert_eq!(
+            parse(&args(&[
+                "serve",
+                "--config",
+                "openai",
+                "-p",
+                "9000",
+                "--listen",
+                "[::]:8000",
+                "-l",
+                "unix:/tmp/router.sock",
+                "--log-format",
+                "json",
+            ]))
+            .unwrap(),
+            Command::Serve(ServeOptions {
+                config: Some("openai".to_string()),
+                port: Some(9000),
+                listen: args(&["[::]:8000", "unix:/tmp/router.sock"]),
+                log_format: Some(LogFormat::Json),
+            })
+        );
+        assert_eq!(
+            parse(&args(&["validate-config", "-c", "/etc/router.json"])).unwrap(),
+            Command::ValidateConfig(Some("/etc/router.json".to_string()))
+        );
+        assert_eq!(
+            parse(&args(&["check-upstream"])).unwrap(),
+            Command::CheckUpstream(None)
+        );
+        assert_eq!(
+            parse(&args(&["print-config-schema"])).unwrap(),
+            Command::PrintConfigSchema
+        );
+        assert_eq!(
+            parse(&args(&[
+                "replay",
+                "capture.jsonl",
+                "--upstream",
+                "http://x"
+            ]))
+            .unwrap(),
+            Command::Replay(args(&["capture.jsonl", "--upstream", "http://x"]))
+        );
+    }
+
+    #[test]
+    fn keeps_legacy_positional_arguments() {
+        assert_eq!(
+            parse(&args(&["openai", "9000"])).unwrap(),
+            Command::Serve(ServeOptions {
+                config: Some("openai".to_string()),
+                port: Some(9000),
+                ..ServeOptions::default()
+            })
+        );
+        // 拼错的子命令不会被当作配置名启动
+        assert_eq!(
+            parse(&args(&["valdate-config"])).unwrap_err(),
+            "未知命令 'valdate-config'"
+        );
+    }
+
+    #[test]
+    fn reports_usage_errors_and_help() {
+        assert!(parse(&args(&["serve", "--port", "http"])).is_err());
+        assert!(parse(&args(&["serve", "--bogus"])).is_err());
+        assert!(parse(&args(&["serve", "--log-format", "xml"])).is_err());
+        assert!(parse(&args(&["list-models", "extra"])).is_err());
+        assert!(parse(&args(&["print-config-schema", "-c", "qwen"])).is_err());
+        assert!(parse(&args(&["serve", "--config"])).is_err());
+        assert!(parse(&args(&["help", "nope"])).is_err());
+        assert_eq!(parse(&args(&["--help"])).unwrap(), Command::Help(None));
+        assert_eq!(
+            parse(&args(&["check-upstream", "-h"])).unwrap(),
+            Command::Help(Some("check-upstream".to_string()))
+        );
+        assert_eq!(
+            parse(&args(&["replay", "--help"])).unwrap(),
+            Command::Help(Some("replay".to_string()))
+        );
+        assert_eq!(run(&Command::Help(Some("serve".to_string()))), EXIT_SUCCESS);
+    }
+
+    #[test]
+    fn listen_flags_replace_configured_listeners() {
+        let options = ServeOptions {
+            listen: args(&["127.0.0.1:9000", "unix:/run/router.sock"]),
+            ..ServeOptions::default()
+        };
+        let mut listen = ListenConfig {
+            tcp: args(&["0.0.0.0:8000"]),
+            ..ListenConfig::default()
+        };
+        options.apply_listen(&mut listen);
+        assert_eq!(listen.tcp, args(&["127.0.0.1:9000"]));
+        assert_eq!(listen.unix[0].path, "/run/router.sock");
+    }
+
+    #[test]
+    fn formats_model_mapping_sorted() {
+        let mut config = ApiConfig::default();
+        assert!(format_models(&config).contains("modelMapping"));
+        config.model_mapping = Some(HashMap::from([
+            ("gpt-4o".to_string(), "qwen3-coder-plus".to_string()),
+            ("claude".to_string(), "qwen3-max".to_string()),
+        ]));
+        assert_eq!(
+            format_models(&config),
+            "claude  ->  qwen3-max\ngpt-4o  ->  qwen3-coder-plus\n"
+        );
+    }
+
+    #[test]
+    fn checks_every_distinct_upstream() {
+        let reachable = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+
+        let base_url = format!("http://{}", reachable.local_addr().unwrap());
+        let mut config = ApiConfig {
+            base_url: base_url.clone(),
+            circuit_breaker: Some(CircuitBreakerConfig {
+                fallback: Some(FallbackConfig {
+                    base_url: base_url.clone(),
+                    headers: HashMap::new(),
+                }),
+                ..CircuitBreakerConfig::default()
+            }),
+            ..ApiConfig::default()
+        };
+        assert_eq!(upstream_targets(&config).len(), 1);
+        assert_eq!(smol::block_on(check_upstream(&config)), EXIT_SUCCESS);
+
+        config
+            .circuit_breaker
+            .as_mut()
+            .unwrap()
+            .fallback
+            .as_mut()
+            .unwrap()
+            .base_url = closed_url;
+        let targets = upstream_targets(&config);
+        assert_eq!(targets[1].0, "circuitBreaker.fallback");
+        assert_eq!(smol::block_on(check_upstream(&config)), EXIT_FAILURE);
+    }
+}
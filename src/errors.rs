@@ -34,6 +34,66 @@ pub enum RouterError {
     /// 客户端请求格式错误
     #[error("Bad request: {0}")]
     BadRequest(String),
+    /// 出站代理握手或认证失败
+    #[error("Proxy error: {0}")]
+    Proxy(String),
+    /// 上游熔断器打开且没有可用的备用上游
+    #[error("Circuit open: {0}")]
+    CircuitOpen(String),
+    /// 建立上游 TCP 连接超时
+    #[error("Connect timeout: {0}")]
+    ConnectTimeout(String),
+    /// 上游 TLS 握手超时
+    #[error("TLS handshake timeout: {0}")]
+    TlsHandshakeTimeout(String),
+    /// 发出请求后等待上游首字节超时
+    #[error("First byte timeout: {0}")]
+    FirstByteTimeout(String),
+    /// 上游响应读取过程中长时间没有新数据
+    #[error("Idle read timeout: {0}")]
+    IdleTimeout(String),
+    /// 上游请求超过整体截止时间
+    #[error("Total timeout: {0}")]
+    TotalTimeout(String),
+    /// 请求被管理接口取消
+    #[error("Request cancelled: {0}")]
+    Cancelled(String),
+}
+
+impl RouterError {
+    /// 错误分类标识，用于指标标签与访问日志
+    pub fn class(&self) -> &'static str {
+        match self {
+            RouterError::Url(_) => "url_error",
+            RouterError::Io(_) => "io_error",
+            RouterError::ConfigRead(_) => "config_read_error",
+            RouterError::ConfigParse(_) => "config_parse_error",
+            RouterError::Json(_) => "json_error",
+            RouterError::Upstream(_) => "upstream_error",
+            RouterError::Tls(_) => "tls_error",
+            RouterError::BadRequest(_) => "bad_request",
+            RouterError::Proxy(_) => "proxy_error",
+            RouterError::CircuitOpen(_) => "circuit_open",
+            RouterError::ConnectTimeout(_) => "connect_timeout",
+            RouterError::TlsHandshakeTimeout(_) => "tls_handshake_timeout",
+            RouterError::FirstByteTimeout(_) => "first_byte_timeout",
+            RouterError::IdleTimeout(_) => "idle_timeout",
+            RouterError::TotalTimeout(_) => "total_timeout",
+            RouterError::Cancelled(_) => "cancelled",
+        }
+    }
+
+    /// 是否为上游超时错误
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            RouterError::ConnectTimeout(_)
+                | RouterError::TlsHandshakeTimeout(_)
+                | RouterError::FirstByteTimeout(_)
+                | RouterError::IdleTimeout(_)
+                | RouterError::TotalTimeout(_)
+        )
+    }
 }
 
 /// Router 操作的统一结果类型
@@ -101,6 +161,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_literal_unwrap)]
     fn router_result_ok_works() {
         let result: RouterResult<i32> = Ok(42);
         assert_eq!(result.unwrap(), 42);
@@ -119,4 +180,20 @@ mod tests {
         assert!(debug_str.contains("BadRequest"));
         assert!(debug_str.contains("test"));
     }
+
+    #[test]
+    fn timeout_errors_have_distinct_classes() {
+        let errors = [
+            RouterError::ConnectTimeout("c".to_string()),
+            RouterError::TlsHandshakeTimeout("t".to_string()),
+            RouterError::FirstByteTimeout("f".to_string()),
+            RouterError::IdleTimeout("i".to_string()),
+            RouterError::TotalTimeout("a".to_string()),
+        ];
+        let classes: std::collections::HashSet<_> = errors.iter().map(|e| e.class()).collect();
+        assert_eq!(classes.len(), errors.len());
+        assert!(errors.iter().all(RouterError::is_timeout));
+        assert!(!RouterError::Upstream("x".to_string()).is_timeout());
+        assert_eq!(format!("{}", errors[2]), "First byte timeout: f");
+    }
 }
@@ -0,0 +1,615 @@
+//! 管理接口模块
+//!
+//! `/admin` 下的运行时查看与控制接口，请求与响应均为 JSON：
+//! - `GET /admin/config`：脱敏后的生效配置；`POST /admin/config/reload`：立即重新加载配置
+//! - `GET /admin/rate-limits`、`DELETE /admin/rate-limits?key=...`：列出与重置令牌桶
+//! - `GET /admin/providers`、`POST /admin/providers/{name}/enable|disable`：启用或停用提供商
+//! - `GET /admin/circuits`、`POST /admin/circuits/{provider}/open|close|half-open`：查看与设置熔断器
+//! - `GET /admin/requests`、`DELETE /admin/requests/{id}`：列出与取消进行中的请求
+//!
+//! 配置了 `admin.listen` 时只在独立监听端上提供；否则需要配置 `admin.token`，
+//! 在主监听端上凭令牌访问。两种方式下配置了令牌时都要校验
+
+use crate::circuit_breaker::{upstream_id, CircuitState, CIRCUIT_BREAKERS};
+use crate::config::{load_api_config, redacted_config_json, reload_config, ApiConfig};
+use crate::inflight::ACTIVE_REQUESTS;
+use crate::rate_limit::RATE_LIMITER;
+use crate::reload::reload_status;
+use crate::tracing_util::extract_provider;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write as IoWrite;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{info, warn};
+
+use super::parser::{anonymize_key, key_alias, ParsedRequest};
+use super::router::PROXY_ROUTES;
+
+/// 管理接口的路由前缀
+pub const ADMIN_PREFIX: &str = "/admin";
+
+/// 管理接口是否绑定在独立监听端上，只在启动时设置
+static DEDICATED_LISTENER: AtomicBool = AtomicBool::new(false);
+
+/// 标记管理接口已绑定独立监听端，之后主监听端不再提供 `/admin`
+pub fn set_dedicated_listener(enabled: bool) {
+    DEDICATED_LISTENER.store(enabled, Ordering::Relaxed);
+}
+
+/// 路径是否属于管理接口
+pub(super) fn is_admin_path(path: &str) -> bool {
+    path == ADMIN_PREFIX
+        || path
+            .strip_prefix(ADMIN_PREFIX)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// 管理接口响应
+#[derive(Debug)]
+pub(super) struct AdminResponse {
+    pub status: u16,
+    reason: &'static str,
+    pub body: Value,
+}
+
+impl AdminResponse {
+    fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            reason: "OK",
+            body,
+        }
+    }
+
+    fn error(status: u16, reason: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            reason,
+            body: json!({"error": {"message": message}}),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::error(404, "NOT FOUND", "Not Found")
+    }
+
+    /// 完整的 HTTP 响应
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body = serde_json::to_vec(&self.body).expect("JSON serialization should not fail");
+        let mut response = Vec::with_capacity(160 + body.len());
+        write!(
+            &mut response,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nCache-Control: no-store\r\n",
+            self.status,
+            self.reason,
+            body.len()
+        )
+        .expect("writing to Vec<u8> cannot fail");
+        if self.status == 401 {
+            response.extend_from_slice(b"WWW-Authenticate: Bearer realm=\"admin\"\r\n");
+        }
+        response.extend_from_slice(b"\r\n");
+        response.extend_from_slice(&body);
+        response
+    }
+}
+
+/// 处理管理接口请求；`dedicated` 表示请求来自独立的管理监听端
+pub(super) fn respond(request: &ParsedRequest, dedicated: bool) -> AdminResponse {
+    match load_api_config() {
+        Ok(config) => respond_with(request, dedicated, &config),
+        Err(err) => {
+            warn!("管理接口无法加载配置: {}", err);
+            AdminResponse::error(503, "SERVICE UNAVAILABLE", "Config unavailable")
+        }
+    }
+}
+
+fn respond_with(request: &ParsedRequest, dedicated: bool, config: &ApiConfig) -> AdminResponse {
+    let token = config
+        .admin
+        .as_ref()
+        .and_then(|admin| admin.token.as_deref());
+    // 主监听端上未启用管理接口时与不存在的路由一致
+    if !dedicated && (DEDICATED_LISTENER.load(Ordering::Relaxed) || token.is_none()) {
+        return AdminResponse::not_found();
+    }
+    if let Some(token) = token {
+        if !presented_token(request).is_some_and(|presented| constant_time_eq(presented, token)) {
+            warn!(route = request.route_path(), "管理接口令牌无效");
+            return AdminResponse::error(401, "UNAUTHORIZED", "Invalid admin token");
+        }
+    }
+
+    let path = request.route_path();
+    let segments: Vec<&str> = path
+        .strip_prefix(ADMIN_PREFIX)
+        .unwrap_or(path)
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let query = query_params(request.target());
+    match (request.method(), segments.as_slice()) {
+        ("GET", ["config"]) => AdminResponse::ok(redacted_config_json(config)),
+        ("POST", ["config", "reload"]) => reload(),
+        ("GET", ["rate-limits"]) => list_buckets(config, &query),
+        ("DELETE", ["rate-limits"]) => reset_buckets(config, &query),
+        ("GET", ["providers"]) => list_providers(config),
+        ("POST", ["providers", provider, action]) => set_provider(provider, action),
+        ("GET", ["circuits"]) => AdminResponse::ok(json!({
+            "circuits": CIRCUIT_BREAKERS.snapshot(),
+        })),
+        ("POST", ["circuits", provider, action]) => force_circuit(provider, action, &query),
+        ("GET", ["requests"]) => AdminResponse::ok(json!({
+            "requests": ACTIVE_REQUESTS.snapshot(),
+        })),
+        ("DELETE", ["requests", id]) => cancel_request(id),
+        _ => AdminResponse::not_found(),
+    }
+}
+
+/// 请求中携带的管理令牌：`Authorization: Bearer` 或 `X-Admin-Token`
+fn presented_token(request: &ParsedRequest) -> Option<&str> {
+    request
+        .header("authorization")
+        .and_then(|value| {
+            let (scheme, token) = value.trim().split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        })
+        .or_else(|| request.header("x-admin-token").map(str::trim))
+}
+
+/// 比较耗时与内容无关，避免通过响应时间逐字节猜测令牌
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+fn reload() -> AdminResponse {
+    info!("管理接口触发配置重新加载");
+    match reload_config() {
+        Ok(_) => AdminResponse::ok(json!({
+            "reloaded": true,
+            "status": reload_status().to_json(),
+        })),
+        Err(_) => {
+            let mut response =
+                AdminResponse::error(422, "UNPROCESSABLE ENTITY", "Config reload failed");
+            response.body["status"] = reload_status().to_json();
+            response
+        }
+    }
+}
+
+/// 客户端 Key 是否与查询条件匹配：原文、别名或脱敏形式
+fn key_matches(config: &ApiConfig, api_key: &str, wanted: &str) -> bool {
+    api_key == wanted || key_alias(config, api_key) == wanted || anonymize_key(api_key) == wanted
+}
+
+fn bucket_filter<'a>(
+    config: &'a ApiConfig,
+    query: &'a BTreeMap<String, String>,
+) -> impl Fn(&str, &str) -> bool + 'a {
+    move |route, api_key| {
+        query.get("route").is_none_or(|wanted| wanted == route)
+            && query
+                .get("key")
+                .is_none_or(|wanted| key_matches(config, api_key, wanted))
+    }
+}
+
+fn list_buckets(config: &ApiConfig, query: &BTreeMap<String, String>) -> AdminResponse {
+    let buckets: Vec<Value> = RATE_LIMITER
+        .buckets(bucket_filter(config, query))
+        .into_iter()
+        .map(|bucket| {
+            json!({
+                "route": bucket.route,
+                "key": key_alias(config, &bucket.api_key),
+                "tokens": (bucket.tokens * 100.0).floor() / 100.0,
+                "burst": bucket.settings.burst,
+                "requestsPerMinute": bucket.settings.requests_per_minute,
+            })
+        })
+        .collect();
+    AdminResponse::ok(json!({ "buckets": buckets }))
+}
+
+fn reset_buckets(config: &ApiConfig, query: &BTreeMap<String, String>) -> AdminResponse {
+    let Some(key) = query.get("key") else {
+        return AdminResponse::error(400, "BAD REQUEST", "Missing query parameter: key");
+    };
+    let reset = RATE_LIMITER.reset(bucket_filter(config, query));
+    info!(
+        key = %anonymize_key(key),
+        route = query.get("route").map(String::as_str).unwrap_or("*"),
+        reset,
+        "管理接口重置令牌桶"
+    );
+    AdminResponse::ok(json!({ "reset": reset }))
+}
+
+/// 配置中出现的上游（主上游、熔断备用上游与对冲上游）及其用途，按 [`upstream_id`] 区分
+fn configured_upstreams(config: &ApiConfig) -> BTreeMap<String, Vec<&'static str>> {
+    let mut upstreams: BTreeMap<String, Vec<&'static str>> = BTreeMap::new();
+    let mut add = |base_url: &str, role: &'static str| {
+        let roles = upstreams.entry(upstream_id(base_url)).or_default();
+        if !roles.contains(&role) {
+            roles.push(role);
+        }
+    };
+    add(&config.base_url, "primary");
+    let breakers = std::iter::once(config.circuit_breaker.as_ref()).chain(
+        config
+            .endpoints
+            .values()
+            .map(|e| e.circuit_breaker.as_ref()),
+    );
+    for fallback in breakers.flatten().filter_map(|b| b.fallback.as_ref()) {
+        add(&fallback.base_url, "fallback");
+    }
+    let hedges = std::iter::once(config.hedge.as_ref())
+        .chain(config.endpoints.values().map(|e| e.hedge.as_ref()));
+    for alternate in hedges.flatten().filter_map(|h| h.alternate.as_ref()) {
+        add(&alternate.base_url, "hedge");
+    }
+    upstreams
+}
+
+fn list_providers(config: &ApiConfig) -> AdminResponse {
+    let mut upstreams = configured_upstreams(config);
+    for disabled in CIRCUIT_BREAKERS.disabled_upstreams() {
+        upstreams.entry(disabled).or_default();
+    }
+    let providers: Vec<Value> = upstreams
+        .into_iter()
+        .map(|(name, roles)| {
+            json!({
+                "enabled": CIRCUIT_BREAKERS.is_enabled(&name),
+                "provider": extract_provider(&name),
+                "name": name,
+                "roles": roles,
+            })
+        })
+        .collect();
+    AdminResponse::ok(json!({ "providers": providers }))
+}
+
+fn set_provider(provider: &str, action: &str) -> AdminResponse {
+    let enabled = match action {
+        "enable" => true,
+        "disable" => false,
+        _ => return AdminResponse::not_found(),
+    };
+    let changed = CIRCUIT_BREAKERS.set_enabled(provider, enabled);
+    AdminResponse::ok(json!({
+        "provider": provider,
+        "enabled": enabled,
+        "changed": changed,
+    }))
+}
+
+fn force_circuit(provider: &str, action: &str, query: &BTreeMap<String, String>) -> AdminResponse {
+    let state = match action {
+        "open" => CircuitState::Open,
+        "close" => CircuitState::Closed,
+        "half-open" => CircuitState::HalfOpen,
+        _ => return AdminResponse::not_found(),
+    };
+    let routes: Vec<&str> = match query.get("route") {
+        Some(route) if PROXY_ROUTES.contains(&route.as_str()) => vec![route.as_str()],
+        Some(route) => {
+            return AdminResponse::error(400, "BAD REQUEST", &format!("Unknown route: {}", route))
+        }
+        None => PROXY_ROUTES.to_vec(),
+    };
+    for route in &routes {
+        CIRCUIT_BREAKERS.force_state(provider, route, state);
+    }
+    warn!(provider = %provider, state = ?state, routes = ?routes, "管理接口设置熔断器状态");
+    AdminResponse::ok(json!({
+        "provider": provider,
+        "state": state,
+        "routes": routes,
+    }))
+}
+
+fn cancel_request(id: &str) -> AdminResponse {
+    if ACTIVE_REQUESTS.cancel(id) {
+        warn!(cancelled_request = %id, "管理接口取消请求");
+        AdminResponse::ok(json!({ "cancelled": id }))
+    } else {
+        AdminResponse::error(404, "NOT FOUND", "Request not found")
+    }
+}
+
+/// 解析查询参数，解码 `%XX` 与 `+`；重复的参数以最后一个为准
+fn query_params(target: &str) -> BTreeMap<String, String> {
+    target
+        .split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'+' => decoded.push(b' '),
+            b'%' if index + 2 < bytes.len() => {
+                match (hex(bytes[index + 1]), hex(bytes[index + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        index += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AdminConfig, CircuitBreakerConfig, FallbackConfig, KeyConfig};
+    use crate::inflight::RequestInfo;
+    use crate::rate_limit::RateLimitSettings;
+    use std::collections::HashMap;
+
+    fn request(method: &str, target: &str, token: Option<&str>) -> ParsedRequest {
+        let mut headers = HashMap::new();
+        if let Some(token) = token {
+            headers.insert("Authorization".to_string(), format!("Bearer {}", token));
+        }
+        ParsedRequest::new_for_tests(method, target, "HTTP/1.1", headers, Vec::new())
+    }
+
+    fn config(token: Option<&str>) -> ApiConfig {
+        ApiConfig {
+            base_url: "https://api.cohere.com".to_string(),
+            admin: Some(AdminConfig {
+                token: token.map(str::to_string),
+                listen: None,
+            }),
+            ..ApiConfig::default()
+        }
+    }
+
+    #[test]
+    fn requires_token_on_shared_listener() {
+        let get_config = |token| request("GET", "/admin/config", token);
+        let disabled = respond_with(&get_config(None), false, &config(None));
+        assert_eq!(disabled.status, 404);
+
+        let config = config(Some("admin-secret"));
+        let missing = respond_with(&get_config(None), false, &config);
+        assert_eq!(missing.status, 401);
+        assert!(String::from_utf8(missing.to_bytes())
+            .unwrap()
+            .contains("WWW-Authenticate: Bearer"));
+        assert_eq!(
+            respond_with(&get_config(Some("admin-secreT")), false, &config).status,
+            401
+        );
+
+        let allowed = respond_with(&get_config(Some("admin-secret")), false, &config);
+        assert_eq!(allowed.status, 200);
+        assert_eq!(allowed.body["baseUrl"], "https://api.cohere.com");
+        assert_eq!(allowed.body["admin"]["token"], crate::capture::REDACTED);
+
+        let mut headers = HashMap::new();
+        headers.insert("X-Admin-Token".to_string(), "admin-secret".to_string());
+        let header_token =
+            ParsedRequest::new_for_tests("GET", "/admin/config", "HTTP/1.1", headers, Vec::new());
+        assert_eq!(respond_with(&header_token, false, &config).status, 200);
+    }
+
+    #[test]
+    fn dedicated_listener_without_token_is_open() {
+        let response = respond_with(
+            &request("GET", "/admin/requests", None),
+            true,
+            &ApiConfig::default(),
+        );
+        assert_eq!(response.status, 200);
+        assert!(response.body["requests"].is_array());
+        assert_eq!(
+            respond_with(
+                &request("GET", "/admin/unknown", None),
+                true,
+                &ApiConfig::default()
+            )
+            .status,
+            404
+        );
+    }
+
+    #[test]
+    fn lists_and_resets_rate_limit_buckets_by_alias() {
+        let mut config = config(None);
+        config.keys.insert(
+            "sk-admin-bucket-key".to_string(),
+            KeyConfig {
+                alias: Some("admin-team".to_string()),
+                priority: None,
+            },
+        );
+        let settings = RateLimitSettings {
+            requests_per_minute: 60,
+            burst: 5,
+        };
+        RATE_LIMITER.check("/v1/admin-test", "sk-admin-bucket-key", &settings);
+
+        let listed = respond_with(
+            &request("GET", "/admin/rate-limits?key=admin-team", None),
+            true,
+            &config,
+        );
+        let buckets = listed.body["buckets"].as_array().unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0]["key"], "admin-team");
+        assert_eq!(buckets[0]["route"], "/v1/admin-test");
+        assert_eq!(buckets[0]["burst"], 5);
+        assert!(!listed.body.to_string().contains("sk-admin-bucket-key"));
+
+        assert_eq!(
+            respond_with(
+                &request("DELETE", "/admin/rate-limits", None),
+                true,
+                &config
+            )
+            .status,
+            400
+        );
+        let reset = respond_with(
+            &request(
+                "DELETE",
+                "/admin/rate-limits?key=sk-a***ey&route=%2Fv1%2Fadmin-test",
+                None,
+            ),
+            true,
+            &config,
+        );
+        assert_eq!(reset.body["reset"], 1);
+        assert!(RATE_LIMITER
+            .buckets(|route, _| route == "/v1/admin-test")
+            .is_empty());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn toggles_providers_and_circuits() {
+        let mut config = config(None);
+        config.circuit_breaker = Some(CircuitBreakerConfig {
+            fallback: Some(FallbackConfig {
+                base_url: "https://api.anthropic.com".to_string(),
+                headers: HashMap::new(),
+            }),
+            ..CircuitBreakerConfig::default()
+        });
+
+        let disabled = respond_with(
+            &request("POST", "/admin/providers/api.cohere.com/disable", None),
+            true,
+            &config,
+        );
+        assert_eq!(disabled.body["changed"], true);
+        let providers = respond_with(&request("GET", "/admin/providers", None), true, &config);
+        assert_eq!(
+            providers.body["providers"],
+            json!([
+                {"name": "api.anthropic.com", "provider": "anthropic", "enabled": true, "roles": ["fallback"]},
+                {"name": "api.cohere.com", "provider": "cohere", "enabled": false, "roles": ["primary"]},
+            ])
+        );
+        respond_with(
+            &request("POST", "/admin/providers/api.cohere.com/enable", None),
+            true,
+            &config,
+        );
+        assert!(CIRCUIT_BREAKERS.is_enabled("api.cohere.com"));
+
+        let opened = respond_with(
+            &request(
+                "POST",
+                "/admin/circuits/api.cohere.com/open?route=/v1/embeddings",
+                None,
+            ),
+            true,
+            &config,
+        );
+        assert_eq!(opened.body["routes"], json!(["/v1/embeddings"]));
+        let circuits = respond_with(&request("GET", "/admin/circuits", None), true, &config);
+        assert!(circuits.body["circuits"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|c| c["upstream"] == "api.cohere.com" && c["state"] == "open"));
+        assert_eq!(
+            respond_with(
+                &request(
+                    "POST",
+                    "/admin/circuits/api.cohere.com/close?route=/v1/nope",
+                    None
+                ),
+                true,
+                &config
+            )
+            .status,
+            400
+        );
+        let closed = respond_with(
+            &request("POST", "/admin/circuits/api.cohere.com/close", None),
+            true,
+            &config,
+        );
+        assert_eq!(
+            closed.body["routes"].as_array().unwrap().len(),
+            PROXY_ROUTES.len()
+        );
+    }
+
+    #[test]
+    fn cancels_in_flight_requests() {
+        let config = ApiConfig::default();
+        let guard = ACTIVE_REQUESTS.register(
+            "admin-test-request",
+            RequestInfo {
+                route: "/v1/chat/completions".to_string(),
+                ..RequestInfo::default()
+            },
+        );
+        let listed = respond_with(&request("GET", "/admin/requests", None), true, &config);
+        assert!(listed.body["requests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|r| r["id"] == "admin-test-request"));
+
+        let cancelled = respond_with(
+            &request("DELETE", "/admin/requests/admin-test-request", None),
+            true,
+            &config,
+        );
+        assert_eq!(cancelled.status, 200);
+        smol::block_on(guard.cancelled());
+        drop(guard);
+        assert_eq!(
+            respond_with(
+                &request("DELETE", "/admin/requests/admin-test-request", None),
+                true,
+                &config
+            )
+            .status,
+            404
+        );
+    }
+
+    #[test]
+    fn decodes_query_parameters() {
+        let query = query_params("/admin/rate-limits?key=team+a&route=%2Fv1%2Fembeddings&bad=%zz");
+        assert_eq!(query["key"], "team a");
+        assert_eq!(query["route"], "/v1/embeddings");
+        assert_eq!(query["bad"], "%zz");
+        assert!(is_admin_path("/admin"));
+        assert!(is_admin_path("/admin/config"));
+        assert!(!is_admin_path("/administrator"));
+    }
+}
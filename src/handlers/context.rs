@@ -0,0 +1,105 @@
+//! 请求上下文模块
+//!
+//! 在路由处理过程中收集与单个请求相关的观测数据（提供商、映射后的模型、
+//! 上游延迟等），供请求结束时统一记录指标
+
+use crate::config::ApiConfig;
+use crate::usage::TokenUsage;
+use std::time::Duration;
+
+/// 未在配置中出现的模型在指标中的统一标签
+pub const OTHER_MODEL_LABEL: &str = "other";
+
+/// 单个代理请求的上下文
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    /// 上游提供商名称
+    pub provider: String,
+    /// 客户端请求的模型名称
+    pub requested_model: String,
+    /// 映射后的上游模型名称
+    pub model: String,
+    /// 上游请求耗时（流式请求为整个流的持续时间）
+    pub upstream_latency: Option<Duration>,
+    /// 流式请求的首字节时间
+    pub first_byte: Option<Duration>,
+    /// 上游响应的状态码
+    pub upstream_status: Option<u16>,
+    /// 上游响应中报告的 token 用量
+    pub usage: Option<TokenUsage>,
+    /// 写给客户端的响应正文字节数
+    pub bytes_out: u64,
+    /// 响应是否来自响应缓存（未访问上游）
+    pub cache_hit: bool,
+}
+
+impl RequestContext {
+    /// 使用提供商名称创建上下文
+    pub fn new(provider: &str) -> Self {
+        Self {
+            provider: provider.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// 用于指标标签的模型名
+    ///
+    /// 只保留配置中出现过的模型（`modelMapping` 的键或值、`pricing` 中的模型），
+    /// 其他由客户端传入的模型记为 [`OTHER_MODEL_LABEL`]，避免标签基数随客户端输入增长
+    pub fn metrics_model(&self, config: &ApiConfig) -> &str {
+        if self.model.is_empty() {
+            return "";
+        }
+        let mapped = config.model_mapping.as_ref().is_some_and(|mapping| {
+            mapping.contains_key(&self.requested_model)
+                || mapping.values().any(|m| *m == self.model)
+        });
+        if mapped || config.pricing.contains_key(&self.model) {
+            &self.model
+        } else {
+            OTHER_MODEL_LABEL
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModelPrice;
+    use std::collections::HashMap;
+
+    fn context(requested_model: &str, model: &str) -> RequestContext {
+        RequestContext {
+            requested_model: requested_model.to_string(),
+            model: model.to_string(),
+            ..RequestContext::new("openai")
+        }
+    }
+
+    #[test]
+    fn metrics_model_collapses_models_missing_from_config() {
+        let config = ApiConfig {
+            model_mapping: Some(HashMap::from([(
+                "gpt-4".to_string(),
+                "claude-3-opus".to_string(),
+            )])),
+            pricing: HashMap::from([("gpt-4o".to_string(), ModelPrice::default())]),
+            ..ApiConfig::default()
+        };
+
+        assert_eq!(
+            context("gpt-4", "claude-3-opus").metrics_model(&config),
+            "claude-3-opus"
+        );
+        assert_eq!(
+            context("claude-3-opus", "claude-3-opus").metrics_model(&config),
+            "claude-3-opus"
+        );
+        assert_eq!(context("gpt-4o", "gpt-4o").metrics_model(&config), "gpt-4o");
+        assert_eq!(
+            context("random-123", "random-123").metrics_model(&config),
+            OTHER_MODEL_LABEL
+        );
+        assert_eq!(context("", "").metrics_model(&config), "");
+    }
+}
@@ -1,10 +1,13 @@
+pub mod admin;
+pub mod context;
 pub mod parser;
 pub mod plan;
 pub mod response;
 pub mod router;
 pub mod routes;
 
-pub use router::handle_request;
+pub use admin::set_dedicated_listener;
+pub use router::{handle_admin_request, handle_request};
 
 #[cfg(test)]
 mod tests;
@@ -2,6 +2,7 @@
 //!
 //! 提供 HTTP 请求的解析功能，包括请求行、头部和正文的提取
 
+use crate::config::ApiConfig;
 use crate::errors::{RouterError, RouterResult};
 use std::collections::HashMap;
 use std::env;
@@ -145,7 +146,7 @@ pub(super) fn extract_client_api_key(
         .unwrap_or_else(|| default_api_key.to_string())
 }
 
-pub(super) fn anonymize_key(key: &str) -> String {
+pub(crate) fn anonymize_key(key: &str) -> String {
     if key.is_empty() {
         return "unknown".to_string();
     }
@@ -160,6 +161,25 @@ pub(super) fn anonymize_key(key: &str) -> String {
     format!("{}***{}", prefix, suffix)
 }
 
+/// 未配置别名的 Key 在指标中的统一标签
+pub(super) const UNALIASED_KEY_LABEL: &str = "unaliased";
+
+/// 返回用于日志的 Key 标识：优先使用配置中的别名，否则使用脱敏后的 Key
+pub(super) fn key_alias(config: &ApiConfig, key: &str) -> String {
+    configured_alias(config, key).unwrap_or_else(|| anonymize_key(key))
+}
+
+/// 返回用于指标标签的 Key 标识：配置中的别名，未配置时为 [`UNALIASED_KEY_LABEL`]
+///
+/// 指标不使用脱敏 Key，避免在标签中暴露 Key 的部分字符，也避免标签数量随客户端 Key 增长
+pub(super) fn metrics_key_label(config: &ApiConfig, key: &str) -> String {
+    configured_alias(config, key).unwrap_or_else(|| UNALIASED_KEY_LABEL.to_string())
+}
+
+fn configured_alias(config: &ApiConfig, key: &str) -> Option<String> {
+    config.keys.get(key).and_then(|entry| entry.alias.clone())
+}
+
 fn parse_authorization_header(value: &str) -> Option<String> {
     let trimmed = value.trim();
     if trimmed.is_empty() {
@@ -178,6 +198,7 @@ fn parse_authorization_header(value: &str) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::KeyConfig;
 
     #[test]
     fn parse_http_request_extracts_all_parts() {
@@ -354,6 +375,25 @@ mod tests {
         assert_eq!(anon, "unknown");
     }
 
+    #[test]
+    fn key_alias_prefers_configured_alias() {
+        let mut config = ApiConfig::default();
+        config.keys.insert(
+            "sk-team-secret".to_string(),
+            KeyConfig {
+                alias: Some("team-a".to_string()),
+                priority: None,
+            },
+        );
+        assert_eq!(key_alias(&config, "sk-team-secret"), "team-a");
+        assert_eq!(key_alias(&config, "sk-other-key"), "sk-o***ey");
+        assert_eq!(metrics_key_label(&config, "sk-team-secret"), "team-a");
+        assert_eq!(
+            metrics_key_label(&config, "sk-other-key"),
+            UNALIASED_KEY_LABEL
+        );
+    }
+
     #[test]
     fn parse_authorization_header_extracts_bearer_token() {
         let token = parse_authorization_header("Bearer token123");
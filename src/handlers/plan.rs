@@ -1,4 +1,7 @@
-use crate::config::{ApiConfig, EndpointConfig, StreamConfig};
+use crate::config::{ApiConfig, EndpointConfig, FallbackConfig, StreamConfig};
+use crate::otel::current_traceparent;
+use crate::retry::{resolve_retry_policy, RetryPolicy};
+use crate::timeout::{resolve_timeouts, Timeouts};
 
 use super::parser::ParsedRequest;
 use std::collections::HashMap;
@@ -10,6 +13,8 @@ pub(super) struct ForwardPlan {
     base_url: String,
     path: String,
     stream_config: Option<StreamConfig>,
+    retry: Option<RetryPolicy>,
+    timeouts: Timeouts,
 }
 
 impl ForwardPlan {
@@ -33,9 +38,25 @@ impl ForwardPlan {
         self.stream_config.as_ref()
     }
 
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry.as_ref()
+    }
+
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
     pub fn full_url(&self) -> String {
         join_base_and_path(&self.base_url, &self.path)
     }
+
+    /// 生成发往另一个上游的计划：替换基础 URL，去掉原上游的鉴权头后覆盖为该上游的请求头
+    pub fn rebased(&self, upstream: &FallbackConfig) -> ForwardPlan {
+        let mut plan = self.clone();
+        plan.base_url = normalized_base_url(&upstream.base_url);
+        upstream.rebase_headers(&mut plan.headers);
+        plan
+    }
 }
 
 pub(super) fn map_model_name(config: &ApiConfig, model: &str) -> String {
@@ -72,6 +93,8 @@ pub(super) fn prepare_forward_plan(
         .stream_config
         .clone()
         .or_else(|| config.stream_config.clone());
+    let retry = resolve_retry_policy(route_path, config);
+    let timeouts = resolve_timeouts(route_path, config);
 
     ForwardPlan {
         method,
@@ -79,14 +102,14 @@ pub(super) fn prepare_forward_plan(
         base_url,
         path,
         stream_config,
+        retry,
+        timeouts,
     }
 }
 
 pub(super) fn compute_upstream_path(request_target: &str, endpoint: &EndpointConfig) -> String {
     let normalize = |path: &str| {
-        if path.starts_with("http://") || path.starts_with("https://") {
-            path.to_string()
-        } else if path.starts_with('/') {
+        if path.starts_with("http://") || path.starts_with("https://") || path.starts_with('/') {
             path.to_string()
         } else {
             format!("/{}", path)
@@ -182,6 +205,15 @@ fn build_upstream_headers(
     copy_header_if_present(&mut headers, client_headers, "user-agent", "User-Agent");
     copy_header_if_present(&mut headers, client_headers, "x-request-id", "x-request-id");
 
+    // W3C Trace Context：启用链路导出时注入当前 span，否则原样透传客户端的上下文
+    match current_traceparent() {
+        Some(traceparent) => {
+            headers.insert("traceparent".to_string(), traceparent);
+        }
+        None => copy_header_if_present(&mut headers, client_headers, "traceparent", "traceparent"),
+    }
+    copy_header_if_present(&mut headers, client_headers, "tracestate", "tracestate");
+
     headers
 }
 
@@ -203,18 +235,14 @@ fn copy_header_if_present(
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
 
     fn base_config() -> ApiConfig {
         ApiConfig {
             base_url: "https://api.example.com".to_string(),
-            headers: HashMap::new(),
-            model_mapping: None,
-            endpoints: HashMap::new(),
-            port: 8000,
-            rate_limit: None,
-            stream_config: None,
+            ..ApiConfig::default()
         }
     }
 
@@ -441,6 +469,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn prepare_forward_plan_passes_through_trace_context_without_exporter() {
+        let config = base_config();
+        let mut headers = HashMap::new();
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        headers.insert("traceparent".to_string(), traceparent.to_string());
+        headers.insert("tracestate".to_string(), "vendor=1".to_string());
+        let request = ParsedRequest::new_for_tests("POST", "/v1/chat", "HTTP/1.1", headers, vec![]);
+        let plan = prepare_forward_plan("/v1/chat", &request, &config, "key", None);
+        assert_eq!(
+            plan.headers().get("traceparent"),
+            Some(&traceparent.to_string())
+        );
+        assert_eq!(
+            plan.headers().get("tracestate"),
+            Some(&"vendor=1".to_string())
+        );
+    }
+
+    #[test]
+    fn prepare_forward_plan_injects_current_span_with_exporter() {
+        use crate::otel::{OtlpConfig, OtlpExporter, OtlpLayer, TRACEPARENT_FIELD};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let config = base_config();
+        let mut headers = HashMap::new();
+        headers.insert(
+            "traceparent".to_string(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_string(),
+        );
+        let request = ParsedRequest::new_for_tests("POST", "/v1/chat", "HTTP/1.1", headers, vec![]);
+        let exporter = OtlpExporter::new(OtlpConfig {
+            endpoint: "http://127.0.0.1:9/v1/traces".to_string(),
+            service_name: "plan-test".to_string(),
+        });
+        let subscriber = tracing_subscriber::registry().with(OtlpLayer::new(exporter));
+
+        let injected = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("http_request", traceparent = tracing::field::Empty);
+            span.record(
+                TRACEPARENT_FIELD,
+                request.header("traceparent").unwrap_or_default(),
+            );
+            let _enter = span.enter();
+            let plan = prepare_forward_plan("/v1/chat", &request, &config, "key", None);
+            plan.headers().get("traceparent").cloned().unwrap()
+        });
+
+        assert!(injected.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(!injected.contains("00f067aa0ba902b7"));
+    }
+
     #[test]
     fn prepare_forward_plan_preserves_client_authorization() {
         let config = base_config();
@@ -473,10 +553,48 @@ mod tests {
             base_url: "https://api.test".to_string(),
             path: "/v1/chat".to_string(),
             stream_config: None,
+            retry: None,
+            timeouts: Timeouts::default(),
         };
         assert_eq!(plan.method(), "POST");
         assert_eq!(plan.base_url(), "https://api.test");
         assert_eq!(plan.path(), "/v1/chat");
         assert!(plan.stream_config().is_none());
+        assert!(plan.retry_policy().is_none());
+
+        let alternate = plan.rebased(&FallbackConfig {
+            base_url: "https://backup.test/".to_string(),
+            headers: HashMap::from([("x-api-key".to_string(), "backup".to_string())]),
+        });
+        assert_eq!(alternate.full_url(), "https://backup.test/v1/chat");
+        assert_eq!(alternate.headers()["x-api-key"], "backup");
+    }
+
+    #[test]
+    fn rebased_plan_drops_primary_credentials() {
+        let mut config = base_config();
+        config.headers = HashMap::from([
+            ("X-Api-Key".to_string(), "primary-key".to_string()),
+            ("OpenAI-Beta".to_string(), "assistants=v2".to_string()),
+        ]);
+        let plan = prepare_forward_plan(
+            "/v1/chat/completions",
+            &mock_parsed_request("/v1/chat/completions"),
+            &config,
+            "primary-default",
+            Some("application/json"),
+        );
+        assert_eq!(plan.headers()["Authorization"], "Bearer primary-default");
+
+        let alternate = plan.rebased(&FallbackConfig {
+            base_url: "https://backup.test".to_string(),
+            headers: HashMap::from([("x-api-key".to_string(), "backup".to_string())]),
+        });
+        let headers = alternate.headers();
+        assert!(headers.values().all(|value| !value.contains("primary")));
+        assert!(!has_header_case_insensitive(headers, "authorization"));
+        assert_eq!(headers["x-api-key"], "backup");
+        assert_eq!(headers["OpenAI-Beta"], "assistants=v2");
+        assert_eq!(headers["Content-Type"], "application/json");
     }
 }
@@ -1,6 +1,6 @@
 use crate::errors::{RouterError, RouterResult};
+use crate::listener::ClientStream;
 use smol::io::AsyncWriteExt;
-use smol::net::TcpStream;
 use std::io::Write as IoWrite;
 
 pub(super) fn build_error_response(status_code: u16, reason: &str, message: &str) -> Vec<u8> {
@@ -39,35 +39,99 @@ pub(super) fn build_error_response_with_headers(
     response
 }
 
+/// 返回错误对应的 HTTP 状态码，与 `map_error_to_response` 保持一致
+pub(super) fn error_status(err: &RouterError) -> u16 {
+    match err {
+        RouterError::BadRequest(_) | RouterError::Json(_) => 400,
+        RouterError::ConfigRead(_) | RouterError::ConfigParse(_) | RouterError::Io(_) => 500,
+        RouterError::Url(_)
+        | RouterError::Tls(_)
+        | RouterError::Upstream(_)
+        | RouterError::Proxy(_) => 502,
+        RouterError::CircuitOpen(_) | RouterError::Cancelled(_) => 503,
+        RouterError::ConnectTimeout(_)
+        | RouterError::TlsHandshakeTimeout(_)
+        | RouterError::FirstByteTimeout(_)
+        | RouterError::IdleTimeout(_)
+        | RouterError::TotalTimeout(_) => 504,
+    }
+}
+
 pub(super) fn map_error_to_response(err: &RouterError) -> Vec<u8> {
     match err {
         RouterError::BadRequest(msg) => build_error_response(400, "BAD REQUEST", msg),
         RouterError::ConfigRead(msg) | RouterError::ConfigParse(msg) => {
             build_error_response(500, "INTERNAL SERVER ERROR", msg)
         }
-        RouterError::Url(msg) | RouterError::Tls(msg) | RouterError::Upstream(msg) => {
-            build_error_response(502, "BAD GATEWAY", msg)
-        }
+        RouterError::Url(msg)
+        | RouterError::Tls(msg)
+        | RouterError::Upstream(msg)
+        | RouterError::Proxy(msg) => build_error_response(502, "BAD GATEWAY", msg),
         RouterError::Io(msg) => {
             build_error_response(500, "INTERNAL SERVER ERROR", &msg.to_string())
         }
         RouterError::Json(msg) => build_error_response(400, "BAD REQUEST", &msg.to_string()),
+        RouterError::CircuitOpen(msg) | RouterError::Cancelled(msg) => {
+            build_error_response(503, "SERVICE UNAVAILABLE", msg)
+        }
+        RouterError::ConnectTimeout(msg)
+        | RouterError::TlsHandshakeTimeout(msg)
+        | RouterError::FirstByteTimeout(msg)
+        | RouterError::IdleTimeout(msg)
+        | RouterError::TotalTimeout(msg) => build_error_response(504, "GATEWAY TIMEOUT", msg),
     }
 }
 
 pub(super) async fn write_success(
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
+    content_type: &str,
+    payload: &[u8],
+) -> RouterResult<()> {
+    write_response(stream, 200, "OK", content_type, payload).await
+}
+
+/// 写出 200 响应并附加额外的响应头
+pub(super) async fn write_success_with_headers(
+    stream: &mut ClientStream,
     content_type: &str,
+    extra_headers: &[(&str, &str)],
+    payload: &[u8],
+) -> RouterResult<()> {
+    write_response_with_headers(stream, 200, "OK", content_type, extra_headers, payload).await
+}
+
+pub(super) async fn write_response(
+    stream: &mut ClientStream,
+    status_code: u16,
+    reason: &str,
+    content_type: &str,
+    payload: &[u8],
+) -> RouterResult<()> {
+    write_response_with_headers(stream, status_code, reason, content_type, &[], payload).await
+}
+
+async fn write_response_with_headers(
+    stream: &mut ClientStream,
+    status_code: u16,
+    reason: &str,
+    content_type: &str,
+    extra_headers: &[(&str, &str)],
     payload: &[u8],
 ) -> RouterResult<()> {
     let mut response = Vec::with_capacity(128 + payload.len());
     write!(
         &mut response,
-        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n",
+        status_code,
+        reason,
         content_type,
         payload.len()
     )
     .expect("writing to Vec<u8> cannot fail");
+    for (key, value) in extra_headers {
+        write!(&mut response, "{}: {}\r\n", key, value).expect("writing to Vec<u8> cannot fail");
+    }
+    response.extend_from_slice(b"\r\n");
     response.extend_from_slice(payload);
     stream.write_all(&response).await?;
     stream.flush().await?;
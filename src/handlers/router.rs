@@ -3,55 +3,109 @@
 //! 负责处理入站的 TCP 连接，解析 HTTP 请求，进行速率限制检查，
 //! 并将请求路由到相应的处理函数
 
+use crate::access_log::{self, AccessLogEntry};
+use crate::capture;
+use crate::circuit_breaker::CIRCUIT_BREAKERS;
 use crate::config::load_api_config;
 use crate::error_tracking::capture_error_with_context;
+use crate::errors::RouterError;
+use crate::inflight::{RequestInfo, ACTIVE_REQUESTS};
+use crate::listener::{ClientStream, PeerAddr};
 use crate::metrics::{
-    gather_metrics, observe_request_latency, record_request, update_rate_limiter_buckets,
+    gather_metrics, observe_request_latency, observe_stream_ttfb, observe_upstream_latency,
+    record_estimated_cost, record_request, record_token_usage, update_rate_limiter_buckets,
     ConnectionGuard,
 };
-use crate::rate_limit::{resolve_rate_limit_settings, RateLimitDecision, RATE_LIMITER};
+use crate::otel::TRACEPARENT_FIELD;
+use crate::proxy;
+use crate::rate_limit::{resolve_rate_limit_settings, RATE_LIMITER};
+use crate::reload;
+use crate::response_cache;
+use crate::scheduler::{
+    resolve_priority_lane, resolve_shared_capacity, AdmissionDecision, ADMISSION_SCHEDULER,
+};
+use crate::shutdown;
+use crate::tls;
 use crate::tracing_util::{elapsed_ms, extract_provider, generate_request_id};
 use serde_json::json;
 use smol::io::{AsyncReadExt, AsyncWriteExt};
-use smol::net::TcpStream;
-use std::net::SocketAddr;
 use std::time::Instant;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, warn, Instrument, Span};
 
+use super::admin;
+use super::context::RequestContext;
 use super::parser::{
-    anonymize_key, extract_client_api_key, extract_content_length, parse_http_request,
-    resolve_default_api_key,
+    anonymize_key, extract_client_api_key, extract_content_length, key_alias, metrics_key_label,
+    parse_http_request, resolve_default_api_key,
+};
+use super::response::{
+    build_error_response_with_headers, error_status, map_error_to_response, write_response,
+    write_success,
 };
-use super::response::{build_error_response_with_headers, map_error_to_response, write_success};
 use super::routes::handle_route;
 
+/// 转发到上游的路由，可在配置的 `endpoints` 中覆盖；与 [`handle_request`] 中的路由匹配保持一致
+pub const PROXY_ROUTES: &[&str] = &[
+    "/v1/chat/completions",
+    "/v1/completions",
+    "/v1/embeddings",
+    "/v1/audio/transcriptions",
+    "/v1/audio/translations",
+    "/v1/messages",
+];
+
+/// 无法解析或未匹配任何路由的请求在指标中的路由标签
+const UNKNOWN_ROUTE_LABEL: &str = "/unknown";
+
 /// 处理单个 HTTP 请求
 ///
 /// 该函数是请求处理的入口点，执行以下步骤：
 /// 1. 生成请求 ID 并创建追踪 span
 /// 2. 读取并解析 HTTP 请求
-/// 3. 提取 API Key 并进行速率限制检查
+/// 3. 提取 API Key，进行速率限制检查与优先级准入调度
 /// 4. 加载配置并路由请求到相应的处理函数
 /// 5. 记录指标并返回响应
 ///
 /// # 参数
-/// - `stream`: TCP 连接流
+/// - `stream`: 客户端连接流
 /// - `addr`: 客户端地址
-pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
-    let request_id = generate_request_id();
-    let request_start = Instant::now();
-    let client_ip = addr.ip().to_string();
+pub async fn handle_request(stream: ClientStream, addr: PeerAddr) {
+    handle_connection(stream, addr, false).await
+}
 
+/// 处理独立管理监听端上的请求，只提供 `/admin` 下的路由
+pub async fn handle_admin_request(stream: ClientStream, addr: PeerAddr) {
+    handle_connection(stream, addr, true).await
+}
+
+async fn handle_connection(stream: ClientStream, addr: PeerAddr, admin_listener: bool) {
+    let request_id = generate_request_id();
     let span = tracing::info_span!(
         "http_request",
         request_id = %request_id,
-        client_ip = %client_ip,
+        client_ip = %addr.client_ip(),
         method = tracing::field::Empty,
         route = tracing::field::Empty,
+        priority = tracing::field::Empty,
+        traceparent = tracing::field::Empty,
         status_code = tracing::field::Empty,
         latency_ms = tracing::field::Empty,
     );
-    let _enter = span.enter();
+    // span 随 future 进出，避免跨 await 持有 guard 时被同一线程上的其他任务误用为父节点
+    serve_connection(stream, addr, admin_listener, request_id, span.clone())
+        .instrument(span)
+        .await
+}
+
+async fn serve_connection(
+    mut stream: ClientStream,
+    addr: PeerAddr,
+    admin_listener: bool,
+    request_id: String,
+    span: Span,
+) {
+    let request_start = Instant::now();
+    let client_ip = addr.client_ip();
 
     let _connection_guard = ConnectionGuard::new();
     let start_time = Instant::now();
@@ -93,6 +147,7 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
     if request_bytes.is_empty() {
         return;
     }
+    let mut access = AccessLogEntry::new(&request_id, &client_ip, request_bytes.len());
 
     let parsed_request = match parse_http_request(&request_bytes) {
         Ok(req) => req,
@@ -102,9 +157,14 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
             let response = map_error_to_response(&err);
             let _ = stream.write_all(&response).await;
             let _ = stream.flush().await;
+            access.record.method = "UNKNOWN".to_string();
+            access.record.route = "/unknown".to_string();
+            access.record.status = 400;
+            access.record.bytes_out = response_body_len(&response);
+            access.record.error = Some(err.class().to_string());
             let latency = start_time.elapsed().as_secs_f64();
-            observe_request_latency("/unknown", latency);
-            record_request("/unknown", "UNKNOWN", 400);
+            observe_request_latency(UNKNOWN_ROUTE_LABEL, "", "", 400, latency);
+            record_request(UNKNOWN_ROUTE_LABEL, "UNKNOWN", 400);
             return;
         }
     };
@@ -112,28 +172,67 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
     let route_path = parsed_request.route_path();
     span.record("method", parsed_request.method());
     span.record("route", route_path);
+    access.record.method = parsed_request.method().to_string();
+    access.record.route = route_path.to_string();
+    access.record.http_version = parsed_request.version().to_string();
+    if let Some(traceparent) = parsed_request.header("traceparent") {
+        span.record(TRACEPARENT_FIELD, traceparent);
+    }
 
     match (parsed_request.method(), route_path) {
+        (method, path) if admin_listener || admin::is_admin_path(path) => {
+            let response = if admin::is_admin_path(path) {
+                admin::respond(&parsed_request, admin_listener)
+            } else {
+                admin::AdminResponse::not_found()
+            };
+            let bytes = response.to_bytes();
+            let _ = stream.write_all(&bytes).await;
+            let _ = stream.flush().await;
+            access.record.status = response.status;
+            access.record.bytes_out = response_body_len(&bytes);
+            span.record("status_code", response.status);
+            span.record("latency_ms", elapsed_ms(request_start));
+            // 管理接口的路径含提供商名与请求 ID，指标中统一记为前缀，避免标签基数膨胀
+            let latency = start_time.elapsed().as_secs_f64();
+            observe_request_latency(admin::ADMIN_PREFIX, "", "", response.status, latency);
+            record_request(admin::ADMIN_PREFIX, metrics_method(method), response.status);
+        }
         ("GET", "/health") => {
             let snapshot = RATE_LIMITER.snapshot();
             update_rate_limiter_buckets(snapshot.active_buckets);
+            let scheduler_snapshot = ADMISSION_SCHEDULER.snapshot();
+            // 停机排空期间返回 503，便于负载均衡摘除本实例
+            let (status, status_code, reason) = if shutdown::is_draining() {
+                ("draining", 503, "SERVICE UNAVAILABLE")
+            } else {
+                ("ok", 200, "OK")
+            };
             let payload = json!({
-                "status": "ok",
+                "status": status,
                 "message": "Light API Router running",
                 "rateLimiter": {
                     "activeBuckets": snapshot.active_buckets,
                     "routes": snapshot.routes,
-                }
+                },
+                "scheduler": {
+                    "queued": scheduler_snapshot.queued,
+                },
+                "circuitBreakers": CIRCUIT_BREAKERS.snapshot(),
+                "config": reload::reload_status().to_json(),
             });
             if let Ok(body) = serde_json::to_vec(&payload) {
-                let _ = write_success(&mut stream, "application/json", &body).await;
+                let _ = write_response(&mut stream, status_code, reason, "application/json", &body)
+                    .await;
+                access.record.bytes_out = body.len() as u64;
             }
-            span.record("status_code", 200);
+            access.record.status = status_code;
+            span.record("status_code", status_code);
             span.record("latency_ms", elapsed_ms(request_start));
             info!("Health check completed");
             let latency = start_time.elapsed().as_secs_f64();
-            observe_request_latency("/health", latency);
-            record_request("/health", "GET", 200);
+            observe_request_latency("/health", "", "", status_code, latency);
+            record_request("/health", "GET", status_code);
         }
         ("GET", "/metrics") => {
             let snapshot = RATE_LIMITER.snapshot();
@@ -146,8 +245,10 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
                         metrics_output.as_bytes(),
                     )
                     .await;
+                    access.record.status = 200;
+                    access.record.bytes_out = metrics_output.len() as u64;
                     let latency = start_time.elapsed().as_secs_f64();
-                    observe_request_latency("/metrics", latency);
+                    observe_request_latency("/metrics", "", "", 200, latency);
                     record_request("/metrics", "GET", 200);
                 }
                 Err(e) => {
@@ -155,8 +256,10 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
                     let response = b"HTTP/1.1 500 INTERNAL SERVER ERROR\r\nContent-Length: 21\r\n\r\nFailed to get metrics";
                     let _ = stream.write_all(response).await;
                     let _ = stream.flush().await;
+                    access.record.status = 500;
+                    access.record.bytes_out = response_body_len(response);
                     let latency = start_time.elapsed().as_secs_f64();
-                    observe_request_latency("/metrics", latency);
+                    observe_request_latency("/metrics", "", "", 500, latency);
                     record_request("/metrics", "GET", 500);
                 }
             }
@@ -164,11 +267,13 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
         ("GET", "/v1/models") => {
             let body = b"{\"object\": \"list\", \"data\": [{\"id\": \"qwen3-coder-plus\", \"object\": \"model\", \"created\": 1677610602, \"owned_by\": \"organization-owner\"}]}";
             let _ = write_success(&mut stream, "application/json", body).await;
+            access.record.status = 200;
+            access.record.bytes_out = body.len() as u64;
             span.record("status_code", 200);
             span.record("latency_ms", elapsed_ms(request_start));
             info!("Models list retrieved");
             let latency = start_time.elapsed().as_secs_f64();
-            observe_request_latency("/v1/models", latency);
+            observe_request_latency("/v1/models", "", "", 200, latency);
             record_request("/v1/models", "GET", 200);
         }
         ("POST", "/v1/chat/completions")
@@ -189,19 +294,57 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
                     let response = map_error_to_response(&err);
                     let _ = stream.write_all(&response).await;
                     let _ = stream.flush().await;
+                    access.record.status = 500;
+                    access.record.bytes_out = response_body_len(&response);
+                    access.record.error = Some(err.class().to_string());
                     let latency = start_time.elapsed().as_secs_f64();
-                    observe_request_latency(route_path, latency);
+                    observe_request_latency(route_path, "", "", 500, latency);
                     record_request(route_path, "POST", 500);
                     return;
                 }
             };
+            access_log::configure(config.access_log.as_ref());
+            capture::configure(config.capture.as_ref());
+            response_cache::configure(config.response_cache.as_ref());
+            proxy::configure(config.proxy.as_ref());
+            tls::configure(config.tls.as_ref());
+            let provider = extract_provider(&config.base_url);
+            let mut context = RequestContext::new(provider);
             let default_api_key = resolve_default_api_key();
             let client_api_key = extract_client_api_key(parsed_request.headers(), &default_api_key);
+            let alias = key_alias(config.as_ref(), &client_api_key);
+            access.record.provider = Some(provider.to_string());
+            access.record.key = Some(alias.clone());
 
             if let Some(settings) = resolve_rate_limit_settings(route_path, config.as_ref()) {
-                match RATE_LIMITER.check(route_path, &client_api_key, &settings) {
-                    RateLimitDecision::Allowed => {}
-                    RateLimitDecision::Limited {
+                let lane = resolve_priority_lane(
+                    config.as_ref(),
+                    &client_api_key,
+                    parsed_request.headers(),
+                );
+                if let Some(lane) = &lane {
+                    span.record("priority", lane.name.as_str());
+                }
+                match ADMISSION_SCHEDULER
+                    .admit(
+                        &RATE_LIMITER,
+                        route_path,
+                        &client_api_key,
+                        &settings,
+                        resolve_shared_capacity(config.as_ref()).as_ref(),
+                        lane.as_ref(),
+                    )
+                    .await
+                {
+                    AdmissionDecision::Admitted { waited } => {
+                        if !waited.is_zero() {
+                            debug!(
+                                queued_ms = waited.as_secs_f64() * 1000.0,
+                                "Request admitted after queuing"
+                            );
+                        }
+                    }
+                    AdmissionDecision::Rejected {
                         retry_after_seconds,
                     } => {
                         span.record("status_code", 429);
@@ -219,56 +362,135 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
                         );
                         let _ = stream.write_all(&response).await;
                         let _ = stream.flush().await;
+                        access.record.status = 429;
+                        access.record.bytes_out = response_body_len(&response);
+                        access.record.error = Some("rate_limited".to_string());
                         let latency = start_time.elapsed().as_secs_f64();
-                        observe_request_latency(route_path, latency);
+                        observe_request_latency(route_path, provider, "", 429, latency);
                         record_request(route_path, "POST", 429);
                         return;
                     }
                 }
             }
 
-            let result = handle_route(
-                route_path,
-                &parsed_request,
-                &mut stream,
-                config.as_ref(),
-                &default_api_key,
+            let active = ACTIVE_REQUESTS.register(
                 &request_id,
+                RequestInfo {
+                    method: parsed_request.method().to_string(),
+                    route: route_path.to_string(),
+                    client_ip: client_ip.clone(),
+                    key: alias.clone(),
+                    provider: provider.to_string(),
+                },
+            );
+            // 被管理接口取消时丢弃转发任务，上游连接随之关闭
+            let result = smol::future::or(
+                handle_route(
+                    route_path,
+                    &parsed_request,
+                    &mut stream,
+                    config.as_ref(),
+                    &default_api_key,
+                    &request_id,
+                    &mut context,
+                ),
+                async {
+                    active.cancelled().await;
+                    Err(RouterError::Cancelled(
+                        "Request cancelled by administrator".to_string(),
+                    ))
+                },
             )
             .await;
+            drop(active);
+
+            let status = match &result {
+                Ok(()) => 200,
+                Err(err) => error_status(err),
+            };
+            let model_label = context.metrics_model(config.as_ref());
+            // 上游指标按上游返回的状态码记录；未收到上游响应（建连失败、超时等）时记为返回给客户端的错误状态码
+            let upstream_status = context.upstream_status.unwrap_or(status);
+            if let Some(upstream_latency) = context.upstream_latency {
+                observe_upstream_latency(
+                    route_path,
+                    &context.provider,
+                    model_label,
+                    upstream_status,
+                    upstream_latency.as_secs_f64(),
+                );
+            }
+            if let Some(first_byte) = context.first_byte {
+                observe_stream_ttfb(
+                    route_path,
+                    &context.provider,
+                    model_label,
+                    upstream_status,
+                    first_byte.as_secs_f64(),
+                );
+            }
+            fill_access_record(&mut access, &context, status);
+            if let Some(usage) = context.usage {
+                let key_label = metrics_key_label(config.as_ref(), &client_api_key);
+                record_token_usage(
+                    &key_label,
+                    &context.provider,
+                    model_label,
+                    usage.prompt_tokens,
+                    usage.completion_tokens,
+                );
+                if let Some(price) = config.pricing.get(&context.model) {
+                    record_estimated_cost(
+                        &key_label,
+                        &context.provider,
+                        model_label,
+                        usage.cost(price),
+                    );
+                }
+            }
 
             match result {
                 Ok(()) => {
                     span.record("status_code", 200);
                     span.record("latency_ms", elapsed_ms(request_start));
-                    info!(
-                        provider = crate::tracing_util::extract_provider(&config.base_url),
-                        "Request completed successfully"
-                    );
+                    info!(provider = %context.provider, "Request completed successfully");
                     let latency = start_time.elapsed().as_secs_f64();
-                    observe_request_latency(route_path, latency);
-                    record_request(route_path, "POST", 200);
+                    observe_request_latency(
+                        route_path,
+                        &context.provider,
+                        model_label,
+                        status,
+                        latency,
+                    );
+                    record_request(route_path, "POST", status);
                 }
                 Err(err) => {
-                    span.record("status_code", 500);
+                    span.record("status_code", status);
                     span.record("latency_ms", elapsed_ms(request_start));
 
                     // Capture error with Sentry with full context
-                    let provider = extract_provider(&config.base_url);
                     capture_error_with_context(
                         &err,
                         &request_id,
-                        &client_api_key,
+                        &alias,
                         route_path,
-                        Some(&provider),
+                        Some(&context.provider),
                     );
 
                     let response = map_error_to_response(&err);
                     let _ = stream.write_all(&response).await;
                     let _ = stream.flush().await;
+                    access.record.bytes_out = response_body_len(&response);
+                    access.record.error = Some(err.class().to_string());
                     let latency = start_time.elapsed().as_secs_f64();
-                    observe_request_latency(route_path, latency);
-                    record_request(route_path, "POST", 500);
+                    observe_request_latency(
+                        route_path,
+                        &context.provider,
+                        model_label,
+                        status,
+                        latency,
+                    );
+                    record_request(route_path, "POST", status);
                 }
             }
         }
@@ -279,9 +501,55 @@ pub async fn handle_request(mut stream: TcpStream, addr: SocketAddr) {
             let response = b"HTTP/1.1 404 NOT FOUND\r\nContent-Length: 9\r\n\r\nNot Found";
             let _ = stream.write_all(response).await;
             let _ = stream.flush().await;
+            access.record.status = 404;
+            access.record.bytes_out = response_body_len(response);
+            access.record.error = Some("not_found".to_string());
             let latency = start_time.elapsed().as_secs_f64();
-            observe_request_latency(route_path, latency);
-            record_request(route_path, parsed_request.method(), 404);
+            // 未匹配的路径由客户端决定，指标中统一记为 `/unknown`，避免标签基数膨胀
+            observe_request_latency(UNKNOWN_ROUTE_LABEL, "", "", 404, latency);
+            record_request(
+                UNKNOWN_ROUTE_LABEL,
+                metrics_method(parsed_request.method()),
+                404,
+            );
         }
     }
 }
+
+/// 指标中的请求方法：非标准方法统一记为 `OTHER`
+fn metrics_method(method: &str) -> &str {
+    match method {
+        "GET" | "HEAD" | "POST" | "PUT" | "DELETE" | "PATCH" | "OPTIONS" => method,
+        _ => "OTHER",
+    }
+}
+
+/// 将请求上下文中的观测数据写入访问日志记录
+fn fill_access_record(access: &mut AccessLogEntry, context: &RequestContext, status: u16) {
+    let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+    let record = &mut access.record;
+    record.status = status;
+    record.upstream_status = context.upstream_status;
+    record.provider = non_empty(&context.provider);
+    record.requested_model = non_empty(&context.requested_model);
+    record.model = non_empty(&context.model);
+    record.upstream_latency_ms = context
+        .upstream_latency
+        .map(|latency| latency.as_secs_f64() * 1000.0);
+    record.ttfb_ms = context
+        .first_byte
+        .map(|latency| latency.as_secs_f64() * 1000.0);
+    record.prompt_tokens = context.usage.map(|usage| usage.prompt_tokens);
+    record.completion_tokens = context.usage.map(|usage| usage.completion_tokens);
+    record.bytes_out = context.bytes_out;
+    record.cache_hit = context.cache_hit;
+}
+
+/// 计算完整 HTTP 响应中正文部分的字节数
+fn response_body_len(response: &[u8]) -> u64 {
+    response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|pos| response.len() - pos - 4)
+        .unwrap_or(response.len()) as u64
+}
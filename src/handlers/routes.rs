@@ -1,32 +1,84 @@
+use crate::capture::{
+    duration_ms, CaptureSession, CapturedPlan, CapturedRequest, CapturedResponse, ChunkRecorder,
+};
+use crate::circuit_breaker::{is_failure_status, CallOutcome, CIRCUIT_BREAKERS};
 use crate::config::ApiConfig;
-use crate::error_tracking::track_upstream_failure;
+use crate::error_tracking::{track_upstream_failure, track_upstream_success};
 use crate::errors::{RouterError, RouterResult};
-use crate::http_client::{handle_streaming_request, send_http_request};
-use crate::metrics::record_upstream_error;
+use crate::hedge::{resolve_hedge_policy, run_hedged, ROUTE_LATENCIES};
+use crate::http_client::{
+    handle_streaming_request, send_upstream_request, write_sse_response, UpstreamResponse,
+};
+use crate::listener::ClientStream;
+use crate::metrics::{record_response_cache_lookup, record_upstream_error};
 use crate::models::{
-    AnthropicMessagesRequest, ChatCompletionRequest, CompletionRequest, EmbeddingRequest,
+    AnthropicMessagesRequest, ChatCompletionRequest, CompletionRequest, EmbeddingData,
+    EmbeddingRequest, EmbeddingResponse, Usage,
+};
+use crate::response_cache::{
+    self, embedding_inputs, split_tokens, CacheLookup, CachedEmbedding, CachedResponse,
+    EmbeddingCache, StreamBuffer, CACHE_HEADER,
 };
+use crate::retry::RetryPolicy;
+use crate::timeout::Timeouts;
 use crate::tracing_util::{elapsed_ms, extract_provider};
+use crate::usage::{extract_usage, request_stream_usage, strip_usage_events, UsageTracker};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use smol::net::TcpStream;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::time::Instant;
-use tracing::debug;
+use tracing::{debug, Instrument, Span};
 
+use super::context::RequestContext;
 use super::parser::ParsedRequest;
-use super::plan::{map_model_name, prepare_forward_plan};
+use super::plan::{map_model_name, prepare_forward_plan, ForwardPlan};
 use super::response;
 
 pub(super) async fn handle_route(
     route_path: &str,
     request: &ParsedRequest,
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
     config: &ApiConfig,
     default_api_key: &str,
     request_id: &str,
+    context: &mut RequestContext,
 ) -> RouterResult<()> {
+    let selection = match CIRCUIT_BREAKERS.select(route_path, config) {
+        Ok(selection) => selection,
+        Err(err) => {
+            record_upstream_error(err.class());
+            return Err(err);
+        }
+    };
+    if selection.fallback {
+        debug!(provider = %selection.provider, "Primary upstream circuit open, using fallback");
+    }
+    context.provider = selection.provider.clone();
+    let config: &ApiConfig = &selection.config;
+    let upstream_start = Instant::now();
+
+    // span 随转发 future 进出，不跨 await 持有 guard
+    let span = if matches!(
+        route_path,
+        "/v1/audio/transcriptions" | "/v1/audio/translations"
+    ) {
+        tracing::info_span!(
+            "upstream_multipart_request",
+            request_id = %request_id,
+            provider = %selection.provider,
+            upstream_latency_ms = tracing::field::Empty,
+            attempts = tracing::field::Empty,
+        )
+    } else {
+        tracing::info_span!(
+            "upstream_request",
+            request_id = %request_id,
+            provider = %selection.provider,
+            upstream_latency_ms = tracing::field::Empty,
+            attempts = tracing::field::Empty,
+        )
+    };
     let result = match route_path {
         "/v1/chat/completions" => {
             forward_json_route::<ChatCompletionRequest>(
@@ -36,9 +88,11 @@ pub(super) async fn handle_route(
                 config,
                 default_api_key,
                 request_id,
+                context,
                 adjust_chat_request,
                 Some(chat_should_stream),
             )
+            .instrument(span)
             .await
         }
         "/v1/completions" => {
@@ -49,22 +103,24 @@ pub(super) async fn handle_route(
                 config,
                 default_api_key,
                 request_id,
+                context,
                 adjust_completion_request,
                 Some(completion_should_stream),
             )
+            .instrument(span)
             .await
         }
         "/v1/embeddings" => {
-            forward_json_route::<EmbeddingRequest>(
+            forward_embeddings(
                 route_path,
                 request,
                 stream,
                 config,
                 default_api_key,
                 request_id,
-                adjust_embedding_request,
-                None,
+                context,
             )
+            .instrument(span)
             .await
         }
         "/v1/audio/transcriptions" | "/v1/audio/translations" => {
@@ -75,7 +131,9 @@ pub(super) async fn handle_route(
                 config,
                 default_api_key,
                 request_id,
+                context,
             )
+            .instrument(span)
             .await
         }
         "/v1/messages" => {
@@ -86,69 +144,79 @@ pub(super) async fn handle_route(
                 config,
                 default_api_key,
                 request_id,
+                context,
                 adjust_anthropic_request,
                 Some(anthropic_should_stream),
             )
+            .instrument(span)
             .await
         }
         _ => Err(RouterError::BadRequest("Unsupported route".to_string())),
     };
 
-    if let Err(ref err) = result {
-        let error_type = match err {
-            RouterError::Url(_) => "url_error",
-            RouterError::Io(_) => "io_error",
-            RouterError::ConfigRead(_) => "config_read_error",
-            RouterError::ConfigParse(_) => "config_parse_error",
-            RouterError::Json(_) => "json_error",
-            RouterError::Upstream(_) => "upstream_error",
-            RouterError::Tls(_) => "tls_error",
-            RouterError::BadRequest(_) => "bad_request",
-        };
-        record_upstream_error(error_type);
+    // 缓存命中没有访问上游，不计入熔断与告警统计，只释放可能占用的半开探测名额
+    if context.cache_hit {
+        CIRCUIT_BREAKERS.record(&selection, route_path, CallOutcome::Ignored, None);
+        return result;
+    }
 
-        // Track upstream failures for alerting
-        if matches!(err, RouterError::Upstream(_) | RouterError::Tls(_)) {
-            let provider = extract_provider(&config.base_url);
-            track_upstream_failure(&provider, err);
+    let outcome = CallOutcome::from_result(&result, context.upstream_status);
+    CIRCUIT_BREAKERS.record(
+        &selection,
+        route_path,
+        outcome,
+        Some(upstream_start.elapsed()),
+    );
+
+    let provider = selection.provider.as_str();
+    match (&result, context.upstream_status) {
+        (Ok(()), Some(status)) if outcome == CallOutcome::Failure => {
+            let err = RouterError::Upstream(format!("Upstream returned status {}", status));
+            track_upstream_failure(provider, &err, config.alerting.as_ref());
+        }
+        (Ok(()), Some(status)) if (200..300).contains(&status) => {
+            track_upstream_success(provider, config.alerting.as_ref())
+        }
+        (Ok(()), _) => {}
+        (Err(err), _) => {
+            record_upstream_error(err.class());
+
+            // Track upstream failures for alerting
+            if matches!(err, RouterError::Upstream(_) | RouterError::Tls(_)) || err.is_timeout() {
+                track_upstream_failure(provider, err, config.alerting.as_ref());
+            }
         }
     }
 
     result
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn forward_json_route<T>(
     route_path: &str,
     request: &ParsedRequest,
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
     config: &ApiConfig,
     default_api_key: &str,
     request_id: &str,
+    context: &mut RequestContext,
     adjust: fn(&ApiConfig, &mut T),
     stream_decider: Option<fn(&T) -> bool>,
 ) -> RouterResult<()>
 where
-    T: DeserializeOwned + Serialize,
+    T: DeserializeOwned + Serialize + UpstreamPayload,
 {
     let upstream_start = Instant::now();
     let provider = extract_provider(&config.base_url);
 
-    let span = tracing::debug_span!(
-        "upstream_request",
-        request_id = %request_id,
-        provider = %provider,
-        upstream_latency_ms = tracing::field::Empty,
-    );
-    let _enter = span.enter();
-
     if !request.has_body() {
         return Err(RouterError::BadRequest("Empty request body".to_string()));
     }
 
     let mut payload: T = serde_json::from_slice(request.body())?;
+    context.requested_model = payload.model().to_string();
     adjust(config, &mut payload);
-    let body_bytes = serde_json::to_vec(&payload)?;
-
+    context.model = payload.model().to_string();
     let plan = prepare_forward_plan(
         route_path,
         request,
@@ -156,6 +224,44 @@ where
         default_api_key,
         Some("application/json"),
     );
+    let strip_usage =
+        plan.stream_config().is_some_and(|c| c.include_usage) && payload.request_stream_usage();
+    let body_bytes = serde_json::to_vec(&payload)?;
+
+    let cache =
+        (payload.is_deterministic() && response_cache::should_cache(route_path)).then(|| {
+            CacheLookup::new(
+                provider,
+                route_path,
+                &body_bytes,
+                request.header("cache-control"),
+            )
+        });
+    if let Some(cached) = cache.as_ref().and_then(CacheLookup::cached) {
+        record_response_cache_lookup(route_path, true);
+        context.cache_hit = true;
+        context.bytes_out = cached.body.len() as u64;
+        debug!(
+            response_size = cached.body.len(),
+            "Serving response from cache"
+        );
+        let headers = [(CACHE_HEADER, "hit")];
+        return if cached.stream {
+            write_sse_response(stream, &headers, &cached.body).await
+        } else {
+            response::write_success_with_headers(stream, "application/json", &headers, &cached.body)
+                .await
+        };
+    }
+    let cache_headers: &[(&str, &str)] = match &cache {
+        Some(_) => {
+            record_response_cache_lookup(route_path, false);
+            &[(CACHE_HEADER, "miss")]
+        }
+        None => &[],
+    };
+
+    let capture = start_capture(route_path, request, request_id, &plan, &body_bytes);
 
     let should_stream = stream_decider
         .map(|decider| decider(&payload))
@@ -163,56 +269,283 @@ where
 
     if should_stream {
         debug!("Starting streaming request to upstream");
-        handle_streaming_request(
+        let mut usage_tracker = UsageTracker::new();
+        let mut recorder = ChunkRecorder::new(&mut usage_tracker, capture.is_some());
+        let mut buffer = StreamBuffer::new(&mut recorder, cache.is_some());
+        let stream_result = handle_streaming_request(
             stream,
             plan.base_url(),
             plan.method(),
             plan.path(),
             plan.headers(),
             &body_bytes,
+            cache_headers,
             plan.stream_config(),
+            strip_usage,
+            &mut buffer,
+            plan.retry_policy(),
+            plan.timeouts(),
         )
-        .await?;
-        span.record("upstream_latency_ms", elapsed_ms(upstream_start));
+        .await;
+        let streamed = buffer.into_response();
+        let chunks = recorder.into_chunks();
+        context.upstream_latency = Some(upstream_start.elapsed());
+        context.usage = usage_tracker.finish();
+        if let Some(session) = capture {
+            session.finish(CapturedResponse {
+                stream: true,
+                chunks,
+                latency_ms: duration_ms(upstream_start.elapsed()),
+                error: stream_result.as_ref().err().map(ToString::to_string),
+                ..CapturedResponse::default()
+            });
+        }
+        let summary = stream_result?;
+        context.upstream_status = summary.status;
+        context.first_byte = summary.first_byte;
+        context.bytes_out = summary.bytes;
+        if let (Some(cache), Some(mut response)) = (&cache, streamed) {
+            if strip_usage {
+                response.body = strip_usage_events(&response.body);
+            }
+            cache.store(response);
+        }
+        Span::current().record("upstream_latency_ms", elapsed_ms(upstream_start));
         debug!(
             upstream_latency_ms = elapsed_ms(upstream_start),
             "Streaming request completed"
         );
     } else {
-        let full_url = plan.full_url();
-        let response_body =
-            forward_to_upstream(&full_url, plan.method(), plan.headers(), Some(&body_bytes))
-                .await?;
-        span.record("upstream_latency_ms", elapsed_ms(upstream_start));
+        let upstream_result = forward_buffered(route_path, config, &plan, &body_bytes).await;
+        context.upstream_latency = Some(upstream_start.elapsed());
+        finish_buffered_capture(capture, &upstream_result, upstream_start);
+        let upstream = upstream_result?;
+        context.upstream_status = Some(upstream.status);
+        let success = (200..300).contains(&upstream.status);
+        let response_body = upstream.body;
+        context.usage = extract_usage(&response_body);
+        context.bytes_out = response_body.len() as u64;
+        Span::current().record("upstream_latency_ms", elapsed_ms(upstream_start));
         debug!(
             upstream_latency_ms = elapsed_ms(upstream_start),
             response_size = response_body.len(),
             "Upstream request completed"
         );
-        response::write_success(stream, "application/json", &response_body).await?;
+        response::write_success_with_headers(
+            stream,
+            "application/json",
+            cache_headers,
+            &response_body,
+        )
+        .await?;
+        // 只缓存上游 2xx 响应，错误响应不能在之后作为命中结果返回
+        if let (Some(cache), true) = (&cache, success) {
+            cache.store(CachedResponse {
+                stream: false,
+                body: response_body,
+            });
+        }
     }
 
     Ok(())
 }
 
-async fn forward_multipart_route(
+/// 转发 embeddings 请求
+///
+/// 开启逐条缓存时按单条输入查找缓存，只把未命中的输入发往上游，再按原始顺序拼装响应；
+/// 无法逐条缓存的请求按普通 JSON 路由转发
+#[allow(clippy::too_many_arguments)]
+async fn forward_embeddings(
     route_path: &str,
     request: &ParsedRequest,
-    stream: &mut TcpStream,
+    stream: &mut ClientStream,
     config: &ApiConfig,
     default_api_key: &str,
     request_id: &str,
+    context: &mut RequestContext,
 ) -> RouterResult<()> {
-    let upstream_start = Instant::now();
     let provider = extract_provider(&config.base_url);
+    let batch = response_cache::caches_embedding_items()
+        .then(|| serde_json::from_slice::<EmbeddingRequest>(request.body()).ok())
+        .flatten()
+        .and_then(|mut payload| {
+            let requested_model = payload.model.clone();
+            adjust_embedding_request(config, &mut payload);
+            let cache = EmbeddingCache::new(provider, &payload, request.header("cache-control"))?;
+            let inputs = embedding_inputs(&payload.input)?;
+            Some((requested_model, payload, cache, inputs))
+        });
+    let Some((requested_model, payload, cache, inputs)) = batch else {
+        return forward_json_route::<EmbeddingRequest>(
+            route_path,
+            request,
+            stream,
+            config,
+            default_api_key,
+            request_id,
+            context,
+            adjust_embedding_request,
+            None,
+        )
+        .await;
+    };
+    let upstream_start = Instant::now();
+
+    context.requested_model = requested_model;
+    context.model = payload.model.clone();
+
+    let cached: Vec<Option<CachedEmbedding>> = inputs.iter().map(|text| cache.get(text)).collect();
+    for item in &cached {
+        record_response_cache_lookup(route_path, item.is_some());
+    }
+    let missing: Vec<usize> = (0..inputs.len()).filter(|&i| cached[i].is_none()).collect();
+
+    if missing.is_empty() {
+        let items = cached.into_iter().flatten().collect::<Vec<_>>();
+        let body = serde_json::to_vec(&assemble_embeddings(&payload.model, items))?;
+        context.cache_hit = true;
+        context.bytes_out = body.len() as u64;
+        debug!(inputs = inputs.len(), "Serving embeddings from cache");
+        return response::write_success_with_headers(
+            stream,
+            "application/json",
+            &[(CACHE_HEADER, "hit")],
+            &body,
+        )
+        .await;
+    }
 
-    let span = tracing::debug_span!(
-        "upstream_multipart_request",
-        request_id = %request_id,
-        provider = %provider,
-        upstream_latency_ms = tracing::field::Empty,
+    // 只把未命中的输入发往上游，全部未命中时保持原始请求体
+    let partial = missing.len() < inputs.len();
+    let mut payload = payload;
+    if partial {
+        payload.input = missing
+            .iter()
+            .map(|&i| serde_json::Value::String(inputs[i].clone()))
+            .collect();
+    }
+    let body_bytes = serde_json::to_vec(&payload)?;
+    let plan = prepare_forward_plan(
+        route_path,
+        request,
+        config,
+        default_api_key,
+        Some("application/json"),
     );
-    let _enter = span.enter();
+    let capture = start_capture(route_path, request, request_id, &plan, &body_bytes);
+    let upstream_result = forward_buffered(route_path, config, &plan, &body_bytes).await;
+    context.upstream_latency = Some(upstream_start.elapsed());
+    finish_buffered_capture(capture, &upstream_result, upstream_start);
+    let upstream = upstream_result?;
+    context.upstream_status = Some(upstream.status);
+    let success = (200..300).contains(&upstream.status);
+    let response_body = upstream.body;
+    context.usage = extract_usage(&response_body);
+    Span::current().record("upstream_latency_ms", elapsed_ms(upstream_start));
+    debug!(
+        upstream_latency_ms = elapsed_ms(upstream_start),
+        inputs = inputs.len(),
+        upstream_inputs = missing.len(),
+        "Embeddings upstream request completed"
+    );
+
+    let upstream = success
+        .then(|| serde_json::from_slice::<EmbeddingResponse>(&response_body).ok())
+        .flatten()
+        .filter(|response| {
+            response.data.len() == missing.len()
+                && response
+                    .data
+                    .iter()
+                    .all(|data| (data.index as usize) < missing.len())
+        });
+    let body = match upstream {
+        Some(upstream) => {
+            let texts: Vec<&str> = missing.iter().map(|&i| inputs[i].as_str()).collect();
+            let shares = split_tokens(
+                upstream
+                    .usage
+                    .as_ref()
+                    .map_or(0, |usage| usage.prompt_tokens),
+                &texts,
+            );
+            let mut items = cached;
+            for data in &upstream.data {
+                let position = data.index as usize;
+                let item = CachedEmbedding {
+                    embedding: data.embedding.clone(),
+                    prompt_tokens: shares[position],
+                };
+                cache.put(texts[position], &item);
+                items[missing[position]] = Some(item);
+            }
+            match (partial, items.into_iter().collect::<Option<Vec<_>>>()) {
+                (true, Some(items)) => {
+                    let model = upstream.model.as_deref().unwrap_or(&payload.model);
+                    serde_json::to_vec(&assemble_embeddings(model, items))?
+                }
+                (true, None) => {
+                    return Err(RouterError::Upstream(
+                        "Embedding response does not cover every input".to_string(),
+                    ))
+                }
+                (false, _) => response_body,
+            }
+        }
+        // 上游错误原样返回；成功却与输入对不上的响应无法与缓存拼装
+        None if partial && success => {
+            return Err(RouterError::Upstream(
+                "Embedding response does not match the requested inputs".to_string(),
+            ));
+        }
+        None => response_body,
+    };
+    context.bytes_out = body.len() as u64;
+    let status = if partial { "partial" } else { "miss" };
+    response::write_success_with_headers(
+        stream,
+        "application/json",
+        &[(CACHE_HEADER, status)],
+        &body,
+    )
+    .await
+}
+
+/// 按原始输入顺序拼装 embeddings 响应
+///
+/// 上游用量已按文本长度分摊到本次返回的条目上，`usage` 为全部条目的 token 数之和
+fn assemble_embeddings(model: &str, items: Vec<CachedEmbedding>) -> EmbeddingResponse {
+    let prompt_tokens = items.iter().map(|item| item.prompt_tokens).sum();
+    EmbeddingResponse {
+        object: "list".to_string(),
+        data: items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| EmbeddingData {
+                object: "embedding".to_string(),
+                embedding: item.embedding,
+                index: index as u32,
+            })
+            .collect(),
+        model: Some(model.to_string()),
+        usage: Some(Usage {
+            prompt_tokens,
+            completion_tokens: 0,
+            total_tokens: prompt_tokens,
+        }),
+    }
+}
+
+async fn forward_multipart_route(
+    route_path: &str,
+    request: &ParsedRequest,
+    stream: &mut ClientStream,
+    config: &ApiConfig,
+    default_api_key: &str,
+    request_id: &str,
+    context: &mut RequestContext,
+) -> RouterResult<()> {
+    let upstream_start = Instant::now();
     if !request.has_body() {
         return Err(RouterError::BadRequest("Empty request body".to_string()));
     }
@@ -229,16 +562,23 @@ async fn forward_multipart_route(
         Some(content_type),
     );
 
+    if let Some(model) = extract_model_from_multipart(request.body()) {
+        context.requested_model = model;
+    }
     let body = rewrite_multipart_model(request.body(), config);
-    let full_url = plan.full_url();
-    let response_body = forward_to_upstream(
-        &full_url,
-        plan.method(),
-        plan.headers(),
-        Some(body.as_ref()),
-    )
-    .await?;
-    span.record("upstream_latency_ms", elapsed_ms(upstream_start));
+    if let Some(model) = extract_model_from_multipart(&body) {
+        context.model = model;
+    }
+    let capture = start_capture(route_path, request, request_id, &plan, &body);
+    let upstream_result = forward_buffered(route_path, config, &plan, &body).await;
+    context.upstream_latency = Some(upstream_start.elapsed());
+    finish_buffered_capture(capture, &upstream_result, upstream_start);
+    let upstream = upstream_result?;
+    context.upstream_status = Some(upstream.status);
+    let response_body = upstream.body;
+    context.usage = extract_usage(&response_body);
+    context.bytes_out = response_body.len() as u64;
+    Span::current().record("upstream_latency_ms", elapsed_ms(upstream_start));
     debug!(
         upstream_latency_ms = elapsed_ms(upstream_start),
         response_size = response_body.len(),
@@ -247,6 +587,181 @@ async fn forward_multipart_route(
     response::write_success(stream, "application/json", &response_body).await
 }
 
+/// 转发非流式请求，按对冲策略在主请求过慢时向同一或备用上游发送对冲请求
+///
+/// 上游返回 5xx 或 429 的响应不会在对冲中胜出，另一方仍可能返回可用的结果
+async fn forward_buffered(
+    route_path: &str,
+    config: &ApiConfig,
+    plan: &ForwardPlan,
+    body: &[u8],
+) -> RouterResult<UpstreamResponse> {
+    let started = Instant::now();
+    let full_url = plan.full_url();
+    let primary = forward_to_upstream(
+        &full_url,
+        plan.method(),
+        plan.headers(),
+        Some(body),
+        plan.retry_policy(),
+        plan.timeouts(),
+    );
+
+    let hedge = resolve_hedge_policy(route_path, config).and_then(|policy| {
+        let delay = policy.trigger_delay(route_path, &ROUTE_LATENCIES)?;
+        let (hedge_plan, alternate) = match policy.alternate() {
+            // 停用或熔断打开的备用上游不接收对冲请求
+            Some(alternate) => (
+                plan.rebased(alternate),
+                Some(CIRCUIT_BREAKERS.select_alternate(route_path, config, alternate)?),
+            ),
+            None => (plan.clone(), None),
+        };
+        Some((delay, hedge_plan, alternate))
+    });
+    let result = match &hedge {
+        Some((delay, hedge_plan, alternate)) => {
+            let hedge_url = hedge_plan.full_url();
+            let usable: fn(&UpstreamResponse) -> bool =
+                |response| !is_failure_status(response.status);
+            run_hedged(route_path, *delay, usable, primary, || async move {
+                let hedge_start = Instant::now();
+                let result = forward_to_upstream(
+                    &hedge_url,
+                    hedge_plan.method(),
+                    hedge_plan.headers(),
+                    Some(body),
+                    hedge_plan.retry_policy(),
+                    hedge_plan.timeouts(),
+                )
+                .await;
+                // 备用上游的结果计入其自身的熔断器，主上游的结果由调用方记录
+                if let Some(selection) = alternate {
+                    let status = result.as_ref().ok().map(|response| response.status);
+                    CIRCUIT_BREAKERS.record(
+                        selection,
+                        route_path,
+                        CallOutcome::from_result(&result, status),
+                        Some(hedge_start.elapsed()),
+                    );
+                }
+                result
+            })
+            .await
+        }
+        None => primary.await,
+    };
+
+    if matches!(&result, Ok(response) if response.is_success()) {
+        ROUTE_LATENCIES.record(route_path, started.elapsed());
+    }
+    result
+}
+
+/// 按录制配置为当前请求创建录制会话
+fn start_capture(
+    route_path: &str,
+    request: &ParsedRequest,
+    request_id: &str,
+    plan: &ForwardPlan,
+    body: &[u8],
+) -> Option<CaptureSession> {
+    CaptureSession::start(
+        request_id,
+        route_path,
+        || {
+            CapturedRequest::new(
+                request.method(),
+                request.target(),
+                request.headers(),
+                request.body(),
+            )
+        },
+        || CapturedPlan::new(plan.method(), &plan.full_url(), plan.headers(), body),
+    )
+}
+
+/// 写出非流式请求的录制记录
+fn finish_buffered_capture(
+    capture: Option<CaptureSession>,
+    result: &RouterResult<UpstreamResponse>,
+    upstream_start: Instant,
+) {
+    if let Some(session) = capture {
+        session.finish(CapturedResponse {
+            body: result
+                .as_ref()
+                .ok()
+                .map(|response| String::from_utf8_lossy(&response.body).into_owned()),
+            latency_ms: duration_ms(upstream_start.elapsed()),
+            error: result.as_ref().err().map(ToString::to_string),
+            ..CapturedResponse::default()
+        });
+    }
+}
+
+/// 可转发到上游的 JSON 请求体
+trait UpstreamPayload {
+    /// 请求体中的模型名称
+    fn model(&self) -> &str;
+
+    /// 相同请求是否总是得到相同响应，只有确定性请求才使用响应缓存
+    fn is_deterministic(&self) -> bool;
+
+    /// 为流式请求开启上游用量事件，返回是否由路由器开启（见 [`request_stream_usage`]）
+    fn request_stream_usage(&mut self) -> bool {
+        false
+    }
+}
+
+impl UpstreamPayload for ChatCompletionRequest {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn is_deterministic(&self) -> bool {
+        self.temperature == Some(0.0)
+    }
+
+    fn request_stream_usage(&mut self) -> bool {
+        request_stream_usage(self.stream, &mut self.stream_options)
+    }
+}
+
+impl UpstreamPayload for CompletionRequest {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn is_deterministic(&self) -> bool {
+        self.temperature == Some(0.0)
+    }
+
+    fn request_stream_usage(&mut self) -> bool {
+        request_stream_usage(self.stream, &mut self.stream_options)
+    }
+}
+
+impl UpstreamPayload for EmbeddingRequest {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn is_deterministic(&self) -> bool {
+        true
+    }
+}
+
+impl UpstreamPayload for AnthropicMessagesRequest {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn is_deterministic(&self) -> bool {
+        self.temperature == Some(0.0)
+    }
+}
+
 fn adjust_chat_request(config: &ApiConfig, payload: &mut ChatCompletionRequest) {
     payload.model = map_model_name(config, &payload.model);
 }
@@ -333,17 +848,29 @@ async fn forward_to_upstream(
     method: &str,
     headers: &HashMap<String, String>,
     body: Option<&[u8]>,
-) -> RouterResult<Vec<u8>> {
+    retry: Option<&RetryPolicy>,
+    timeouts: &Timeouts,
+) -> RouterResult<UpstreamResponse> {
     #[cfg(test)]
     {
         if let Some(lock) = HTTP_CLIENT_OVERRIDE.get() {
             if let Some(ref handler) = *lock.read().unwrap() {
-                return (handler)(url, method, headers, body);
+                // 模拟响应以状态行开头时按完整响应解析，否则视为 200 响应的正文
+                return (handler)(url, method, headers, body).map(|response| {
+                    if response.starts_with(b"HTTP/") {
+                        UpstreamResponse::parse(response)
+                    } else {
+                        UpstreamResponse {
+                            status: 200,
+                            body: response,
+                        }
+                    }
+                });
             }
         }
     }
 
-    send_http_request(url, method, headers, body).await
+    send_upstream_request(url, method, headers, body, retry, timeouts).await
 }
 
 #[cfg(test)]
@@ -1,9 +1,15 @@
+use super::context::RequestContext;
 use super::parser::{extract_content_length, ParsedRequest};
 use super::plan::compute_upstream_path;
 use super::response::build_error_response_with_headers;
 use super::routes::{handle_route, with_mock_http_client};
-use crate::config::{ApiConfig, EndpointConfig};
-use crate::models::{AnthropicMessagesRequest, ChatCompletionRequest, EmbeddingRequest};
+use crate::config::{ApiConfig, EndpointConfig, ResponseCacheConfig};
+use crate::errors::RouterError;
+use crate::listener::ClientStream;
+use crate::models::{
+    AnthropicMessagesRequest, ChatCompletionRequest, EmbeddingRequest, EmbeddingResponse,
+};
+use crate::response_cache;
 use serde_json::json;
 use serial_test::serial;
 use smol::io::AsyncReadExt;
@@ -11,12 +17,12 @@ use smol::net::{TcpListener, TcpStream};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-async fn tcp_pair() -> std::io::Result<(TcpStream, TcpStream)> {
+async fn tcp_pair() -> std::io::Result<(ClientStream, TcpStream)> {
     let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
     let addr = listener.local_addr()?;
     let client = TcpStream::connect(addr).await?;
     let (server, _) = listener.accept().await?;
-    Ok((server, client))
+    Ok((ClientStream::from(server), client))
 }
 
 #[test]
@@ -141,6 +147,7 @@ fn chat_completions_respects_endpoint_overrides() {
                     &config,
                     "default-key",
                     "test-req-id",
+                    &mut RequestContext::default(),
                 )
                 .await
                 .unwrap();
@@ -219,6 +226,7 @@ fn embeddings_route_forwards_with_mocked_upstream() {
                 );
 
                 let (mut server_stream, mut client_stream) = tcp_pair().await.unwrap();
+                let mut context = RequestContext::new("test");
                 handle_route(
                     "/v1/embeddings",
                     &parsed_request,
@@ -226,10 +234,13 @@ fn embeddings_route_forwards_with_mocked_upstream() {
                     &config,
                     "unused-key",
                     "test-req-id",
+                    &mut context,
                 )
                 .await
                 .unwrap();
                 drop(server_stream);
+                assert_eq!(context.model, "qwen3");
+                assert!(context.upstream_latency.is_some());
 
                 let mut buf = vec![0u8; 512];
                 let n = client_stream.read(&mut buf).await.unwrap();
@@ -320,6 +331,7 @@ fn audio_route_rewrites_model_and_forwards() {
                     &config,
                     "fallback-key",
                     "test-req-id",
+                    &mut RequestContext::default(),
                 )
                 .await
                 .unwrap();
@@ -415,6 +427,7 @@ fn anthropic_messages_route_forwards_with_model_mapping() {
                     &config,
                     "default-key",
                     "test-req-id",
+                    &mut RequestContext::default(),
                 )
                 .await
                 .unwrap();
@@ -493,6 +506,7 @@ fn anthropic_messages_route_with_system_message() {
                     &config,
                     "test-key",
                     "test-req-id",
+                    &mut RequestContext::default(),
                 )
                 .await
                 .unwrap();
@@ -510,3 +524,403 @@ fn anthropic_messages_route_with_system_message() {
     assert!(response.contains("\"id\":\"msg_456\""));
     assert!(*send_called.lock().unwrap());
 }
+
+#[test]
+#[serial]
+fn embeddings_are_served_from_response_cache() {
+    let calls = Arc::new(Mutex::new(0));
+    let calls_clone = Arc::clone(&calls);
+
+    let responses = with_mock_http_client(
+        Box::new(move |_url, _method, _headers, _body| {
+            *calls_clone.lock().unwrap() += 1;
+            Ok(br#"{"object":"list","data":[]}"#.to_vec())
+        }),
+        || {
+            smol::block_on(async {
+                response_cache::configure(Some(&ResponseCacheConfig::default()));
+                let config: ApiConfig =
+                    serde_json::from_str(r#"{"baseUrl": "https://api.test", "port": 8000}"#)
+                        .unwrap();
+                let mut responses = Vec::new();
+                for (input, cache_control) in [
+                    ("cached", None),
+                    ("cached", None),
+                    ("cached", Some("no-cache")),
+                    ("other", Some("no-store")),
+                    ("other", None),
+                ] {
+                    let mut headers = HashMap::new();
+                    headers.insert("content-type".to_string(), "application/json".to_string());
+                    if let Some(value) = cache_control {
+                        headers.insert("cache-control".to_string(), value.to_string());
+                    }
+                    let body = json!({"model": "text-embedding-3-small", "input": input});
+                    let parsed_request = ParsedRequest::new_for_tests(
+                        "POST",
+                        "/v1/embeddings",
+                        "HTTP/1.1",
+                        headers,
+                        serde_json::to_vec(&body).unwrap(),
+                    );
+
+                    let (mut server_stream, mut client_stream) = tcp_pair().await.unwrap();
+                    let mut context = RequestContext::default();
+                    handle_route(
+                        "/v1/embeddings",
+                        &parsed_request,
+                        &mut server_stream,
+                        &config,
+                        "default-key",
+                        "test-req-id",
+                        &mut context,
+                    )
+                    .await
+                    .unwrap();
+                    drop(server_stream);
+
+                    let mut response = String::new();
+                    client_stream.read_to_string(&mut response).await.unwrap();
+                    assert!(response.contains("\"data\":[]"));
+                    responses.push((response, context.cache_hit));
+                }
+                response_cache::configure(None);
+                responses
+            })
+        },
+    );
+
+    let hits: Vec<bool> = responses.iter().map(|(_, hit)| *hit).collect();
+    assert_eq!(hits, [false, true, false, false, false]);
+    assert!(responses[0].0.contains("\r\nx-router-cache: miss\r\n"));
+    assert!(responses[1].0.contains("\r\nx-router-cache: hit\r\n"));
+    // no-store 的响应不写入缓存，之后同样的请求仍然访问上游
+    assert_eq!(*calls.lock().unwrap(), 4);
+}
+
+#[test]
+#[serial]
+fn embedding_batches_only_send_uncached_inputs_upstream() {
+    let upstream_inputs = Arc::new(Mutex::new(Vec::new()));
+    let upstream_inputs_clone = Arc::clone(&upstream_inputs);
+
+    let responses = with_mock_http_client(
+        Box::new(move |_url, _method, _headers, body| {
+            let payload: EmbeddingRequest = serde_json::from_slice(body.expect("body")).unwrap();
+            assert_eq!(payload.model, "text-embedding-3-small");
+            let texts: Vec<String> = serde_json::from_value(payload.input).unwrap();
+            let data: Vec<_> = texts
+                .iter()
+                .enumerate()
+                .map(|(index, text)| {
+                    json!({
+                        "object": "embedding",
+                        "embedding": [text.len() as f32, 0.5],
+                        "index": index,
+                    })
+                })
+                .collect();
+            let tokens = 2 * texts.len();
+            upstream_inputs_clone.lock().unwrap().push(texts);
+            Ok(serde_json::to_vec(&json!({
+                "object": "list",
+                "data": data,
+                "model": "text-embedding-3-small",
+                "usage": {"prompt_tokens": tokens, "total_tokens": tokens},
+            }))
+            .unwrap())
+        }),
+        || {
+            smol::block_on(async {
+                response_cache::configure(Some(&ResponseCacheConfig {
+                    embedding_items: true,
+                    ..ResponseCacheConfig::default()
+                }));
+                let config: ApiConfig = serde_json::from_str(
+                    r#"{
+                        "baseUrl": "https://api.test",
+                        "modelMapping": {"embedder": "text-embedding-3-small"},
+                        "port": 8000
+                    }"#,
+                )
+                .unwrap();
+                let mut responses = Vec::new();
+                for input in [json!(["a", "bb"]), json!(["bb", "cccc", "a"]), json!("a")] {
+                    let mut headers = HashMap::new();
+                    headers.insert("content-type".to_string(), "application/json".to_string());
+                    let body = json!({"model": "embedder", "input": input});
+                    let parsed_request = ParsedRequest::new_for_tests(
+                        "POST",
+                        "/v1/embeddings",
+                        "HTTP/1.1",
+                        headers,
+                        serde_json::to_vec(&body).unwrap(),
+                    );
+
+                    let (mut server_stream, mut client_stream) = tcp_pair().await.unwrap();
+                    let mut context = RequestContext::default();
+                    handle_route(
+                        "/v1/embeddings",
+                        &parsed_request,
+                        &mut server_stream,
+                        &config,
+                        "default-key",
+                        "test-req-id",
+                        &mut context,
+                    )
+                    .await
+                    .unwrap();
+                    drop(server_stream);
+                    assert_eq!(context.requested_model, "embedder");
+
+                    let mut response = String::new();
+                    client_stream.read_to_string(&mut response).await.unwrap();
+                    responses.push(response);
+                }
+                response_cache::configure(None);
+                responses
+            })
+        },
+    );
+
+    assert_eq!(
+        *upstream_inputs.lock().unwrap(),
+        vec![
+            vec!["a".to_string(), "bb".to_string()],
+            vec!["cccc".to_string()],
+        ]
+    );
+    assert!(responses[0].contains("\r\nx-router-cache: miss\r\n"));
+    assert!(responses[1].contains("\r\nx-router-cache: partial\r\n"));
+    assert!(responses[2].contains("\r\nx-router-cache: hit\r\n"));
+
+    let body = |response: &str| -> EmbeddingResponse {
+        serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap()
+    };
+    let partial = body(&responses[1]);
+    let embeddings: Vec<(u32, f32)> = partial
+        .data
+        .iter()
+        .map(|data| (data.index, data.embedding[0]))
+        .collect();
+    assert_eq!(embeddings, [(0, 2.0), (1, 4.0), (2, 1.0)]);
+    // "a" 与 "bb" 按文本长度分摊首次请求的 4 个 token，"cccc" 独占第二次请求的 2 个 token
+    let usage = partial.usage.unwrap();
+    assert_eq!((usage.prompt_tokens, usage.total_tokens), (6, 6));
+
+    let hit = body(&responses[2]);
+    assert_eq!(hit.data.len(), 1);
+    assert_eq!(hit.data[0].embedding, [1.0, 0.5]);
+    assert_eq!(hit.model.as_deref(), Some("text-embedding-3-small"));
+}
+
+#[test]
+#[serial]
+fn upstream_error_responses_are_not_cached() {
+    let calls = Arc::new(Mutex::new(0));
+    let calls_clone = Arc::clone(&calls);
+
+    let hits = with_mock_http_client(
+        Box::new(move |_url, _method, _headers, _body| {
+            *calls_clone.lock().unwrap() += 1;
+            Ok(b"HTTP/1.1 429 Too Many Requests\r\nContent-Length: 22\r\n\r\n{\"message\":\"slow down\"}".to_vec())
+        }),
+        || {
+            smol::block_on(async {
+                response_cache::configure(Some(&ResponseCacheConfig::default()));
+                let config: ApiConfig =
+                    serde_json::from_str(r#"{"baseUrl": "https://uncached.test", "port": 8000}"#)
+                        .unwrap();
+                let mut hits = Vec::new();
+                for _ in 0..2 {
+                    let mut headers = HashMap::new();
+                    headers.insert("content-type".to_string(), "application/json".to_string());
+                    let body = json!({
+                        "model": "gpt-4o",
+                        "messages": [{"role": "user", "content": "hi"}],
+                        "temperature": 0
+                    });
+                    let parsed_request = ParsedRequest::new_for_tests(
+                        "POST",
+                        "/v1/chat/completions",
+                        "HTTP/1.1",
+                        headers,
+                        serde_json::to_vec(&body).unwrap(),
+                    );
+
+                    let (mut server_stream, _client_stream) = tcp_pair().await.unwrap();
+                    let mut context = RequestContext::default();
+                    handle_route(
+                        "/v1/chat/completions",
+                        &parsed_request,
+                        &mut server_stream,
+                        &config,
+                        "default-key",
+                        "test-req-id",
+                        &mut context,
+                    )
+                    .await
+                    .unwrap();
+                    hits.push(context.cache_hit);
+                }
+                response_cache::configure(None);
+                hits
+            })
+        },
+    );
+
+    assert_eq!(hits, [false, false]);
+    assert_eq!(*calls.lock().unwrap(), 2);
+}
+
+#[test]
+#[serial]
+fn upstream_error_status_opens_circuit_breaker() {
+    let calls = Arc::new(Mutex::new(0));
+    let calls_clone = Arc::clone(&calls);
+
+    let results = with_mock_http_client(
+        Box::new(move |_url, _method, _headers, _body| {
+            *calls_clone.lock().unwrap() += 1;
+            Ok(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 26\r\n\r\n{\"error\":\"overloaded now\"}".to_vec())
+        }),
+        || {
+            smol::block_on(async {
+                let config: ApiConfig = serde_json::from_str(
+                    r#"{
+                        "baseUrl": "https://breaker-status.test",
+                        "port": 8000,
+                        "circuitBreaker": {"minimumRequests": 2, "openSecs": 60}
+                    }"#,
+                )
+                .unwrap();
+                let mut results = Vec::new();
+                for _ in 0..3 {
+                    let mut headers = HashMap::new();
+                    headers.insert("content-type".to_string(), "application/json".to_string());
+                    let body = json!({"model": "text-embedding-3-small", "input": "text"});
+                    let parsed_request = ParsedRequest::new_for_tests(
+                        "POST",
+                        "/v1/embeddings",
+                        "HTTP/1.1",
+                        headers,
+                        serde_json::to_vec(&body).unwrap(),
+                    );
+
+                    let (mut server_stream, _client_stream) = tcp_pair().await.unwrap();
+                    let mut context = RequestContext::default();
+                    let result = handle_route(
+                        "/v1/embeddings",
+                        &parsed_request,
+                        &mut server_stream,
+                        &config,
+                        "default-key",
+                        "test-req-id",
+                        &mut context,
+                    )
+                    .await;
+                    results.push((result, context.upstream_status));
+                }
+                results
+            })
+        },
+    );
+
+    assert!(results[0].0.is_ok());
+    assert_eq!(results[0].1, Some(503));
+    assert!(results[1].0.is_ok());
+    // 两次 503 之后熔断打开，第三次请求不再访问上游
+    assert!(matches!(results[2].0, Err(RouterError::CircuitOpen(_))));
+    assert_eq!(*calls.lock().unwrap(), 2);
+}
+
+/// 启动只响应一次的本地上游，返回基础 URL 与收到的请求
+fn spawn_sse_upstream(response: &'static [u8]) -> (String, std::thread::JoinHandle<String>) {
+    use std::io::{Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = extract_content_length(&text[..end]).unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    break;
+                }
+            }
+        }
+        stream.write_all(response).unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    (format!("http://{}", addr), handle)
+}
+
+fn stream_usage_request(include_usage: bool) -> (String, String, RequestContext) {
+    let (base_url, upstream) = spawn_sse_upstream(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\ndata: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"hi\"}}]}\n\ndata: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":1}}\n\ndata: [DONE]\n\n",
+    );
+    let config: ApiConfig = serde_json::from_value(json!({
+        "baseUrl": base_url,
+        "streamConfig": {"includeUsage": include_usage}
+    }))
+    .unwrap();
+
+    let (received, context) = smol::block_on(async {
+        let mut headers = HashMap::new();
+        headers.insert("content-type".to_string(), "application/json".to_string());
+        let body = json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}],
+            "stream": true
+        });
+        let parsed_request = ParsedRequest::new_for_tests(
+            "POST",
+            "/v1/chat/completions",
+            "HTTP/1.1",
+            headers,
+            serde_json::to_vec(&body).unwrap(),
+        );
+
+        let (mut server_stream, mut client_stream) = tcp_pair().await.unwrap();
+        let mut context = RequestContext::default();
+        handle_route(
+            "/v1/chat/completions",
+            &parsed_request,
+            &mut server_stream,
+            &config,
+            "default-key",
+            "test-req-id",
+            &mut context,
+        )
+        .await
+        .unwrap();
+        drop(server_stream);
+        let mut received = String::new();
+        client_stream.read_to_string(&mut received).await.unwrap();
+        (received, context)
+    });
+    (upstream.join().unwrap(), received, context)
+}
+
+#[test]
+fn stream_usage_is_not_requested_by_default() {
+    let (request, received, _) = stream_usage_request(false);
+    assert!(!request.contains("stream_options"));
+    assert!(received.contains("\"usage\""));
+}
+
+#[test]
+fn injected_stream_usage_is_tracked_but_not_forwarded() {
+    let (request, received, context) = stream_usage_request(true);
+    assert!(request.contains(r#""stream_options":{"include_usage":true}"#));
+    assert!(received.contains("\"content\":\"hi\""));
+    assert!(received.contains("data: [DONE]"));
+    assert!(!received.contains("\"usage\""));
+    let usage = context.usage.unwrap();
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (5, 1));
+}
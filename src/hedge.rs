@@ -0,0 +1,283 @@
+//! 请求对冲模块
+//!
+//! 非流式请求在配置的延迟（固定值或路由近期延迟的分位数）内没有响应时，再向同一上游或备用上游
+//! 发送一份相同的请求，采用先成功的结果。落败的请求被直接丢弃，其连接随之关闭而不会放回连接池
+
+use crate::config::{ApiConfig, FallbackConfig, HedgeConfig};
+use crate::errors::RouterResult;
+use crate::metrics::record_hedged_request;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Duration;
+use tracing::debug;
+
+/// 每条路由保留的最近延迟样本数
+const MAX_LATENCY_SAMPLES: usize = 256;
+
+/// 全局路由延迟样本，用于按分位数计算对冲延迟
+pub static ROUTE_LATENCIES: Lazy<LatencyTracker> = Lazy::new(LatencyTracker::default);
+
+/// 按路由记录最近的上游延迟
+#[derive(Debug, Default)]
+pub struct LatencyTracker {
+    samples: DashMap<String, VecDeque<Duration>>,
+}
+
+impl LatencyTracker {
+    /// 记录一次成功请求的上游延迟
+    pub fn record(&self, route: &str, latency: Duration) {
+        let mut samples = self.samples.entry(route.to_string()).or_default();
+        if samples.len() >= MAX_LATENCY_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    /// 返回路由延迟的分位数，样本数少于 `min_samples` 时返回 None
+    pub fn percentile(&self, route: &str, percentile: f64, min_samples: usize) -> Option<Duration> {
+        let samples = self.samples.get(route)?;
+        if samples.is_empty() || samples.len() < min_samples {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+        drop(samples);
+        sorted.sort_unstable();
+        let rank = (percentile.clamp(0.0, 1.0) * (sorted.len() - 1) as f64).round() as usize;
+        Some(sorted[rank])
+    }
+}
+
+/// 生效的对冲策略
+#[derive(Debug, Clone, PartialEq)]
+pub struct HedgePolicy {
+    config: HedgeConfig,
+}
+
+impl HedgePolicy {
+    pub fn new(config: HedgeConfig) -> Self {
+        Self { config }
+    }
+
+    /// 对冲请求的目标，None 表示同一上游
+    pub fn alternate(&self) -> Option<&FallbackConfig> {
+        self.config.alternate.as_ref()
+    }
+
+    /// 计算路由当前的对冲延迟：分位数优先，样本不足时使用固定延迟
+    pub fn trigger_delay(&self, route: &str, latencies: &LatencyTracker) -> Option<Duration> {
+        self.config
+            .percentile
+            .and_then(|percentile| {
+                latencies.percentile(route, percentile, self.config.min_samples.max(1))
+            })
+            .or_else(|| self.config.delay_ms.map(Duration::from_millis))
+    }
+}
+
+/// 解析路由生效的对冲策略：端点配置优先，其次为全局配置
+pub fn resolve_hedge_policy(route: &str, config: &ApiConfig) -> Option<HedgePolicy> {
+    config
+        .endpoints
+        .get(route)
+        .and_then(|endpoint| endpoint.hedge.clone())
+        .or_else(|| config.hedge.clone())
+        .map(HedgePolicy::new)
+}
+
+/// 对冲执行主请求：`delay` 内主请求未完成时发出对冲请求，返回先成功的结果
+///
+/// `usable` 判断响应能否作为结果（如上游状态码不是 5xx 或 429）。先完成的一方失败或响应不可用时
+/// 继续等待另一方，两方都不可用时优先返回上游的响应而不是请求错误；返回时未完成的请求 future 被丢弃
+pub async fn run_hedged<T, P, H, F>(
+    route: &str,
+    delay: Duration,
+    usable: fn(&T) -> bool,
+    primary: P,
+    hedge: H,
+) -> RouterResult<T>
+where
+    P: Future<Output = RouterResult<T>>,
+    H: FnOnce() -> F,
+    F: Future<Output = RouterResult<T>>,
+{
+    let mut primary = Box::pin(primary);
+    let early = smol::future::or(async { Some(primary.as_mut().await) }, async {
+        smol::Timer::after(delay).await;
+        None
+    })
+    .await;
+    if let Some(result) = early {
+        return result;
+    }
+
+    debug!(
+        route = %route,
+        delay_ms = delay.as_millis() as u64,
+        "No upstream response within hedge delay, sending hedged request"
+    );
+    let mut hedge = Box::pin(hedge());
+    let first = smol::future::or(async { (false, primary.as_mut().await) }, async {
+        (true, hedge.as_mut().await)
+    })
+    .await;
+
+    let (hedge_won, result) = match first {
+        (from_hedge, Ok(value)) if usable(&value) => (from_hedge, Ok(value)),
+        (from_hedge, first_result) => {
+            let other = if from_hedge {
+                primary.await
+            } else {
+                hedge.await
+            };
+            match (other, first_result) {
+                (Err(_), Ok(value)) => (from_hedge, Ok(value)),
+                (other, _) => (!from_hedge, other),
+            }
+        }
+    };
+    record_hedged_request(route, if hedge_won { "hedge" } else { "primary" });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EndpointConfig;
+    use crate::errors::RouterError;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn accept_all(_: &&'static str) -> bool {
+        true
+    }
+
+    async fn respond_after(
+        ms: u64,
+        result: RouterResult<&'static str>,
+    ) -> RouterResult<&'static str> {
+        smol::Timer::after(Duration::from_millis(ms)).await;
+        result
+    }
+
+    #[test]
+    fn fast_primary_does_not_hedge() {
+        let hedged = AtomicBool::new(false);
+        let result = smol::block_on(run_hedged(
+            "/test",
+            Duration::from_millis(200),
+            accept_all,
+            respond_after(1, Ok("primary")),
+            || {
+                hedged.store(true, Ordering::SeqCst);
+                respond_after(1, Ok("hedge"))
+            },
+        ));
+        assert_eq!(result.unwrap(), "primary");
+        assert!(!hedged.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn slow_primary_loses_to_hedge() {
+        let result = smol::block_on(run_hedged(
+            "/test",
+            Duration::from_millis(10),
+            accept_all,
+            respond_after(5_000, Ok("primary")),
+            || respond_after(1, Ok("hedge")),
+        ));
+        assert_eq!(result.unwrap(), "hedge");
+    }
+
+    #[test]
+    fn failed_hedge_waits_for_primary() {
+        let result = smol::block_on(run_hedged(
+            "/test",
+            Duration::from_millis(10),
+            accept_all,
+            respond_after(50, Ok("primary")),
+            || respond_after(1, Err(RouterError::Upstream("boom".to_string()))),
+        ));
+        assert_eq!(result.unwrap(), "primary");
+    }
+
+    #[test]
+    fn unusable_response_waits_for_the_other_side() {
+        let usable: fn(&&str) -> bool = |body| !body.starts_with("error");
+        let result = smol::block_on(run_hedged(
+            "/test",
+            Duration::from_millis(10),
+            usable,
+            respond_after(50, Ok("primary")),
+            || respond_after(1, Ok("error: overloaded")),
+        ));
+        assert_eq!(result.unwrap(), "primary");
+
+        // 另一方也失败时返回先到的错误响应，而不是连接错误
+        let result = smol::block_on(run_hedged(
+            "/test",
+            Duration::from_millis(10),
+            usable,
+            respond_after(50, Err(RouterError::Upstream("boom".to_string()))),
+            || respond_after(1, Ok("error: overloaded")),
+        ));
+        assert_eq!(result.unwrap(), "error: overloaded");
+    }
+
+    #[test]
+    fn trigger_delay_prefers_percentile_with_enough_samples() {
+        let tracker = LatencyTracker::default();
+        let policy = HedgePolicy::new(HedgeConfig {
+            delay_ms: Some(500),
+            percentile: Some(0.9),
+            min_samples: 10,
+            alternate: None,
+        });
+        assert_eq!(
+            policy.trigger_delay("/v1/embeddings", &tracker),
+            Some(Duration::from_millis(500))
+        );
+        for ms in 1..=10 {
+            tracker.record("/v1/embeddings", Duration::from_millis(ms * 10));
+        }
+        assert_eq!(
+            policy.trigger_delay("/v1/embeddings", &tracker),
+            Some(Duration::from_millis(90))
+        );
+
+        let percentile_only = HedgePolicy::new(HedgeConfig {
+            percentile: Some(0.5),
+            ..HedgeConfig::default()
+        });
+        assert_eq!(percentile_only.trigger_delay("/other", &tracker), None);
+    }
+
+    #[test]
+    fn endpoint_policy_overrides_global() {
+        let mut config = ApiConfig {
+            hedge: Some(HedgeConfig {
+                delay_ms: Some(100),
+                ..HedgeConfig::default()
+            }),
+            ..ApiConfig::default()
+        };
+        config.endpoints.insert(
+            "/v1/embeddings".to_string(),
+            EndpointConfig {
+                hedge: Some(HedgeConfig {
+                    delay_ms: Some(20),
+                    ..HedgeConfig::default()
+                }),
+                ..Default::default()
+            },
+        );
+        let tracker = LatencyTracker::default();
+        let delay = |route| {
+            resolve_hedge_policy(route, &config)
+                .and_then(|policy| policy.trigger_delay(route, &tracker))
+        };
+        assert_eq!(delay("/v1/embeddings"), Some(Duration::from_millis(20)));
+        assert_eq!(delay("/v1/completions"), Some(Duration::from_millis(100)));
+        assert!(resolve_hedge_policy("/v1/embeddings", &ApiConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,200 @@
+//! 进行中请求登记模块
+//!
+//! 登记正在转发的代理请求（路由、客户端、Key 别名、提供商与开始时间），供管理接口列出与取消。
+//! 取消时丢弃请求的转发任务，上游连接随之关闭，客户端收到 503
+
+use crate::access_log::format_rfc3339;
+use async_channel::{Receiver, Sender};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::time::{Instant, SystemTime};
+
+/// 全局进行中请求登记表
+pub static ACTIVE_REQUESTS: Lazy<RequestRegistry> = Lazy::new(RequestRegistry::default);
+
+/// 登记请求时提供的描述信息
+#[derive(Debug, Clone, Default)]
+pub struct RequestInfo {
+    pub method: String,
+    pub route: String,
+    pub client_ip: String,
+    /// Key 别名或脱敏后的 Key
+    pub key: String,
+    pub provider: String,
+}
+
+/// 进行中请求的快照
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestSnapshot {
+    pub id: String,
+    pub method: String,
+    pub route: String,
+    pub client_ip: String,
+    pub key: String,
+    pub provider: String,
+    /// 开始时间（RFC 3339）
+    pub started_at: String,
+    /// 已进行的毫秒数
+    pub age_ms: u64,
+    /// 是否已请求取消、正在结束
+    pub cancelled: bool,
+}
+
+struct Entry {
+    info: RequestInfo,
+    started: Instant,
+    started_at: SystemTime,
+    /// 关闭发送端即通知请求取消
+    cancel: Sender<()>,
+}
+
+/// 进行中请求登记表
+#[derive(Default)]
+pub struct RequestRegistry {
+    requests: DashMap<String, Entry>,
+}
+
+impl RequestRegistry {
+    /// 登记请求，返回的守卫释放时注销
+    pub fn register(&self, id: &str, info: RequestInfo) -> ActiveRequestGuard<'_> {
+        let (cancel, cancelled) = async_channel::bounded(1);
+        self.requests.insert(
+            id.to_string(),
+            Entry {
+                info,
+                started: Instant::now(),
+                started_at: SystemTime::now(),
+                cancel,
+            },
+        );
+        ActiveRequestGuard {
+            registry: self,
+            id: id.to_string(),
+            cancelled,
+        }
+    }
+
+    /// 取消请求，请求不存在时返回 false
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.requests.get(id) {
+            Some(entry) => {
+                entry.cancel.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 进行中的请求，最早开始的在前
+    pub fn snapshot(&self) -> Vec<RequestSnapshot> {
+        let mut entries: Vec<(Instant, RequestSnapshot)> = self
+            .requests
+            .iter()
+            .map(|entry| {
+                let (id, request) = entry.pair();
+                let snapshot = RequestSnapshot {
+                    id: id.clone(),
+                    method: request.info.method.clone(),
+                    route: request.info.route.clone(),
+                    client_ip: request.info.client_ip.clone(),
+                    key: request.info.key.clone(),
+                    provider: request.info.provider.clone(),
+                    started_at: format_rfc3339(request.started_at),
+                    age_ms: request.started.elapsed().as_millis() as u64,
+                    cancelled: request.cancel.is_closed(),
+                };
+                (request.started, snapshot)
+            })
+            .collect();
+        entries.sort_by_key(|(started, _)| *started);
+        entries.into_iter().map(|(_, snapshot)| snapshot).collect()
+    }
+
+    /// 进行中的请求数
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// 是否没有进行中的请求
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+}
+
+/// 进行中请求守卫
+pub struct ActiveRequestGuard<'a> {
+    registry: &'a RequestRegistry,
+    id: String,
+    cancelled: Receiver<()>,
+}
+
+impl ActiveRequestGuard<'_> {
+    /// 等待请求被取消；未被取消时一直挂起
+    pub async fn cancelled(&self) {
+        let _ = self.cancelled.recv().await;
+    }
+}
+
+impl Drop for ActiveRequestGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.requests.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn info(route: &str) -> RequestInfo {
+        RequestInfo {
+            method: "POST".to_string(),
+            route: route.to_string(),
+            provider: "openai".to_string(),
+            ..RequestInfo::default()
+        }
+    }
+
+    #[test]
+    fn lists_requests_oldest_first_and_unregisters_on_drop() {
+        let registry = RequestRegistry::default();
+        let first = registry.register("req-1", info("/v1/chat/completions"));
+        std::thread::sleep(Duration::from_millis(5));
+        let second = registry.register("req-2", info("/v1/embeddings"));
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].id, "req-1");
+        assert!(snapshot[0].age_ms >= snapshot[1].age_ms);
+        assert_eq!(snapshot[1].route, "/v1/embeddings");
+
+        drop(first);
+        assert_eq!(registry.len(), 1);
+        drop(second);
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn cancel_interrupts_the_request_future() {
+        let registry = RequestRegistry::default();
+        let guard = registry.register("req-1", info("/v1/chat/completions"));
+        assert!(!registry.cancel("missing"));
+
+        let result = smol::block_on(async {
+            let work = async {
+                smol::Timer::after(Duration::from_secs(30)).await;
+                "finished"
+            };
+            let cancelled = async {
+                guard.cancelled().await;
+                "cancelled"
+            };
+            assert!(registry.cancel("req-1"));
+            smol::future::or(work, cancelled).await
+        });
+        assert_eq!(result, "cancelled");
+        assert!(registry.snapshot()[0].cancelled);
+    }
+}
@@ -0,0 +1,436 @@
+//! 配置格式与分层合并模块
+//!
+//! 按扩展名识别配置格式：`.json`、`.yaml` / `.yml`（支持锚点、别名与 `<<` 合并键）、`.toml`。
+//! 生效配置由以下各层依次合并（后者优先）：
+//! 1. 基础配置文件
+//! 2. 环境覆盖层：设置 `API_ROUTER_ENV=<环境>` 时，基础文件旁的 `<文件名>.<环境>.<扩展名>`（存在时）
+//! 3. `API_ROUTER_CONFIG_OVERLAYS` 列出的覆盖文件（按路径列表分隔符分隔，按顺序合并，必须存在）
+//! 4. `API_ROUTER__<字段>__<子字段>` 形式的环境变量
+//!
+//! 对象（包括 `endpoints`、`keys` 等映射）逐键深度合并，数组与标量整体替换，覆盖层中的 `null` 删除该字段。
+//! 各层顶层以 `x-` 开头的字段被忽略，可用来定义 YAML 锚点
+
+use crate::errors::{RouterError, RouterResult};
+use crate::validation::{config_schema, resolve_schema};
+use serde_json::{Map, Value};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// 支持的配置文件扩展名，按查找顺序排列
+pub const CONFIG_EXTENSIONS: &[&str] = &["json", "yaml", "yml", "toml"];
+
+/// 选择环境覆盖层的环境变量
+pub const ENV_OVERLAY_VAR: &str = "API_ROUTER_ENV";
+/// 额外覆盖文件列表的环境变量
+pub const OVERLAYS_VAR: &str = "API_ROUTER_CONFIG_OVERLAYS";
+/// 按字段覆盖配置的环境变量前缀
+pub const ENV_OVERRIDE_PREFIX: &str = "API_ROUTER__";
+
+/// 顶层扩展字段前缀，这类字段用于放置 YAML 锚点等共享片段，加载时忽略
+const EXTENSION_FIELD_PREFIX: &str = "x-";
+
+/// 配置文件路径及其最后修改时间，按合并顺序排列
+pub type LayerStamps = Vec<(PathBuf, Option<SystemTime>)>;
+
+/// 配置文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// 按扩展名识别格式，未知扩展名按 JSON 处理
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    /// 将配置文本解析为 JSON 文档
+    pub fn parse(self, text: &str) -> Result<Value, String> {
+        match self {
+            ConfigFormat::Json => {
+                serde_json::from_str(text).map_err(|err| format!("JSON 语法错误: {}", err))
+            }
+            ConfigFormat::Yaml => {
+                let mut value: serde_yaml::Value =
+                    serde_yaml::from_str(text).map_err(|err| format!("YAML 语法错误: {}", err))?;
+                value
+                    .apply_merge()
+                    .map_err(|err| format!("YAML 合并键错误: {}", err))?;
+                // 空文档视为空对象，便于只写注释的覆盖层
+                if value.is_null() {
+                    return Ok(Value::Object(Map::new()));
+                }
+                serde_json::to_value(value).map_err(|err| format!("YAML 内容无法转换: {}", err))
+            }
+            ConfigFormat::Toml => {
+                toml::from_str(text).map_err(|err| format!("TOML 语法错误: {}", err))
+            }
+        }
+    }
+}
+
+/// 合并后的配置文档
+#[derive(Debug, Clone)]
+pub struct ConfigDocument {
+    /// 合并后的 JSON 文档，尚未展开插值与校验
+    pub value: Value,
+    /// 参与合并的配置文件及其修改时间，按优先级从低到高
+    pub layers: LayerStamps,
+    /// 生效的字段覆盖环境变量名，按名称排序
+    pub env_overrides: Vec<String>,
+}
+
+/// 读取基础配置文件及其覆盖层并合并
+///
+/// 基础文件不可读时返回 [`RouterError::ConfigRead`]（调用方可回退到默认配置），
+/// 覆盖层不可读或任一层语法错误时返回 [`RouterError::ConfigParse`]
+pub fn read_document(base: &Path) -> RouterResult<ConfigDocument> {
+    let mut layers = Vec::new();
+    let mut value = read_layer(base).map_err(|err| match err {
+        LayerError::Read(message) => RouterError::ConfigRead(message),
+        LayerError::Parse(message) => RouterError::ConfigParse(message),
+    })?;
+    layers.push((base.to_path_buf(), modified(base)));
+
+    for overlay in overlay_paths(base) {
+        let overlay_value = read_layer(&overlay).map_err(|err| match err {
+            LayerError::Read(message) | LayerError::Parse(message) => {
+                RouterError::ConfigParse(message)
+            }
+        })?;
+        merge(&mut value, overlay_value);
+        layers.push((overlay.clone(), modified(&overlay)));
+    }
+
+    let overrides = env_overrides(std::env::vars());
+    let env_overrides = overrides.iter().map(|(name, _, _)| name.clone()).collect();
+    apply_env_overrides(&mut value, overrides);
+    Ok(ConfigDocument {
+        value,
+        layers,
+        env_overrides,
+    })
+}
+
+/// 当前参与合并的配置文件及其修改时间，用于判断是否需要重新加载
+pub fn layer_stamps(base: &Path) -> LayerStamps {
+    std::iter::once(base.to_path_buf())
+        .chain(overlay_paths(base))
+        .map(|path| {
+            let modified = modified(&path);
+            (path, modified)
+        })
+        .collect()
+}
+
+/// 基础配置文件的覆盖层路径，按合并顺序排列
+pub fn overlay_paths(base: &Path) -> Vec<PathBuf> {
+    overlay_paths_with(
+        base,
+        std::env::var(ENV_OVERLAY_VAR).ok().as_deref(),
+        std::env::var_os(OVERLAYS_VAR),
+    )
+}
+
+fn overlay_paths_with(
+    base: &Path,
+    environment: Option<&str>,
+    overlays: Option<OsString>,
+) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(environment) = environment.map(str::trim).filter(|env| !env.is_empty()) {
+        let stem = base
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let dir = base.parent().unwrap_or_else(|| Path::new(""));
+        if let Some(path) = CONFIG_EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(format!("{}.{}.{}", stem, environment, ext)))
+            .find(|path| path.is_file())
+        {
+            paths.push(path);
+        }
+    }
+    if let Some(overlays) = overlays {
+        paths.extend(std::env::split_paths(&overlays).filter(|p| !p.as_os_str().is_empty()));
+    }
+    paths
+}
+
+enum LayerError {
+    Read(String),
+    Parse(String),
+}
+
+fn read_layer(path: &Path) -> Result<Value, LayerError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| LayerError::Read(format!("无法打开配置文件 {}: {}", path.display(), e)))?;
+    let value = ConfigFormat::from_path(path)
+        .parse(&text)
+        .map_err(|message| LayerError::Parse(format!("{}: {}", path.display(), message)))?;
+    let Value::Object(mut map) = value else {
+        return Err(LayerError::Parse(format!(
+            "{}: 配置文件的顶层必须是对象",
+            path.display()
+        )));
+    };
+    map.retain(|key, _| !key.starts_with(EXTENSION_FIELD_PREFIX));
+    Ok(Value::Object(map))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// 将覆盖层深度合并到 `base`：对象逐键合并，其余值整体替换，`null` 删除该字段
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                if value.is_null() {
+                    base.remove(&key);
+                    continue;
+                }
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// 从环境变量中筛选字段覆盖，返回 `(变量名, 字段路径, 值)`，按变量名排序以保证合并顺序确定
+///
+/// 值能解析为 JSON 时按 JSON 处理（如 `9000`、`true`、`["a"]`），否则作为字符串
+fn env_overrides(
+    vars: impl Iterator<Item = (String, String)>,
+) -> Vec<(String, Vec<String>, Value)> {
+    let mut overrides: Vec<_> = vars
+        .filter_map(|(name, raw)| {
+            let segments: Vec<String> = name
+                .strip_prefix(ENV_OVERRIDE_PREFIX)?
+                .split("__")
+                .map(str::to_string)
+                .collect();
+            if segments.iter().any(String::is_empty) {
+                return None;
+            }
+            let value = serde_json::from_str(&raw).unwrap_or(Value::String(raw));
+            Some((name, segments, value))
+        })
+        .collect();
+    overrides.sort_by(|a, b| a.0.cmp(&b.0));
+    overrides
+}
+
+/// 应用字段覆盖；字段名不区分大小写，优先匹配文档中已有的键，其次匹配配置结构中的字段
+fn apply_env_overrides(document: &mut Value, overrides: Vec<(String, Vec<String>, Value)>) {
+    if overrides.is_empty() {
+        return;
+    }
+    let schema = config_schema();
+    let empty = Map::new();
+    let definitions = schema
+        .get("definitions")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+    for (_, segments, value) in overrides {
+        let mut node = &mut *document;
+        let mut node_schema = Some(&schema);
+        for (index, segment) in segments.iter().enumerate() {
+            let resolved = node_schema.map(|schema| resolve_schema(definitions, schema));
+            if !node.is_object() {
+                *node = Value::Object(Map::new());
+            }
+            let Value::Object(map) = node else {
+                unreachable!()
+            };
+            let properties = resolved
+                .and_then(|schema| schema.get("properties"))
+                .and_then(Value::as_object);
+            let key = map
+                .keys()
+                .find(|key| key.eq_ignore_ascii_case(segment))
+                .or_else(|| {
+                    properties.and_then(|p| p.keys().find(|key| key.eq_ignore_ascii_case(segment)))
+                })
+                .cloned()
+                .unwrap_or_else(|| segment.to_ascii_lowercase());
+            node_schema = properties
+                .and_then(|p| p.get(&key))
+                .or_else(|| resolved.and_then(|schema| schema.get("additionalProperties")))
+                .filter(|schema| schema.is_object());
+            if index + 1 == segments.len() {
+                map.insert(key, value);
+                break;
+            }
+            node = map.entry(key).or_insert(Value::Null);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_yaml_with_anchors_and_toml() {
+        let yaml = r#"
+baseUrl: https://api.openai.com
+x-shared: &stream
+  streamSupport: true
+  headers:
+    Accept: text/event-stream
+endpoints:
+  /v1/chat/completions:
+    <<: *stream
+    upstreamPath: /v1/chat/completions
+  /v1/completions: *stream
+"#;
+        let value = ConfigFormat::Yaml.parse(yaml).unwrap();
+        assert_eq!(
+            value["endpoints"]["/v1/chat/completions"],
+            json!({
+                "streamSupport": true,
+                "headers": {"Accept": "text/event-stream"},
+                "upstreamPath": "/v1/chat/completions"
+            })
+        );
+        assert_eq!(value["endpoints"]["/v1/completions"]["streamSupport"], true);
+
+        let toml = r#"
+baseUrl = "https://api.anthropic.com"
+port = 9000
+
+[endpoints."/v1/messages"]
+upstreamPath = "/v1/messages"
+"#;
+        let value = ConfigFormat::Toml.parse(toml).unwrap();
+        assert_eq!(value["port"], 9000);
+        assert_eq!(
+            value["endpoints"]["/v1/messages"]["upstreamPath"],
+            "/v1/messages"
+        );
+
+        assert!(ConfigFormat::Yaml
+            .parse("baseUrl: [")
+            .unwrap_err()
+            .starts_with("YAML 语法错误"));
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("prod.YML")),
+            ConfigFormat::Yaml
+        );
+    }
+
+    #[test]
+    fn deep_merges_objects_and_replaces_other_values() {
+        let mut base = json!({
+            "baseUrl": "https://api.openai.com",
+            "headers": {"User-Agent": "api-router", "X-Debug": "1"},
+            "endpoints": {
+                "/v1/chat/completions": {"upstreamPath": "/v1/chat/completions", "streamSupport": true},
+                "/v1/embeddings": {"upstreamPath": "/v1/embeddings"}
+            },
+            "retry": {"retryOnStatus": [429, 503]}
+        });
+        merge(
+            &mut base,
+            json!({
+                "headers": {"X-Debug": null},
+                "endpoints": {
+                    "/v1/chat/completions": {"rateLimit": {"requestsPerMinute": 30}},
+                    "/v1/messages": {"upstreamPath": "/v1/messages"}
+                },
+                "retry": {"retryOnStatus": [500]}
+            }),
+        );
+        assert_eq!(
+            base,
+            json!({
+                "baseUrl": "https://api.openai.com",
+                "headers": {"User-Agent": "api-router"},
+                "endpoints": {
+                    "/v1/chat/completions": {
+                        "upstreamPath": "/v1/chat/completions",
+                        "streamSupport": true,
+                        "rateLimit": {"requestsPerMinute": 30}
+                    },
+                    "/v1/embeddings": {"upstreamPath": "/v1/embeddings"},
+                    "/v1/messages": {"upstreamPath": "/v1/messages"}
+                },
+                "retry": {"retryOnStatus": [500]}
+            })
+        );
+    }
+
+    #[test]
+    fn applies_env_overrides_case_insensitively_in_name_order() {
+        let vars = [
+            ("API_ROUTER__RATELIMIT__BURST", "5"),
+            (
+                "API_ROUTER__RATELIMIT",
+                r#"{"requestsPerMinute": 60, "burst": 1}"#,
+            ),
+            ("API_ROUTER__PORT", "9000"),
+            ("API_ROUTER__BASEURL", "https://eu.api.openai.com"),
+            ("API_ROUTER__PROXY__PROVIDERS__OPENAI", "direct"),
+            ("API_ROUTER__TLS__MINVERSION", "\"1.3\""),
+            ("UNRELATED", "1"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let mut document = json!({"baseUrl": "https://api.openai.com", "port": 8000});
+        let overrides = env_overrides(vars);
+        assert_eq!(overrides.len(), 6);
+        apply_env_overrides(&mut document, overrides);
+        assert_eq!(
+            document,
+            json!({
+                "baseUrl": "https://eu.api.openai.com",
+                "port": 9000,
+                "rateLimit": {"requestsPerMinute": 60, "burst": 5},
+                "proxy": {"providers": {"openai": "direct"}},
+                "tls": {"minVersion": "1.3"}
+            })
+        );
+    }
+
+    #[test]
+    fn discovers_environment_and_explicit_overlays() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("openai.json");
+        let staging = dir.path().join("openai.staging.yaml");
+        let extra = dir.path().join("local.toml");
+        fs::write(&staging, "port: 9100\n").unwrap();
+        let overlays = std::env::join_paths([&extra]).unwrap();
+
+        assert_eq!(
+            overlay_paths_with(&base, Some("staging"), Some(overlays.clone())),
+            vec![staging.clone(), extra.clone()]
+        );
+        assert_eq!(
+            overlay_paths_with(&base, Some("production"), None),
+            Vec::<PathBuf>::new()
+        );
+        assert_eq!(overlay_paths_with(&base, None, Some(overlays)), vec![extra]);
+    }
+}
@@ -1,23 +1,49 @@
 //! API Router 库模块
 //!
 //! 提供 API 转发服务的核心功能，包括：
-//! - 配置管理
+//! - 配置管理（JSON / YAML / TOML、分层覆盖）与加载时校验（附 JSON Schema）、环境变量与机密文件插值、热加载与回滚
 //! - HTTP 客户端和连接池
-//! - 速率限制
-//! - 错误处理和追踪
-//! - 指标收集
+//! - 速率限制与准入调度
+//! - 上游熔断、重试、请求对冲与备用上游切换
+//! - 错误处理和追踪（含 OTLP 链路导出与上游故障告警）
+//! - 指标收集、token 用量统计与访问日志
+//! - 请求/响应录制与重放、确定性请求的响应缓存
+//! - 管理接口（生效配置、重新加载、令牌桶、提供商启停、熔断器与进行中请求的查看与控制）
+//! - 命令行子命令（配置校验、生效配置输出、上游连通性检查）
 //! - OpenAI 兼容的数据模型
 
+pub mod access_log;
+pub mod alerting;
+pub mod capture;
+pub mod circuit_breaker;
+pub mod cli;
 pub mod config;
 pub mod error_tracking;
 pub mod errors;
 pub mod handlers;
+pub mod hedge;
 pub mod http_client;
+pub mod inflight;
+pub mod layering;
+pub mod listener;
 pub mod metrics;
 pub mod models;
+pub mod otel;
+pub mod proxy;
 pub mod rate_limit;
+pub mod reload;
+pub mod replay;
+pub mod response_cache;
+pub mod retry;
+pub mod scheduler;
+pub mod secrets;
+pub mod shutdown;
+pub mod timeout;
+pub mod tls;
 pub mod tracing_util;
 pub mod url_parser;
+pub mod usage;
+pub mod validation;
 
 pub use http_client::PoolConfig;
 
@@ -0,0 +1,783 @@
+//! 监听端模块
+//!
+//! 按 [`ListenConfig`] 绑定监听端（TCP 地址、Unix 域套接字或 systemd 套接字激活传入的描述符），
+//! 定义客户端连接 [`ClientStream`]（明文 TCP、Unix 套接字或 TLS），以及监听端口的 TLS 终止：
+//! 证书按 [`ServerTlsConfig`] 加载，后台定期检查证书与私钥文件的修改时间并在变化时
+//! 重新加载，已建立的连接不受影响，加载失败时继续使用旧证书
+
+use crate::config::{ListenConfig, ServerTlsConfig, UnixSocketConfig};
+use crate::errors::{RouterError, RouterResult};
+use crate::timeout::{timeout_message, with_timeout};
+use crate::tls::{load_certificates, load_private_key};
+use async_tls::TlsAcceptor;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use smol::io::{AsyncRead, AsyncWrite};
+#[cfg(unix)]
+use smol::net::unix::{UnixListener, UnixStream};
+use smol::net::{TcpListener, TcpStream};
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// 客户端 TLS 握手超时
+const TLS_ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// systemd 传入的第一个监听描述符
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// 监听端
+pub enum Listener {
+    Tcp(TcpListener),
+    /// `path` 为本进程创建的套接字文件，监听端释放时删除；systemd 传入的套接字为 `None`
+    #[cfg(unix)]
+    Unix {
+        listener: UnixListener,
+        path: Option<PathBuf>,
+    },
+}
+
+impl Listener {
+    /// 接受一个客户端连接
+    pub async fn accept(&self) -> std::io::Result<(ClientStream, PeerAddr)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                Ok((ClientStream::Plain(stream), PeerAddr::Tcp(addr)))
+            }
+            #[cfg(unix)]
+            Listener::Unix { listener, .. } => {
+                let (stream, _) = listener.accept().await?;
+                Ok((ClientStream::Unix(stream), PeerAddr::Unix))
+            }
+        }
+    }
+
+    /// 是否为 TCP 监听端（只有 TCP 监听端会终止 TLS）
+    pub fn is_tcp(&self) -> bool {
+        matches!(self, Listener::Tcp(_))
+    }
+
+    /// 实际监听的 TCP 地址
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Listener::Unix { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{}", addr),
+                Err(_) => write!(f, "tcp"),
+            },
+            #[cfg(unix)]
+            Listener::Unix { listener, .. } => {
+                let path = listener
+                    .local_addr()
+                    .ok()
+                    .and_then(|addr| addr.as_pathname().map(|path| path.display().to_string()));
+                write!(f, "unix:{}", path.as_deref().unwrap_or("<unnamed>"))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix {
+            path: Some(path), ..
+        } = self
+        {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// 客户端地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// Unix 域套接字上的对端没有网络地址
+    Unix,
+}
+
+impl PeerAddr {
+    /// 用于日志与追踪的客户端 IP，Unix 套接字连接记为 `unix`
+    pub fn client_ip(&self) -> String {
+        match self {
+            PeerAddr::Tcp(addr) => addr.ip().to_string(),
+            PeerAddr::Unix => "unix".to_string(),
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{}", addr),
+            PeerAddr::Unix => write!(f, "unix socket"),
+        }
+    }
+}
+
+/// 按配置绑定全部监听端
+///
+/// 开启 `systemd` 且本进程收到 `LISTEN_FDS` 时只使用传入的描述符；否则绑定配置的 TCP 地址与
+/// Unix 套接字，两者都未配置时监听 `0.0.0.0:{default_port}`
+pub fn bind_listeners(config: &ListenConfig, default_port: u16) -> RouterResult<Vec<Listener>> {
+    #[cfg(unix)]
+    if config.systemd {
+        let inherited = systemd_listeners()?;
+        if !inherited.is_empty() {
+            return Ok(inherited);
+        }
+    }
+
+    let mut listeners = Vec::new();
+    if config.tcp.is_empty() && config.unix.is_empty() {
+        listeners.push(bind_tcp(
+            SocketAddr::from(([0, 0, 0, 0], default_port)),
+            config.port_fallback,
+        )?);
+    }
+    for address in &config.tcp {
+        listeners.push(bind_tcp(resolve_address(address)?, config.port_fallback)?);
+    }
+    for unix in &config.unix {
+        listeners.push(bind_unix(unix)?);
+    }
+    Ok(listeners)
+}
+
+/// 绑定单个 `host:port` 形式的 TCP 地址，如管理接口的独立监听端
+pub fn bind_address(address: &str) -> RouterResult<Listener> {
+    bind_tcp(resolve_address(address)?, 0)
+}
+
+/// 解析 TCP 监听地址，支持 `host:port` 与 `[ipv6]:port`
+fn resolve_address(address: &str) -> RouterResult<SocketAddr> {
+    address
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| RouterError::ConfigParse(format!("Invalid listen address: {}", address)))
+}
+
+/// 绑定 TCP 地址；端口被占用时最多向后尝试 `port_fallback` 个端口
+pub fn bind_tcp(addr: SocketAddr, port_fallback: u16) -> RouterResult<Listener> {
+    let mut last_error = None;
+    for offset in 0..=port_fallback {
+        let Some(port) = addr.port().checked_add(offset) else {
+            break;
+        };
+        let candidate = SocketAddr::new(addr.ip(), port);
+        match std::net::TcpListener::bind(candidate) {
+            Ok(listener) => {
+                if offset > 0 {
+                    warn!(requested = %addr, bound = %candidate, "Listen port was busy, fell back to a later port");
+                }
+                return Ok(Listener::Tcp(TcpListener::try_from(listener)?));
+            }
+            Err(err) => {
+                if offset < port_fallback {
+                    warn!(
+                        "Failed to bind {}: {}, trying the next port",
+                        candidate, err
+                    );
+                }
+                last_error = Some(err);
+            }
+        }
+    }
+    let err = last_error.unwrap_or_else(|| std::io::Error::other("no port available"));
+    Err(bind_error(&addr.to_string(), err))
+}
+
+fn bind_error(target: &str, err: std::io::Error) -> RouterError {
+    RouterError::Io(std::io::Error::new(
+        err.kind(),
+        format!("Failed to bind {}: {}", target, err),
+    ))
+}
+
+/// 绑定 Unix 域套接字；遗留的套接字文件无人监听时先删除
+#[cfg(unix)]
+fn bind_unix(config: &UnixSocketConfig) -> RouterResult<Listener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = config.mode.as_deref().map(parse_mode).transpose()?;
+    let path = PathBuf::from(&config.path);
+    remove_stale_socket(&path)?;
+    let listener = std::os::unix::net::UnixListener::bind(&path)
+        .map_err(|err| bind_error(&config.path, err))?;
+    // 先构造监听端，设置权限失败时由 Drop 清理套接字文件
+    let listener = Listener::Unix {
+        listener: UnixListener::try_from(listener)?,
+        path: Some(path.clone()),
+    };
+    if let Some(mode) = mode {
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+            .map_err(|err| bind_error(&config.path, err))?;
+    }
+    Ok(listener)
+}
+
+#[cfg(not(unix))]
+fn bind_unix(config: &UnixSocketConfig) -> RouterResult<Listener> {
+    Err(RouterError::ConfigParse(format!(
+        "Unix socket listeners are not supported on this platform: {}",
+        config.path
+    )))
+}
+
+/// 解析八进制权限字符串，如 `"0660"` 或 `"660"`
+#[cfg(unix)]
+pub(crate) fn parse_mode(mode: &str) -> RouterResult<u32> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| RouterError::ConfigParse(format!("Invalid unix socket mode: {}", mode)))
+}
+
+/// 删除无人监听的遗留套接字文件；路径被其他进程监听或不是套接字时报错
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> RouterResult<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    let target = path.display().to_string();
+    if !metadata.file_type().is_socket() {
+        return Err(bind_error(
+            &target,
+            std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "path exists and is not a socket",
+            ),
+        ));
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(bind_error(
+            &target,
+            std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                "another process is listening on this socket",
+            ),
+        ));
+    }
+    std::fs::remove_file(path).map_err(|err| bind_error(&target, err))
+}
+
+/// 接管 systemd 套接字激活传入的监听描述符
+#[cfg(unix)]
+fn systemd_listeners() -> RouterResult<Vec<Listener>> {
+    let count = listen_fds_count(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count)
+        .map(inherited_listener)
+        .collect()
+}
+
+/// 按 `LISTEN_PID` / `LISTEN_FDS` 计算传给本进程的描述符个数，描述符不是传给本进程时为 0
+#[cfg(unix)]
+fn listen_fds_count(listen_pid: Option<&str>, listen_fds: Option<&str>, own_pid: u32) -> i32 {
+    if listen_pid.and_then(|pid| pid.trim().parse::<u32>().ok()) != Some(own_pid) {
+        return 0;
+    }
+    listen_fds
+        .and_then(|count| count.trim().parse::<i32>().ok())
+        .filter(|count| *count > 0)
+        .unwrap_or(0)
+}
+
+/// 将继承的描述符包装为监听端，按套接字地址族区分 TCP 与 Unix 套接字
+#[cfg(unix)]
+fn inherited_listener(fd: i32) -> RouterResult<Listener> {
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+    // SAFETY: 描述符由 systemd 按套接字激活协议传给本进程，且只在启动时接管一次
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+    if tcp.local_addr().is_ok() {
+        return Ok(Listener::Tcp(TcpListener::try_from(tcp)?));
+    }
+    // SAFETY: 同一描述符从 TCP 包装中取回，所有权没有变化
+    let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+    if let Err(err) = unix.local_addr() {
+        return Err(RouterError::Io(std::io::Error::new(
+            err.kind(),
+            format!("Inherited fd {} is not a TCP or unix socket: {}", fd, err),
+        )));
+    }
+    Ok(Listener::Unix {
+        listener: UnixListener::try_from(unix)?,
+        path: None,
+    })
+}
+
+/// 已接受的客户端连接
+pub enum ClientStream {
+    Plain(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tls(Box<async_tls::server::TlsStream<TcpStream>>),
+}
+
+impl From<TcpStream> for ClientStream {
+    fn from(stream: TcpStream) -> Self {
+        ClientStream::Plain(stream)
+    }
+}
+
+impl AsyncRead for ClientStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            ClientStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            ClientStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            ClientStream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ClientStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            ClientStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            ClientStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            ClientStream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ClientStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            ClientStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            ClientStream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ClientStream::Plain(stream) => Pin::new(stream).poll_close(cx),
+            #[cfg(unix)]
+            ClientStream::Unix(stream) => Pin::new(stream).poll_close(cx),
+            ClientStream::Tls(stream) => Pin::new(stream.as_mut()).poll_close(cx),
+        }
+    }
+}
+
+/// 证书与私钥文件的修改时间，用于判断是否需要重新加载
+type FileStamps = (Option<SystemTime>, Option<SystemTime>);
+
+/// 可重新加载的服务端证书
+struct ReloadingCertResolver {
+    cert_file: String,
+    key_file: String,
+    current: RwLock<(Arc<CertifiedKey>, FileStamps)>,
+}
+
+impl ReloadingCertResolver {
+    fn load(cert_file: &str, key_file: &str) -> RouterResult<Self> {
+        let (key, stamps) = load_certified_key(cert_file, key_file)?;
+        Ok(Self {
+            cert_file: cert_file.to_string(),
+            key_file: key_file.to_string(),
+            current: RwLock::new((key, stamps)),
+        })
+    }
+
+    fn stamps(&self) -> FileStamps {
+        (modified(&self.cert_file), modified(&self.key_file))
+    }
+
+    /// 文件修改时间变化时重新加载，返回是否已更换证书
+    fn reload_if_changed(&self) -> RouterResult<bool> {
+        let unchanged = self
+            .current
+            .read()
+            .map(|current| current.1 == self.stamps())
+            .unwrap_or(false);
+        if unchanged {
+            return Ok(false);
+        }
+        let (key, stamps) = load_certified_key(&self.cert_file, &self.key_file)?;
+        if let Ok(mut current) = self.current.write() {
+            *current = (key, stamps);
+        }
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|current| current.0.clone())
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn load_certified_key(
+    cert_file: &str,
+    key_file: &str,
+) -> RouterResult<(Arc<CertifiedKey>, FileStamps)> {
+    // 先记录修改时间再读取，读取期间的写入会在下一次检查时生效
+    let stamps = (modified(cert_file), modified(key_file));
+    let certs = load_certificates(cert_file)?;
+    let key = load_private_key(key_file)?;
+    let signing_key = rustls::sign::any_supported_type(&key)
+        .map_err(|_| RouterError::Tls(format!("Unsupported private key type in {}", key_file)))?;
+    Ok((Arc::new(CertifiedKey::new(certs, signing_key)), stamps))
+}
+
+/// 监听端口的 TLS 终止
+pub struct ServerTls {
+    resolver: Arc<ReloadingCertResolver>,
+    acceptor: TlsAcceptor,
+    reload_interval: Option<Duration>,
+}
+
+impl ServerTls {
+    /// 加载证书并构造 TLS 终止；证书或私钥无效时返回 [`RouterError::Tls`]
+    pub fn from_config(config: &ServerTlsConfig) -> RouterResult<Self> {
+        let resolver = Arc::new(ReloadingCertResolver::load(
+            &config.cert_file,
+            &config.key_file,
+        )?);
+        let mut server_config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+        server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Self {
+            resolver,
+            acceptor: TlsAcceptor::from(Arc::new(server_config)),
+            reload_interval: (config.reload_interval_secs > 0)
+                .then(|| Duration::from_secs(config.reload_interval_secs)),
+        })
+    }
+
+    /// 完成客户端 TLS 握手
+    pub async fn accept(&self, stream: TcpStream) -> RouterResult<ClientStream> {
+        let tls_stream = with_timeout(
+            Some(TLS_ACCEPT_TIMEOUT),
+            async {
+                self.acceptor
+                    .accept(stream)
+                    .await
+                    .map_err(|e| RouterError::Tls(format!("Client TLS handshake failed: {}", e)))
+            },
+            |limit| {
+                RouterError::TlsHandshakeTimeout(timeout_message("Client TLS handshake", limit))
+            },
+        )
+        .await?;
+        Ok(ClientStream::Tls(Box::new(tls_stream)))
+    }
+
+    /// 证书或私钥文件变化时重新加载，返回是否已更换证书
+    pub fn reload_if_changed(&self) -> RouterResult<bool> {
+        self.resolver.reload_if_changed()
+    }
+
+    /// 启动后台任务，按配置的间隔检查并重新加载证书
+    pub fn spawn_reloader(self: &Arc<Self>) {
+        let Some(interval) = self.reload_interval else {
+            return;
+        };
+        let server_tls = Arc::clone(self);
+        smol::spawn(async move {
+            loop {
+                smol::Timer::after(interval).await;
+                match server_tls.reload_if_changed() {
+                    Ok(true) => info!(
+                        cert_file = %server_tls.resolver.cert_file,
+                        "Reloaded listener TLS certificate"
+                    ),
+                    Ok(false) => {}
+                    Err(err) => warn!(
+                        "Failed to reload listener TLS certificate, keeping the previous one: {}",
+                        err
+                    ),
+                }
+            }
+        })
+        .detach();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smol::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn write_pair(dir: &std::path::Path, name: &str) -> (rcgen::Certificate, ServerTlsConfig) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_file = dir.join(format!("{}.pem", name));
+        let key_file = dir.join(format!("{}.key", name));
+        std::fs::write(&cert_file, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_file, cert.serialize_private_key_pem()).unwrap();
+        let config = ServerTlsConfig {
+            cert_file: cert_file.display().to_string(),
+            key_file: key_file.display().to_string(),
+            port: None,
+            reload_interval_secs: 0,
+        };
+        (cert, config)
+    }
+
+    /// 以信任 `cert` 的客户端连接，返回服务端回显的数据
+    fn round_trip(server_tls: &ServerTls, cert: &rcgen::Certificate) -> RouterResult<Vec<u8>> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(cert.serialize_der().unwrap()))
+            .unwrap();
+        let connector = async_tls::TlsConnector::from(
+            rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        );
+        smol::block_on(async {
+            let listener = smol::net::TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let client = smol::spawn(async move {
+                let stream = TcpStream::connect(addr).await?;
+                let mut tls = connector.connect("localhost", stream).await?;
+                tls.write_all(b"ping").await?;
+                tls.flush().await?;
+                let mut response = vec![0u8; 4];
+                tls.read_exact(&mut response).await?;
+                Ok::<_, std::io::Error>(response)
+            });
+            let (stream, _) = listener.accept().await?;
+            if let Ok(mut accepted) = server_tls.accept(stream).await {
+                let mut buffer = [0u8; 4];
+                accepted.read_exact(&mut buffer).await?;
+                accepted.write_all(b"pong").await?;
+                accepted.flush().await?;
+            }
+            Ok(client.await?)
+        })
+    }
+
+    #[test]
+    fn terminates_tls_and_reloads_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, config) = write_pair(dir.path(), "server");
+        let server_tls = ServerTls::from_config(&config).unwrap();
+        assert_eq!(round_trip(&server_tls, &first).unwrap(), b"pong");
+        assert!(!server_tls.reload_if_changed().unwrap());
+
+        // 轮换证书：旧证书不再被使用，新证书无需重启即可生效
+        std::thread::sleep(Duration::from_millis(20));
+        let (second, rotated) = write_pair(dir.path(), "rotated");
+        std::fs::copy(&rotated.cert_file, &config.cert_file).unwrap();
+        std::fs::copy(&rotated.key_file, &config.key_file).unwrap();
+        assert!(server_tls.reload_if_changed().unwrap());
+        assert_eq!(round_trip(&server_tls, &second).unwrap(), b"pong");
+        assert!(round_trip(&server_tls, &first).is_err());
+    }
+
+    #[test]
+    fn keeps_previous_certificate_when_reload_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert, config) = write_pair(dir.path(), "server");
+        let server_tls = ServerTls::from_config(&config).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(&config.key_file, "not a key").unwrap();
+        let err = server_tls.reload_if_changed().unwrap_err();
+        assert_eq!(err.class(), "tls_error");
+        assert_eq!(round_trip(&server_tls, &cert).unwrap(), b"pong");
+    }
+
+    #[test]
+    fn port_fallback_is_opt_in() {
+        let busy = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = busy.local_addr().unwrap();
+        let err = bind_tcp(addr, 0).err().unwrap();
+        assert!(err.to_string().contains(&addr.to_string()));
+
+        let listener = bind_tcp(addr, 5).unwrap();
+        let bound = listener.local_addr().unwrap();
+        assert!(bound.port() > addr.port() && bound.port() <= addr.port() + 5);
+    }
+
+    #[test]
+    fn binds_configured_tcp_addresses_including_ipv6() {
+        let config = ListenConfig {
+            tcp: vec!["127.0.0.1:0".to_string(), "[::1]:0".to_string()],
+            systemd: false,
+            ..ListenConfig::default()
+        };
+        let listeners = bind_listeners(&config, 0).unwrap();
+        assert_eq!(listeners.len(), 2);
+        assert!(listeners[0].local_addr().unwrap().is_ipv4());
+        assert!(listeners[1].local_addr().unwrap().is_ipv6());
+        assert!(listeners[1].to_string().starts_with("[::1]:"));
+
+        let err = bind_listeners(
+            &ListenConfig {
+                tcp: vec!["not an address".to_string()],
+                ..config
+            },
+            0,
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("not an address"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serves_unix_socket_with_mode_and_cleans_up() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("router.sock");
+        // 遗留的套接字文件无人监听，绑定时被替换
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let config = ListenConfig {
+            unix: vec![UnixSocketConfig {
+                path: path.display().to_string(),
+                mode: Some("0660".to_string()),
+            }],
+            systemd: false,
+            ..ListenConfig::default()
+        };
+        let mut listeners = bind_listeners(&config, 0).unwrap();
+        assert_eq!(listeners.len(), 1, "unix-only config must not bind TCP");
+        let listener = listeners.pop().unwrap();
+        assert!(!listener.is_tcp());
+        assert_eq!(listener.to_string(), format!("unix:{}", path.display()));
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        let echoed = smol::block_on(async {
+            let client = smol::spawn({
+                let path = path.clone();
+                async move {
+                    let mut stream = UnixStream::connect(path).await?;
+                    stream.write_all(b"ping").await?;
+                    let mut response = vec![0u8; 4];
+                    stream.read_exact(&mut response).await?;
+                    Ok::<_, std::io::Error>(response)
+                }
+            });
+            let (mut stream, peer) = listener.accept().await.unwrap();
+            assert_eq!(peer, PeerAddr::Unix);
+            assert_eq!(peer.client_ip(), "unix");
+            let mut buffer = [0u8; 4];
+            stream.read_exact(&mut buffer).await.unwrap();
+            stream.write_all(b"pong").await.unwrap();
+            client.await.unwrap()
+        });
+        assert_eq!(echoed, b"pong");
+
+        // 正在监听的套接字不会被抢占
+        assert!(bind_listeners(&config, 0).is_err());
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_to_replace_regular_files_and_bad_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        std::fs::write(&path, "{}").unwrap();
+        let err = bind_unix(&UnixSocketConfig {
+            path: path.display().to_string(),
+            mode: None,
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("not a socket"));
+        assert!(path.exists());
+
+        assert_eq!(parse_mode("660").unwrap(), 0o660);
+        assert!(parse_mode("0999").is_err());
+        assert!(parse_mode("rw-rw----").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn counts_systemd_fds_only_for_this_process() {
+        assert_eq!(listen_fds_count(Some("42"), Some("2"), 42), 2);
+        assert_eq!(listen_fds_count(Some("41"), Some("2"), 42), 0);
+        assert_eq!(listen_fds_count(None, Some("2"), 42), 0);
+        assert_eq!(listen_fds_count(Some("42"), Some("x"), 42), 0);
+        assert_eq!(listen_fds_count(Some("42"), None, 42), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn adopts_inherited_tcp_and_unix_sockets() {
+        use std::os::unix::io::IntoRawFd;
+
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let listener = inherited_listener(tcp.into_raw_fd()).unwrap();
+        assert_eq!(listener.local_addr(), Some(addr));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("inherited.sock");
+        let unix = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let listener = inherited_listener(unix.into_raw_fd()).unwrap();
+        assert!(!listener.is_tcp());
+        // systemd 创建的套接字文件由 systemd 管理，释放监听端时保留
+        drop(listener);
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn rejects_missing_certificate_files() {
+        let err = ServerTls::from_config(&ServerTlsConfig {
+            cert_file: "/nonexistent/server.pem".to_string(),
+            key_file: "/nonexistent/server.key".to_string(),
+            port: None,
+            reload_interval_secs: 30,
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("/nonexistent/server.pem"));
+    }
+}
@@ -1,53 +1,44 @@
-mod config;
-mod error_tracking;
-mod errors;
-mod handlers;
-mod http_client;
-mod metrics;
-mod models;
-mod rate_limit;
-mod tracing_util;
-mod url_parser;
-
-#[cfg(test)]
-mod tracing_tests;
-
-use config::{load_api_config, ApiConfig};
-use errors::RouterError;
-use handlers::handle_request;
-
-use std::collections::HashMap;
+use api_router::access_log;
+use api_router::capture;
+use api_router::cli::{self, Command, LogFormat};
+use api_router::config::{load_api_config, ApiConfig};
+use api_router::error_tracking;
+use api_router::errors::RouterError;
+use api_router::handlers::{handle_admin_request, handle_request, set_dedicated_listener};
+use api_router::http_client::close_idle_connections;
+use api_router::listener::{
+    bind_address, bind_listeners, bind_tcp, ClientStream, Listener, ServerTls,
+};
+use api_router::otel;
+use api_router::proxy;
+use api_router::reload;
+use api_router::secrets::redacting;
+use api_router::shutdown::{self, ShutdownSignal};
+use api_router::tls;
+
 use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 
-use smol::net::TcpListener;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-/// 创建一个默认的 API 配置
-fn default_config() -> ApiConfig {
-    ApiConfig {
-        base_url: String::new(),
-        headers: HashMap::new(),
-        model_mapping: None,
-        endpoints: HashMap::new(),
-        port: 8000,
-        rate_limit: None,
-        stream_config: None,
-    }
-}
-
 /// 初始化追踪和日志系统
 ///
-/// 根据 LOG_FORMAT 环境变量决定日志格式：
+/// 日志格式由 `--log-format` 决定，未指定时读取 LOG_FORMAT 环境变量：
 /// - "json": 输出 JSON 格式日志
 /// - 其他: 输出人类可读格式
 ///
-/// 日志级别通过 RUST_LOG 环境变量控制，默认为 info
-fn init_tracing() {
-    let use_json = env::var("LOG_FORMAT")
-        .unwrap_or_default()
-        .eq_ignore_ascii_case("json");
+/// 日志级别通过 RUST_LOG 环境变量控制，默认为 info。
+/// 配置插值得到的机密在输出前替换为占位符。
+/// 配置 OTEL_EXPORTER_OTLP_ENDPOINT 时额外启用 OTLP 链路导出
+fn init_tracing(log_format: Option<LogFormat>) {
+    let use_json = match log_format {
+        Some(format) => format == LogFormat::Json,
+        None => env::var("LOG_FORMAT")
+            .unwrap_or_default()
+            .eq_ignore_ascii_case("json"),
+    };
 
     let env_filter = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new("info"))
@@ -56,22 +47,65 @@ fn init_tracing() {
     if use_json {
         tracing_subscriber::registry()
             .with(env_filter)
-            .with(fmt::layer().json())
+            .with(fmt::layer().json().with_writer(redacting(std::io::stdout)))
+            .with(otel::layer_from_env())
             .init();
     } else {
         tracing_subscriber::registry()
             .with(env_filter)
-            .with(fmt::layer())
+            .with(fmt::layer().with_writer(redacting(std::io::stdout)))
+            .with(otel::layer_from_env())
             .init();
     }
 }
 
+/// 工具子命令只向 stderr 输出告警日志，避免干扰 stdout 上的结果
+fn init_cli_tracing() {
+    let env_filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new("warn"))
+        .unwrap();
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(fmt::layer().with_writer(redacting(std::io::stderr)))
+        .init();
+}
+
 fn main() -> smol::io::Result<()> {
-    init_tracing();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(cli::EXIT_USAGE);
+        }
+    };
+    cli::apply_config_path(&command);
+    let options = match command {
+        Command::Serve(options) => options,
+        Command::Replay(_) => {
+            init_tracing(None);
+            error_tracking::init_from_env();
+            std::process::exit(cli::run(&command));
+        }
+        other => {
+            init_cli_tracing();
+            std::process::exit(cli::run(&other));
+        }
+    };
 
-    smol::block_on(async {
-        let args: Vec<String> = env::args().collect();
+    init_tracing(options.log_format);
+    error_tracking::init_from_env();
 
+    // 尽早注册停机信号，避免启动期间收到 SIGTERM 时直接退出
+    let shutdown_signal = match ShutdownSignal::register() {
+        Ok(signal) => Some(signal),
+        Err(e) => {
+            warn!("无法注册停机信号处理，收到 SIGTERM 时将直接退出: {}", e);
+            None
+        }
+    };
+
+    let result = smol::block_on(async {
         // 加载配置文件，如果失败则使用默认配置
         let config = match load_api_config() {
             Ok(cfg) => cfg,
@@ -87,68 +121,178 @@ fn main() -> smol::io::Result<()> {
                         error!("意外的配置错误 ({}). 使用默认端口 8000", other)
                     }
                 }
-                Arc::new(default_config())
+                Arc::new(ApiConfig::default())
             }
         };
 
-        let configured_port = config.port;
+        // 启动配置热加载；加载失败时同样启动，配置修复后自动生效
+        reload::spawn_watcher(config.config_reload.as_ref());
+        access_log::configure(config.access_log.as_ref());
+        capture::configure(config.capture.as_ref());
+        proxy::configure(config.proxy.as_ref());
+        tls::configure(config.tls.as_ref());
+        let port = options.port.unwrap_or(config.port);
 
-        // 解析命令行参数中的端口号
-        let base_port = if args.len() > 2 {
-            match args[2].parse::<u16>() {
-                Ok(p) => p,
-                Err(e) => {
-                    warn!(
-                        "端口参数无效 '{}': {}. 使用配置/默认端口 {}",
-                        args[2], e, configured_port
-                    );
-                    configured_port
-                }
+        // 绑定监听端：TCP 地址、Unix 套接字或 systemd 传入的描述符
+        let mut listen_config = config.listen.clone().unwrap_or_default();
+        options.apply_listen(&mut listen_config);
+        let listeners = match bind_listeners(&listen_config, port) {
+            Ok(listeners) => listeners,
+            Err(e) => {
+                error!("无法绑定监听端: {}", e);
+                std::process::exit(1);
             }
-        } else {
-            configured_port
         };
 
-        // 尝试绑定端口，如果失败则尝试下一个端口（最多尝试 10 次）
-        let mut listener = None;
-        let mut used_port = 0;
-        for port_offset in 0..10 {
-            let port = base_port + port_offset;
-            let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
-
-            match TcpListener::bind(addr).await {
-                Ok(l) => {
-                    listener = Some(l);
-                    used_port = port;
-                    break;
+        // 监听端 TLS：配置了独立端口时与明文监听并存，否则 TCP 监听端改为 TLS
+        let server_tls = config
+            .server_tls
+            .as_ref()
+            .map(|tls_config| match ServerTls::from_config(tls_config) {
+                Ok(server_tls) => Arc::new(server_tls),
+                Err(err) => {
+                    error!("监听端 TLS 配置无效: {}", err);
+                    std::process::exit(1);
+                }
+            });
+        if let Some(server_tls) = &server_tls {
+            server_tls.spawn_reloader();
+        }
+        let tls_port = config
+            .server_tls
+            .as_ref()
+            .and_then(|tls_config| tls_config.port);
+        let primary_tls = server_tls.clone().filter(|_| tls_port.is_none());
+
+        let mut servers = Vec::new();
+        // 先绑定管理接口的独立监听端（始终为明文），避免主监听端在此之前提供 `/admin`
+        if let Some(address) = config
+            .admin
+            .as_ref()
+            .and_then(|admin| admin.listen.as_ref())
+        {
+            match bind_address(address) {
+                Ok(admin_listener) => {
+                    info!("管理接口启动在 http://{}", admin_listener);
+                    set_dedicated_listener(true);
+                    servers.push(smol::spawn(serve(admin_listener, None, true)));
                 }
                 Err(e) => {
-                    warn!("端口 {} 被占用: {}, 尝试下一个端口", port, e);
-                    continue;
+                    error!("无法绑定管理接口地址 {}: {}", address, e);
+                    std::process::exit(1);
                 }
             }
         }
 
-        if let Some(listener) = listener {
-            info!("API Router 启动在 http://0.0.0.0:{}", used_port);
+        for listener in listeners {
+            let listener_tls = primary_tls.clone().filter(|_| listener.is_tcp());
+            match (listener.is_tcp(), listener_tls.is_some()) {
+                (true, true) => info!("API Router 启动在 https://{}", listener),
+                (true, false) => info!("API Router 启动在 http://{}", listener),
+                (false, _) => info!("API Router 启动在 {}", listener),
+            }
+            servers.push(smol::spawn(serve(listener, listener_tls, false)));
+        }
+        if let (Some(server_tls), Some(port)) = (server_tls, tls_port) {
+            match bind_tcp(std::net::SocketAddr::from(([0, 0, 0, 0], port)), 0) {
+                Ok(tls_listener) => {
+                    info!("API Router 启动在 https://{}", tls_listener);
+                    servers.push(smol::spawn(serve(tls_listener, Some(server_tls), false)));
+                }
+                Err(e) => {
+                    error!("无法绑定 TLS 端口 {}: {}", port, e);
+                    std::process::exit(1);
+                }
+            }
+        }
 
-            // 主循环：接受连接并为每个连接创建异步任务
-            loop {
-                let (stream, addr) = match listener.accept().await {
-                    Ok(s) => s,
-                    Err(e) => {
-                        error!("接受连接失败: {}", e);
-                        continue;
-                    }
-                };
-                smol::spawn(async move {
-                    handle_request(stream, addr).await;
-                })
-                .detach();
+        // 等待停机信号；信号注册失败时只能一直服务下去
+        let Some(mut signal) = shutdown_signal else {
+            for server in servers {
+                server.await;
             }
+            return Ok(());
+        };
+        let received = signal.recv().await;
+        shutdown::begin_draining();
+        match received {
+            Ok(name) => info!("收到 {}，开始停机", name),
+            Err(e) => warn!("停机信号监听失败: {}，开始停机", e),
+        }
+        smol::spawn(async move {
+            if let Ok(name) = signal.recv().await {
+                warn!("停机期间再次收到 {}，立即退出", name);
+                shutdown::flush_telemetry();
+                std::process::exit(1);
+            }
+        })
+        .detach();
+
+        let shutdown_config = shutdown::resolve_shutdown_config(&config);
+        if shutdown_config.drain_delay_secs > 0 {
+            info!(
+                "健康检查已报告 draining，{} 秒后停止接受新连接",
+                shutdown_config.drain_delay_secs
+            );
+            smol::Timer::after(Duration::from_secs(shutdown_config.drain_delay_secs)).await;
+        }
+        // 取消监听任务即停止接受新连接（并删除本进程创建的 Unix 套接字文件），已接受的连接继续处理
+        for server in servers {
+            server.cancel().await;
+        }
+        let grace = Duration::from_secs(shutdown_config.grace_period_secs);
+        info!(
+            "已停止接受新连接，最多等待 {} 秒完成 {} 个进行中的连接",
+            grace.as_secs(),
+            shutdown::IN_FLIGHT.count()
+        );
+
+        let remaining = shutdown::IN_FLIGHT.drain(grace).await;
+        if remaining > 0 {
+            warn!("宽限期结束，仍有 {} 个连接未完成，强制关闭", remaining);
         } else {
-            error!("无法绑定到任何端口，从 {} 到 {}", base_port, base_port + 9);
-            std::process::exit(1);
+            info!("进行中的请求已全部完成");
         }
-    })
+        let closed = close_idle_connections();
+        info!("已关闭 {} 个上游空闲连接", closed);
+        Ok(())
+    });
+    shutdown::flush_telemetry();
+    info!("API Router 已停止");
+    result
+}
+
+/// 主循环：接受连接并为每个连接创建异步任务，配置了 TLS 时先完成握手；`admin` 为管理接口的独立监听端
+async fn serve(listener: Listener, server_tls: Option<Arc<ServerTls>>, admin: bool) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                error!("接受连接失败: {}", e);
+                continue;
+            }
+        };
+        let server_tls = server_tls.clone();
+        smol::spawn(async move {
+            let _in_flight = shutdown::IN_FLIGHT.track();
+            let stream = match (server_tls, stream) {
+                (Some(server_tls), ClientStream::Plain(stream)) => {
+                    match server_tls.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            debug!(client_ip = %addr.client_ip(), "客户端 TLS 握手失败: {}", e);
+                            return;
+                        }
+                    }
+                }
+                (_, stream) => stream,
+            };
+            if admin {
+                handle_admin_request(stream, addr).await;
+            } else {
+                handle_request(stream, addr).await;
+            }
+        })
+        .detach();
+    }
 }
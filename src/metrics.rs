@@ -4,12 +4,86 @@
 
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
+use std::fmt::Write as FmtWrite;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 
-static REQUESTS: Lazy<DashMap<String, AtomicU64>> = Lazy::new(DashMap::new);
+/// 默认延迟直方图桶上界（秒）
+pub const DEFAULT_LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// 直方图序列的标签：(route, provider, model, status)
+type LatencyKey = (String, String, String, u16);
+/// token 计数器的标签：(key, provider, model, type)
+type TokenKey = (String, String, String, &'static str);
+
+static REQUESTS: Lazy<DashMap<(String, String, u16), AtomicU64>> = Lazy::new(DashMap::new);
 static UPSTREAM_ERRORS: Lazy<DashMap<String, AtomicU64>> = Lazy::new(DashMap::new);
 static ACTIVE_CONNECTIONS: AtomicU64 = AtomicU64::new(0);
 static RATE_LIMITER_BUCKETS: AtomicU64 = AtomicU64::new(0);
+static CIRCUIT_BREAKER_STATE: Lazy<DashMap<(String, String), AtomicU64>> = Lazy::new(DashMap::new);
+static HEDGED_REQUESTS: Lazy<DashMap<(String, &'static str), AtomicU64>> = Lazy::new(DashMap::new);
+static CIRCUIT_BREAKER_REJECTIONS: Lazy<DashMap<(String, String), AtomicU64>> =
+    Lazy::new(DashMap::new);
+
+/// 配置重新加载次数：成功 / 失败
+static CONFIG_RELOADS_SUCCESS: AtomicU64 = AtomicU64::new(0);
+static CONFIG_RELOADS_FAILURE: AtomicU64 = AtomicU64::new(0);
+/// 最近一次成功加载配置的 Unix 时间戳（秒）
+static CONFIG_LAST_RELOAD_SECONDS: AtomicU64 = AtomicU64::new(0);
+/// 最近一次加载是否成功（1 / 0）
+static CONFIG_LAST_RELOAD_SUCCESS: AtomicU64 = AtomicU64::new(1);
+
+/// 响应缓存查找次数：(路由, hit / miss)
+static RESPONSE_CACHE_LOOKUPS: Lazy<DashMap<(String, &'static str), AtomicU64>> =
+    Lazy::new(DashMap::new);
+/// 响应缓存当前的条目数与响应体字节数
+static RESPONSE_CACHE_ENTRIES: AtomicU64 = AtomicU64::new(0);
+static RESPONSE_CACHE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+static TOKENS: Lazy<DashMap<TokenKey, AtomicU64>> = Lazy::new(DashMap::new);
+/// 费用累计值（f64 的位表示）
+static COST_USD: Lazy<DashMap<(String, String, String), AtomicU64>> = Lazy::new(DashMap::new);
+
+static LATENCY_BUCKETS: Lazy<RwLock<Arc<[f64]>>> =
+    Lazy::new(|| RwLock::new(Arc::from(&DEFAULT_LATENCY_BUCKETS[..])));
+static REQUEST_LATENCY: Lazy<DashMap<LatencyKey, Histogram>> = Lazy::new(DashMap::new);
+static UPSTREAM_LATENCY: Lazy<DashMap<LatencyKey, Histogram>> = Lazy::new(DashMap::new);
+static STREAM_TTFB: Lazy<DashMap<LatencyKey, Histogram>> = Lazy::new(DashMap::new);
+
+/// 固定桶的累积直方图
+struct Histogram {
+    bounds: Arc<[f64]>,
+    buckets: Vec<AtomicU64>,
+    /// 观测值之和（f64 的位表示）
+    sum_bits: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: Arc<[f64]>) -> Self {
+        let buckets = bounds.iter().map(|_| AtomicU64::new(0)).collect();
+        Self {
+            bounds,
+            buckets,
+            sum_bits: AtomicU64::new(0f64.to_bits()),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: f64) {
+        if let Some(index) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        let _ = self
+            .sum_bits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
 
 pub struct ConnectionGuard;
 
@@ -20,6 +94,12 @@ impl ConnectionGuard {
     }
 }
 
+impl Default for ConnectionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Drop for ConnectionGuard {
     fn drop(&mut self) {
         ACTIVE_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
@@ -27,9 +107,8 @@ impl Drop for ConnectionGuard {
 }
 
 pub fn record_request(route: &str, method: &str, status: u16) {
-    let key = format!("{}:{}:{}", route, method, status);
     REQUESTS
-        .entry(key)
+        .entry((route.to_string(), method.to_string(), status))
         .or_insert_with(|| AtomicU64::new(0))
         .fetch_add(1, Ordering::Relaxed);
 }
@@ -41,42 +120,352 @@ pub fn record_upstream_error(error_type: &str) {
         .fetch_add(1, Ordering::Relaxed);
 }
 
-pub fn observe_request_latency(_route: &str, _latency_seconds: f64) {
-    // Simplified: no histogram, just log if needed
+/// 累计 token 用量，按 Key 别名、提供商、模型和 token 类型（prompt/completion）统计
+pub fn record_token_usage(
+    key_alias: &str,
+    provider: &str,
+    model: &str,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+) {
+    for (kind, count) in [("prompt", prompt_tokens), ("completion", completion_tokens)] {
+        TOKENS
+            .entry((
+                key_alias.to_string(),
+                provider.to_string(),
+                model.to_string(),
+                kind,
+            ))
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+/// 累计估算费用（美元）
+pub fn record_estimated_cost(key_alias: &str, provider: &str, model: &str, cost_usd: f64) {
+    let _ = COST_USD
+        .entry((
+            key_alias.to_string(),
+            provider.to_string(),
+            model.to_string(),
+        ))
+        .or_insert_with(|| AtomicU64::new(0f64.to_bits()))
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + cost_usd).to_bits())
+        });
+}
+
+/// 设置延迟直方图的桶上界
+///
+/// 桶会被排序去重，非法值（非有限数或非正数）被忽略；结果为空时回退到默认桶。
+/// 在加载配置时调用；桶变化时清空所有延迟直方图序列，保证同一指标族内的 `le` 集合一致
+pub fn configure_latency_buckets(buckets: Option<&[f64]>) {
+    let bounds = sanitize_buckets(buckets);
+    let Ok(mut current) = LATENCY_BUCKETS.write() else {
+        return;
+    };
+    if current[..] == bounds[..] {
+        return;
+    }
+    *current = Arc::from(bounds);
+    // 先替换桶再清空：清空之后新建的序列只会使用新的桶
+    REQUEST_LATENCY.clear();
+    UPSTREAM_LATENCY.clear();
+    STREAM_TTFB.clear();
+}
+
+fn sanitize_buckets(buckets: Option<&[f64]>) -> Vec<f64> {
+    let mut bounds: Vec<f64> = buckets
+        .unwrap_or(&DEFAULT_LATENCY_BUCKETS)
+        .iter()
+        .copied()
+        .filter(|bound| bound.is_finite() && *bound > 0.0)
+        .collect();
+    bounds.sort_by(|a, b| a.total_cmp(b));
+    bounds.dedup();
+    if bounds.is_empty() {
+        bounds = DEFAULT_LATENCY_BUCKETS.to_vec();
+    }
+    bounds
+}
+
+fn current_latency_buckets() -> Arc<[f64]> {
+    LATENCY_BUCKETS
+        .read()
+        .map(|bounds| Arc::clone(&bounds))
+        .unwrap_or_else(|_| Arc::from(&DEFAULT_LATENCY_BUCKETS[..]))
+}
+
+fn observe_histogram(
+    series: &DashMap<LatencyKey, Histogram>,
+    route: &str,
+    provider: &str,
+    model: &str,
+    status: u16,
+    seconds: f64,
+) {
+    let key = (
+        route.to_string(),
+        provider.to_string(),
+        model.to_string(),
+        status,
+    );
+    series
+        .entry(key)
+        .or_insert_with(|| Histogram::new(current_latency_buckets()))
+        .observe(seconds);
+}
+
+/// 记录请求总延迟（从接收连接到响应写出）
+pub fn observe_request_latency(
+    route: &str,
+    provider: &str,
+    model: &str,
+    status: u16,
+    latency_seconds: f64,
+) {
+    observe_histogram(
+        &REQUEST_LATENCY,
+        route,
+        provider,
+        model,
+        status,
+        latency_seconds,
+    );
+}
+
+/// 记录上游请求延迟
+pub fn observe_upstream_latency(
+    route: &str,
+    provider: &str,
+    model: &str,
+    status: u16,
+    latency_seconds: f64,
+) {
+    observe_histogram(
+        &UPSTREAM_LATENCY,
+        route,
+        provider,
+        model,
+        status,
+        latency_seconds,
+    );
+}
+
+/// 记录流式响应的首字节时间（从发送上游请求到收到首个响应字节）
+pub fn observe_stream_ttfb(
+    route: &str,
+    provider: &str,
+    model: &str,
+    status: u16,
+    ttfb_seconds: f64,
+) {
+    observe_histogram(&STREAM_TTFB, route, provider, model, status, ttfb_seconds);
 }
 
 pub fn update_rate_limiter_buckets(count: usize) {
     RATE_LIMITER_BUCKETS.store(count as u64, Ordering::Relaxed);
 }
 
+/// 更新熔断器状态（0 = closed，1 = open，2 = half_open）
+pub fn set_circuit_breaker_state(upstream: &str, route: &str, state: u64) {
+    CIRCUIT_BREAKER_STATE
+        .entry((upstream.to_string(), route.to_string()))
+        .or_insert_with(|| AtomicU64::new(0))
+        .store(state, Ordering::Relaxed);
+}
+
+/// 记录一次因熔断打开而被拒绝的请求
+pub fn record_circuit_breaker_rejection(upstream: &str, route: &str) {
+    CIRCUIT_BREAKER_REJECTIONS
+        .entry((upstream.to_string(), route.to_string()))
+        .or_insert_with(|| AtomicU64::new(0))
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// 记录一次发出了对冲请求的调用，`winner` 为 `primary` 或 `hedge`
+pub fn record_hedged_request(route: &str, winner: &'static str) {
+    HEDGED_REQUESTS
+        .entry((route.to_string(), winner))
+        .or_insert_with(|| AtomicU64::new(0))
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// 记录一次配置加载结果；`initial` 为启动时的首次加载，只更新时间戳不计入重新加载次数
+pub fn record_config_reload(success: bool, initial: bool, timestamp_secs: u64) {
+    if success {
+        CONFIG_LAST_RELOAD_SECONDS.store(timestamp_secs, Ordering::Relaxed);
+        if !initial {
+            CONFIG_RELOADS_SUCCESS.fetch_add(1, Ordering::Relaxed);
+        }
+    } else {
+        CONFIG_RELOADS_FAILURE.fetch_add(1, Ordering::Relaxed);
+    }
+    CONFIG_LAST_RELOAD_SUCCESS.store(u64::from(success), Ordering::Relaxed);
+}
+
+/// 记录一次响应缓存查找
+pub fn record_response_cache_lookup(route: &str, hit: bool) {
+    RESPONSE_CACHE_LOOKUPS
+        .entry((route.to_string(), if hit { "hit" } else { "miss" }))
+        .or_insert_with(|| AtomicU64::new(0))
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// 更新响应缓存的条目数与字节数
+pub fn update_response_cache_size(entries: usize, bytes: u64) {
+    RESPONSE_CACHE_ENTRIES.store(entries as u64, Ordering::Relaxed);
+    RESPONSE_CACHE_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+/// 按 Prometheus 文本格式转义标签值（反斜杠、双引号与换行）
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+/// 格式化直方图桶上界，整数值保留一位小数以保持 `le` 标签稳定
+fn format_bound(bound: f64) -> String {
+    if bound.fract() == 0.0 {
+        format!("{:.1}", bound)
+    } else {
+        bound.to_string()
+    }
+}
+
+fn write_histograms(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    series: &DashMap<LatencyKey, Histogram>,
+) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} histogram", name);
+    for entry in series.iter() {
+        let (route, provider, model, status) = entry.key();
+        let labels = format!(
+            "route=\"{}\",provider=\"{}\",model=\"{}\",status=\"{}\"",
+            escape_label_value(route),
+            escape_label_value(provider),
+            escape_label_value(model),
+            status
+        );
+        let histogram = entry.value();
+        let mut cumulative = 0;
+        for (bound, bucket) in histogram.bounds.iter().zip(&histogram.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                output,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name,
+                labels,
+                format_bound(*bound),
+                cumulative
+            );
+        }
+        let count = histogram.count.load(Ordering::Relaxed);
+        let _ = writeln!(
+            output,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, count
+        );
+        let _ = writeln!(
+            output,
+            "{}_sum{{{}}} {}",
+            name,
+            labels,
+            f64::from_bits(histogram.sum_bits.load(Ordering::Relaxed))
+        );
+        let _ = writeln!(output, "{}_count{{{}}} {}", name, labels, count);
+    }
+}
+
 pub fn gather_metrics() -> Result<String, String> {
     let mut output = String::new();
 
     output.push_str("# HELP requests_total Total HTTP requests\n");
     output.push_str("# TYPE requests_total counter\n");
     for entry in REQUESTS.iter() {
-        let parts: Vec<&str> = entry.key().split(':').collect();
-        if parts.len() == 3 {
-            output.push_str(&format!(
-                "requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}\n",
-                parts[0],
-                parts[1],
-                parts[2],
-                entry.value().load(Ordering::Relaxed)
-            ));
-        }
+        let (route, method, status) = entry.key();
+        let _ = writeln!(
+            output,
+            "requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+            escape_label_value(route),
+            escape_label_value(method),
+            status,
+            entry.value().load(Ordering::Relaxed)
+        );
     }
 
     output.push_str("# HELP upstream_errors_total Total upstream errors\n");
     output.push_str("# TYPE upstream_errors_total counter\n");
     for entry in UPSTREAM_ERRORS.iter() {
-        output.push_str(&format!(
-            "upstream_errors_total{{error_type=\"{}\"}} {}\n",
-            entry.key(),
+        let _ = writeln!(
+            output,
+            "upstream_errors_total{{error_type=\"{}\"}} {}",
+            escape_label_value(entry.key()),
             entry.value().load(Ordering::Relaxed)
-        ));
+        );
+    }
+
+    output.push_str("# HELP llm_tokens_total Tokens consumed as reported by upstream usage\n");
+    output.push_str("# TYPE llm_tokens_total counter\n");
+    for entry in TOKENS.iter() {
+        let (key, provider, model, kind) = entry.key();
+        let _ = writeln!(
+            output,
+            "llm_tokens_total{{key=\"{}\",provider=\"{}\",model=\"{}\",type=\"{}\"}} {}",
+            escape_label_value(key),
+            escape_label_value(provider),
+            escape_label_value(model),
+            kind,
+            entry.value().load(Ordering::Relaxed)
+        );
     }
 
+    output.push_str(
+        "# HELP llm_cost_usd_total Estimated cost in USD based on the configured price table\n",
+    );
+    output.push_str("# TYPE llm_cost_usd_total counter\n");
+    for entry in COST_USD.iter() {
+        let (key, provider, model) = entry.key();
+        let _ = writeln!(
+            output,
+            "llm_cost_usd_total{{key=\"{}\",provider=\"{}\",model=\"{}\"}} {}",
+            escape_label_value(key),
+            escape_label_value(provider),
+            escape_label_value(model),
+            f64::from_bits(entry.value().load(Ordering::Relaxed))
+        );
+    }
+
+    write_histograms(
+        &mut output,
+        "request_latency_seconds",
+        "Total request latency in seconds",
+        &REQUEST_LATENCY,
+    );
+    write_histograms(
+        &mut output,
+        "upstream_latency_seconds",
+        "Upstream request latency in seconds",
+        &UPSTREAM_LATENCY,
+    );
+    write_histograms(
+        &mut output,
+        "stream_ttfb_seconds",
+        "Time to first upstream byte for streaming requests in seconds",
+        &STREAM_TTFB,
+    );
+
     output.push_str("# HELP active_connections Active connections\n");
     output.push_str("# TYPE active_connections gauge\n");
     output.push_str(&format!(
@@ -91,12 +480,116 @@ pub fn gather_metrics() -> Result<String, String> {
         RATE_LIMITER_BUCKETS.load(Ordering::Relaxed)
     ));
 
+    output.push_str(
+        "# HELP hedged_requests_total Requests that sent a hedged duplicate, by winning request\n",
+    );
+    output.push_str("# TYPE hedged_requests_total counter\n");
+    for entry in HEDGED_REQUESTS.iter() {
+        let (route, winner) = entry.key();
+        let _ = writeln!(
+            output,
+            "hedged_requests_total{{route=\"{}\",winner=\"{}\"}} {}",
+            escape_label_value(route),
+            winner,
+            entry.value().load(Ordering::Relaxed)
+        );
+    }
+
+    output.push_str("# HELP response_cache_lookups_total Response cache lookups by result\n");
+    output.push_str("# TYPE response_cache_lookups_total counter\n");
+    for entry in RESPONSE_CACHE_LOOKUPS.iter() {
+        let (route, result) = entry.key();
+        let _ = writeln!(
+            output,
+            "response_cache_lookups_total{{route=\"{}\",result=\"{}\"}} {}",
+            escape_label_value(route),
+            result,
+            entry.value().load(Ordering::Relaxed)
+        );
+    }
+    output.push_str("# HELP response_cache_entries Responses held in the response cache\n");
+    output.push_str("# TYPE response_cache_entries gauge\n");
+    let _ = writeln!(
+        output,
+        "response_cache_entries {}",
+        RESPONSE_CACHE_ENTRIES.load(Ordering::Relaxed)
+    );
+    output.push_str("# HELP response_cache_bytes Body bytes held in the response cache\n");
+    output.push_str("# TYPE response_cache_bytes gauge\n");
+    let _ = writeln!(
+        output,
+        "response_cache_bytes {}",
+        RESPONSE_CACHE_BYTES.load(Ordering::Relaxed)
+    );
+
+    output.push_str("# HELP config_reloads_total Config reload attempts by result\n");
+    output.push_str("# TYPE config_reloads_total counter\n");
+    let _ = writeln!(
+        output,
+        "config_reloads_total{{result=\"success\"}} {}",
+        CONFIG_RELOADS_SUCCESS.load(Ordering::Relaxed)
+    );
+    let _ = writeln!(
+        output,
+        "config_reloads_total{{result=\"failure\"}} {}",
+        CONFIG_RELOADS_FAILURE.load(Ordering::Relaxed)
+    );
+    output.push_str(
+        "# HELP config_last_reload_timestamp_seconds Unix time of the last successful config load\n",
+    );
+    output.push_str("# TYPE config_last_reload_timestamp_seconds gauge\n");
+    let _ = writeln!(
+        output,
+        "config_last_reload_timestamp_seconds {}",
+        CONFIG_LAST_RELOAD_SECONDS.load(Ordering::Relaxed)
+    );
+    output.push_str(
+        "# HELP config_last_reload_success Whether the last config load attempt succeeded\n",
+    );
+    output.push_str("# TYPE config_last_reload_success gauge\n");
+    let _ = writeln!(
+        output,
+        "config_last_reload_success {}",
+        CONFIG_LAST_RELOAD_SUCCESS.load(Ordering::Relaxed)
+    );
+
+    output.push_str(
+        "# HELP circuit_breaker_state Circuit breaker state (0=closed, 1=open, 2=half_open)\n",
+    );
+    output.push_str("# TYPE circuit_breaker_state gauge\n");
+    for entry in CIRCUIT_BREAKER_STATE.iter() {
+        let (upstream, route) = entry.key();
+        let _ = writeln!(
+            output,
+            "circuit_breaker_state{{upstream=\"{}\",route=\"{}\"}} {}",
+            escape_label_value(upstream),
+            escape_label_value(route),
+            entry.value().load(Ordering::Relaxed)
+        );
+    }
+
+    output.push_str(
+        "# HELP circuit_breaker_rejections_total Requests rejected by an open circuit breaker\n",
+    );
+    output.push_str("# TYPE circuit_breaker_rejections_total counter\n");
+    for entry in CIRCUIT_BREAKER_REJECTIONS.iter() {
+        let (upstream, route) = entry.key();
+        let _ = writeln!(
+            output,
+            "circuit_breaker_rejections_total{{upstream=\"{}\",route=\"{}\"}} {}",
+            escape_label_value(upstream),
+            escape_label_value(route),
+            entry.value().load(Ordering::Relaxed)
+        );
+    }
+
     Ok(output)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serial_test::serial;
 
     #[test]
     fn record_request_increments_counter() {
@@ -112,6 +605,18 @@ mod tests {
         assert!(metrics.contains("upstream_errors_total"));
     }
 
+    #[test]
+    fn circuit_breaker_metrics_are_exported() {
+        set_circuit_breaker_state("metrics-test", "/v1/chat", 1);
+        record_circuit_breaker_rejection("metrics-test", "/v1/chat");
+        let metrics = gather_metrics().unwrap();
+        assert!(metrics
+            .contains("circuit_breaker_state{upstream=\"metrics-test\",route=\"/v1/chat\"} 1"));
+        assert!(metrics.contains(
+            "circuit_breaker_rejections_total{upstream=\"metrics-test\",route=\"/v1/chat\"} 1"
+        ));
+    }
+
     #[test]
     fn connection_guard_updates_active_connections() {
         let before = ACTIVE_CONNECTIONS.load(Ordering::Relaxed);
@@ -121,4 +626,120 @@ mod tests {
         }
         assert_eq!(ACTIVE_CONNECTIONS.load(Ordering::Relaxed), before);
     }
+
+    #[test]
+    #[serial]
+    fn request_latency_histogram_is_cumulative() {
+        observe_request_latency("/hist-test", "openai", "gpt-4o", 200, 0.003);
+        observe_request_latency("/hist-test", "openai", "gpt-4o", 200, 0.2);
+        observe_request_latency("/hist-test", "openai", "gpt-4o", 200, 30.0);
+        let metrics = gather_metrics().unwrap();
+        let labels = r#"route="/hist-test",provider="openai",model="gpt-4o",status="200""#;
+        assert!(metrics.contains(&format!(
+            "request_latency_seconds_bucket{{{},le=\"0.001\"}} 0",
+            labels
+        )));
+        assert!(metrics.contains(&format!(
+            "request_latency_seconds_bucket{{{},le=\"0.005\"}} 1",
+            labels
+        )));
+        assert!(metrics.contains(&format!(
+            "request_latency_seconds_bucket{{{},le=\"10.0\"}} 2",
+            labels
+        )));
+        assert!(metrics.contains(&format!(
+            "request_latency_seconds_bucket{{{},le=\"+Inf\"}} 3",
+            labels
+        )));
+        assert!(metrics.contains(&format!("request_latency_seconds_count{{{}}} 3", labels)));
+    }
+
+    #[test]
+    #[serial]
+    fn upstream_and_ttfb_histograms_are_exported() {
+        observe_upstream_latency("/v1/embeddings", "qwen", "text-embedding-v2", 502, 0.5);
+        observe_stream_ttfb("/v1/chat/completions", "qwen", "qwen-max", 200, 0.12);
+        let metrics = gather_metrics().unwrap();
+        assert!(metrics.contains("# TYPE upstream_latency_seconds histogram"));
+        assert!(metrics.contains(
+            r#"upstream_latency_seconds_count{route="/v1/embeddings",provider="qwen",model="text-embedding-v2",status="502"} 1"#
+        ));
+        assert!(metrics.contains(
+            r#"stream_ttfb_seconds_count{route="/v1/chat/completions",provider="qwen",model="qwen-max",status="200"} 1"#
+        ));
+    }
+
+    #[test]
+    fn token_usage_and_cost_are_accumulated() {
+        record_token_usage("team-a", "openai", "usage-test-model", 100, 40);
+        record_token_usage("team-a", "openai", "usage-test-model", 20, 10);
+        record_estimated_cost("team-a", "openai", "usage-test-model", 0.25);
+        record_estimated_cost("team-a", "openai", "usage-test-model", 0.5);
+        let metrics = gather_metrics().unwrap();
+        assert!(metrics.contains(
+            r#"llm_tokens_total{key="team-a",provider="openai",model="usage-test-model",type="prompt"} 120"#
+        ));
+        assert!(metrics.contains(
+            r#"llm_tokens_total{key="team-a",provider="openai",model="usage-test-model",type="completion"} 50"#
+        ));
+        assert!(metrics.contains(
+            r#"llm_cost_usd_total{key="team-a",provider="openai",model="usage-test-model"} 0.75"#
+        ));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        record_request("/evil\"}\nfake_metric 1\\", "GET", 404);
+        let metrics = gather_metrics().unwrap();
+        assert!(metrics.contains(
+            r#"requests_total{route="/evil\"}\nfake_metric 1\\",method="GET",status="404"} 1"#
+        ));
+        assert!(!metrics.lines().any(|line| line.starts_with("fake_metric")));
+    }
+
+    #[test]
+    fn route_labels_may_contain_colons() {
+        record_request("/a:b", "GET", 404);
+        let metrics = gather_metrics().unwrap();
+        assert!(metrics.contains(r#"requests_total{route="/a:b",method="GET",status="404"} 1"#));
+    }
+
+    #[test]
+    #[serial]
+    fn changing_buckets_resets_latency_series() {
+        observe_request_latency("/bucket-test", "openai", "gpt-4o", 200, 0.2);
+        configure_latency_buckets(Some(&[0.3, 3.0]));
+        let metrics = gather_metrics().unwrap();
+        assert!(!metrics.contains("route=\"/bucket-test\""));
+
+        observe_request_latency("/bucket-test", "openai", "gpt-4o", 200, 0.2);
+        configure_latency_buckets(Some(&[3.0, 0.3]));
+        let metrics = gather_metrics().unwrap();
+        let labels = r#"route="/bucket-test",provider="openai",model="gpt-4o",status="200""#;
+        assert!(metrics.contains(&format!(
+            "request_latency_seconds_bucket{{{},le=\"0.3\"}} 1",
+            labels
+        )));
+        assert!(!metrics.contains(&format!(
+            "request_latency_seconds_bucket{{{},le=\"0.001\"}}",
+            labels
+        )));
+
+        configure_latency_buckets(None);
+        let metrics = gather_metrics().unwrap();
+        assert!(!metrics.contains("route=\"/bucket-test\""));
+    }
+
+    #[test]
+    fn configured_buckets_are_sorted_and_sanitized() {
+        assert_eq!(
+            sanitize_buckets(Some(&[2.0, f64::NAN, 0.5, -1.0, 0.5])),
+            vec![0.5, 2.0]
+        );
+        assert_eq!(
+            sanitize_buckets(Some(&[])),
+            DEFAULT_LATENCY_BUCKETS.to_vec()
+        );
+        assert_eq!(sanitize_buckets(None), DEFAULT_LATENCY_BUCKETS.to_vec());
+    }
 }
@@ -18,6 +18,9 @@ pub struct ChatCompletionRequest {
     /// 是否启用流式响应
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    /// 流式响应选项，如 `include_usage`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<Value>,
     /// 最大生成 token 数
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
@@ -65,7 +68,8 @@ pub struct Choice {
 pub struct Usage {
     /// 提示词 token 数
     pub prompt_tokens: u32,
-    /// 生成的 token 数
+    /// 生成的 token 数，embeddings 响应中没有该字段
+    #[serde(default)]
     pub completion_tokens: u32,
     /// 总 token 数
     pub total_tokens: u32,
@@ -96,6 +100,9 @@ pub struct CompletionRequest {
     /// 是否启用流式响应
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    /// 流式响应选项，如 `include_usage`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<Value>,
     /// 日志概率数量
     #[serde(skip_serializing_if = "Option::is_none")]
     pub logprobs: Option<u32>,
@@ -219,6 +226,7 @@ mod tests {
             }],
             temperature: Some(0.7),
             stream: Some(true),
+            stream_options: None,
             max_tokens: Some(100),
         };
 
@@ -241,6 +249,7 @@ mod tests {
             }],
             temperature: None,
             stream: None,
+            stream_options: None,
             max_tokens: None,
         };
 
@@ -296,6 +305,7 @@ mod tests {
             top_p: None,
             n: None,
             stream: None,
+            stream_options: None,
             logprobs: None,
             echo: None,
             stop: None,
@@ -317,6 +327,7 @@ mod tests {
             top_p: None,
             n: None,
             stream: None,
+            stream_options: None,
             logprobs: None,
             echo: None,
             stop: None,
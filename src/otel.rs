@@ -0,0 +1,826 @@
+//! OpenTelemetry 追踪导出模块
+//!
+//! 提供一个 tracing-subscriber Layer，将 span 以 OTLP/HTTP（JSON 编码）批量导出到收集器，
+//! 并实现 W3C `traceparent` 的解析与生成：入站请求的 `traceparent` 会成为 `http_request`
+//! span 的远程父节点，上游请求头中注入当前 span 的上下文，使路由器加入分布式链路。
+//!
+//! 通过环境变量启用：
+//! - `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`：完整的 traces 端点 URL
+//! - `OTEL_EXPORTER_OTLP_ENDPOINT`：收集器基础 URL，自动追加 `/v1/traces`
+//! - `OTEL_SERVICE_NAME`：服务名，默认 `api-router`
+
+use crate::errors::{RouterError, RouterResult};
+use crate::http_client::send_direct_request;
+use crate::timeout::Timeouts;
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{warn, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::{LookupSpan, Registry};
+use tracing_subscriber::Layer;
+
+/// 默认服务名
+const DEFAULT_SERVICE_NAME: &str = "api-router";
+/// 导出队列上限，超过后丢弃新 span，避免收集器不可用时内存无限增长
+const MAX_QUEUED_SPANS: usize = 4096;
+/// 后台导出间隔
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// 单次导出请求的超时，收集器不可用时不会长时间阻塞导出线程或停机流程
+const EXPORT_TIMEOUTS: Timeouts = Timeouts {
+    connect: Some(Duration::from_secs(1)),
+    tls_handshake: Some(Duration::from_secs(1)),
+    first_byte: Some(Duration::from_secs(2)),
+    idle_read: Some(Duration::from_secs(1)),
+    total: Some(Duration::from_secs(3)),
+};
+/// 记录入站 `traceparent` 的 span 字段名
+pub const TRACEPARENT_FIELD: &str = "traceparent";
+
+/// W3C Trace Context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// 生成 `traceparent` 头的值（版本 00）
+    pub fn to_traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            hex(&self.trace_id),
+            hex(&self.span_id),
+            u8::from(self.sampled)
+        )
+    }
+}
+
+/// 解析 `traceparent` 头
+///
+/// 只接受版本 00 的格式，trace-id 与 parent-id 不能全为零
+pub fn parse_traceparent(value: &str) -> Option<TraceContext> {
+    let mut parts = value.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let span_id = parts.next()?;
+    let flags = parts.next()?;
+    if version != "00" || parts.next().is_some() {
+        return None;
+    }
+
+    let trace_id: [u8; 16] = decode_hex(trace_id)?.try_into().ok()?;
+    let span_id: [u8; 8] = decode_hex(span_id)?.try_into().ok()?;
+    let flags: [u8; 1] = decode_hex(flags)?.try_into().ok()?;
+    if trace_id.iter().all(|b| *b == 0) || span_id.iter().all(|b| *b == 0) {
+        return None;
+    }
+
+    Some(TraceContext {
+        trace_id,
+        span_id,
+        sampled: flags[0] & 0x01 == 0x01,
+    })
+}
+
+/// 返回当前 span 的 `traceparent`
+///
+/// 仅在安装了 [`OtlpLayer`] 时可用，否则返回 None
+pub fn current_traceparent() -> Option<String> {
+    tracing::Span::current()
+        .with_subscriber(|(id, dispatch)| {
+            let registry = dispatch.downcast_ref::<Registry>()?;
+            let span = registry.span(id)?;
+            let extensions = span.extensions();
+            let data = extensions.get::<SpanData>()?;
+            Some(data.context.to_traceparent())
+        })
+        .flatten()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    // W3C 规范要求小写十六进制
+    if value.bytes().any(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn random_trace_id() -> [u8; 16] {
+    loop {
+        let id = fastrand::u128(..).to_be_bytes();
+        if id.iter().any(|b| *b != 0) {
+            return id;
+        }
+    }
+}
+
+fn random_span_id() -> [u8; 8] {
+    fastrand::u64(1..).to_be_bytes()
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
+
+/// 导出器配置
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// traces 端点完整 URL
+    pub endpoint: String,
+    /// 服务名
+    pub service_name: String,
+}
+
+impl OtlpConfig {
+    /// 从环境变量读取配置，未配置端点时返回 None
+    pub fn from_env() -> Option<Self> {
+        let endpoint = env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .or_else(|| {
+                env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                    .ok()
+                    .filter(|value| !value.trim().is_empty())
+                    .map(|base| format!("{}/v1/traces", base.trim_end_matches('/')))
+            })?;
+        let service_name = env::var("OTEL_SERVICE_NAME")
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string());
+        Some(Self {
+            endpoint,
+            service_name,
+        })
+    }
+}
+
+/// 已结束、等待导出的 span
+#[derive(Debug, Clone)]
+struct FinishedSpan {
+    name: &'static str,
+    context: TraceContext,
+    parent_span_id: Option<[u8; 8]>,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, AttributeValue)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum AttributeValue {
+    String(String),
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+}
+
+impl AttributeValue {
+    fn to_otlp(&self) -> Value {
+        match self {
+            AttributeValue::String(value) => json!({ "stringValue": value }),
+            AttributeValue::Int(value) => json!({ "intValue": value.to_string() }),
+            AttributeValue::Double(value) => json!({ "doubleValue": value }),
+            AttributeValue::Bool(value) => json!({ "boolValue": value }),
+        }
+    }
+}
+
+/// 由 [`layer_from_env`] 创建的全局导出器
+static EXPORTER: OnceCell<Arc<OtlpExporter>> = OnceCell::new();
+
+thread_local! {
+    /// 当前线程是否正在发送导出请求，期间新建的 span（如 `upstream_attempt`）不再导出，
+    /// 避免导出器每个周期都把自身的请求排入下一次导出
+    static EXPORTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 导出期间置位 [`EXPORTING`]，离开作用域时复原
+struct ExportGuard {
+    previous: bool,
+}
+
+impl ExportGuard {
+    fn enter() -> Self {
+        Self {
+            previous: EXPORTING.with(|flag| flag.replace(true)),
+        }
+    }
+}
+
+impl Drop for ExportGuard {
+    fn drop(&mut self) {
+        EXPORTING.with(|flag| flag.set(self.previous));
+    }
+}
+
+/// OTLP/HTTP 导出器
+///
+/// 在内存队列中缓存已结束的 span，由后台线程或 [`OtlpExporter::flush`] 批量发送
+pub struct OtlpExporter {
+    config: OtlpConfig,
+    queue: Mutex<Vec<FinishedSpan>>,
+    timeouts: Timeouts,
+}
+
+impl OtlpExporter {
+    pub fn new(config: OtlpConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            queue: Mutex::new(Vec::new()),
+            timeouts: EXPORT_TIMEOUTS,
+        })
+    }
+
+    /// 启动后台导出线程
+    pub fn spawn_background(self: &Arc<Self>) {
+        let exporter = Arc::clone(self);
+        let _ = thread::Builder::new()
+            .name("otlp-exporter".to_string())
+            .spawn(move || loop {
+                thread::sleep(EXPORT_INTERVAL);
+                if let Err(err) = exporter.flush() {
+                    warn!("OTLP trace export failed: {}", err);
+                }
+            });
+    }
+
+    fn enqueue(&self, span: FinishedSpan) {
+        if let Ok(mut queue) = self.queue.lock() {
+            if queue.len() < MAX_QUEUED_SPANS {
+                queue.push(span);
+            }
+        }
+    }
+
+    /// 立即发送队列中的全部 span
+    ///
+    /// 会阻塞当前线程，不要在异步任务中调用
+    pub fn flush(&self) -> RouterResult<()> {
+        let spans = match self.queue.lock() {
+            Ok(mut queue) => std::mem::take(&mut *queue),
+            Err(_) => return Ok(()),
+        };
+        if spans.is_empty() {
+            return Ok(());
+        }
+
+        let body = serde_json::to_vec(&self.build_payload(&spans))?;
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        let _guard = ExportGuard::enter();
+        // 收集器流量直连，不占用上游连接池，也不经由为上游配置的出站代理
+        let response = smol::block_on(send_direct_request(
+            &self.config.endpoint,
+            "POST",
+            &headers,
+            Some(&body),
+            &self.timeouts,
+        ))?;
+        if !response.is_success() {
+            return Err(RouterError::Upstream(format!(
+                "OTLP collector returned status {}",
+                response.status
+            )));
+        }
+        Ok(())
+    }
+
+    fn build_payload(&self, spans: &[FinishedSpan]) -> Value {
+        let spans: Vec<Value> = spans.iter().map(span_to_otlp).collect();
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{
+                        "key": "service.name",
+                        "value": { "stringValue": self.config.service_name },
+                    }]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": DEFAULT_SERVICE_NAME },
+                    "spans": spans,
+                }]
+            }]
+        })
+    }
+}
+
+fn span_to_otlp(span: &FinishedSpan) -> Value {
+    // SpanKind：1 = INTERNAL，2 = SERVER，3 = CLIENT
+    let kind = if span.name == "http_request" {
+        2
+    } else if span.name.starts_with("upstream") {
+        3
+    } else {
+        1
+    };
+    let is_error = span.attributes.iter().any(|(key, value)| {
+        *key == "status_code" && matches!(value, AttributeValue::Int(code) if *code >= 500)
+    });
+    let attributes: Vec<Value> = span
+        .attributes
+        .iter()
+        .map(|(key, value)| json!({ "key": key, "value": value.to_otlp() }))
+        .collect();
+
+    let mut otlp = json!({
+        "traceId": hex(&span.context.trace_id),
+        "spanId": hex(&span.context.span_id),
+        "name": span.name,
+        "kind": kind,
+        "startTimeUnixNano": unix_nanos(span.start).to_string(),
+        "endTimeUnixNano": unix_nanos(span.end).to_string(),
+        "attributes": attributes,
+        // StatusCode：0 = UNSET，2 = ERROR
+        "status": { "code": if is_error { 2 } else { 0 } },
+    });
+    if let Some(parent) = span.parent_span_id {
+        otlp["parentSpanId"] = Value::String(hex(&parent));
+    }
+    otlp
+}
+
+/// 存放在 span 扩展中的追踪数据
+struct SpanData {
+    context: TraceContext,
+    parent_span_id: Option<[u8; 8]>,
+    start: SystemTime,
+    attributes: Vec<(&'static str, AttributeValue)>,
+}
+
+/// span 字段收集器
+struct AttributeVisitor<'a> {
+    attributes: &'a mut Vec<(&'static str, AttributeValue)>,
+    traceparent: Option<TraceContext>,
+}
+
+impl AttributeVisitor<'_> {
+    fn set(&mut self, field: &Field, value: AttributeValue) {
+        if let Some(existing) = self
+            .attributes
+            .iter_mut()
+            .find(|(key, _)| *key == field.name())
+        {
+            existing.1 = value;
+        } else {
+            self.attributes.push((field.name(), value));
+        }
+    }
+}
+
+impl Visit for AttributeVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == TRACEPARENT_FIELD {
+            self.traceparent = parse_traceparent(value);
+            return;
+        }
+        self.set(field, AttributeValue::String(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, AttributeValue::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(
+            field,
+            AttributeValue::Int(i64::try_from(value).unwrap_or(i64::MAX)),
+        );
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field, AttributeValue::Double(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, AttributeValue::Bool(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let value = format!("{:?}", value);
+        if field.name() == TRACEPARENT_FIELD {
+            self.traceparent = parse_traceparent(value.trim_matches('"'));
+            return;
+        }
+        self.set(field, AttributeValue::String(value));
+    }
+}
+
+/// 将 span 导出为 OTLP 的 tracing-subscriber Layer
+pub struct OtlpLayer {
+    exporter: Arc<OtlpExporter>,
+}
+
+impl OtlpLayer {
+    pub fn new(exporter: Arc<OtlpExporter>) -> Self {
+        Self { exporter }
+    }
+}
+
+/// 根据环境变量创建 OTLP Layer 并启动后台导出线程，未配置时返回 None
+pub fn layer_from_env() -> Option<OtlpLayer> {
+    let config = OtlpConfig::from_env()?;
+    let exporter = OtlpExporter::new(config);
+    exporter.spawn_background();
+    let _ = EXPORTER.set(Arc::clone(&exporter));
+    Some(OtlpLayer::new(exporter))
+}
+
+/// 立即发送全局导出器队列中的 span，未启用时不做任何事；会阻塞当前线程
+pub fn flush_pending() {
+    if let Some(exporter) = EXPORTER.get() {
+        if let Err(err) = exporter.flush() {
+            warn!("OTLP trace export failed: {}", err);
+        }
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if EXPORTING.with(Cell::get) {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let parent = span.parent().and_then(|parent| {
+            parent
+                .extensions()
+                .get::<SpanData>()
+                .map(|data| data.context)
+        });
+
+        let mut attributes = Vec::new();
+        let mut visitor = AttributeVisitor {
+            attributes: &mut attributes,
+            traceparent: None,
+        };
+        attrs.record(&mut visitor);
+        let remote = visitor.traceparent;
+
+        let (trace_id, parent_span_id, sampled) = match (parent, remote) {
+            (Some(parent), _) => (parent.trace_id, Some(parent.span_id), parent.sampled),
+            (None, Some(remote)) => (remote.trace_id, Some(remote.span_id), remote.sampled),
+            (None, None) => (random_trace_id(), None, true),
+        };
+
+        span.extensions_mut().insert(SpanData {
+            context: TraceContext {
+                trace_id,
+                span_id: random_span_id(),
+                sampled,
+            },
+            parent_span_id,
+            start: SystemTime::now(),
+            attributes,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(data) = extensions.get_mut::<SpanData>() else {
+            return;
+        };
+
+        let mut visitor = AttributeVisitor {
+            attributes: &mut data.attributes,
+            traceparent: None,
+        };
+        values.record(&mut visitor);
+
+        // 入站 traceparent 在请求解析后才可用，此时根 span 改为加入远程链路
+        if let Some(remote) = visitor.traceparent {
+            if data.parent_span_id.is_none() {
+                data.context.trace_id = remote.trace_id;
+                data.context.sampled = remote.sampled;
+                data.parent_span_id = Some(remote.span_id);
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+        if !data.context.sampled {
+            return;
+        }
+
+        self.exporter.enqueue(FinishedSpan {
+            name: span.name(),
+            context: data.context,
+            parent_span_id: data.parent_span_id,
+            start: data.start,
+            end: SystemTime::now(),
+            attributes: data.attributes,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const REMOTE_TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    /// 本地收集器桩：接收一个 HTTP 请求并返回其正文
+    fn spawn_collector() -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut buffer).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())
+                                .flatten()
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (format!("http://{}/v1/traces", addr), handle)
+    }
+
+    #[test]
+    fn parses_valid_traceparent() {
+        let context = parse_traceparent(REMOTE_TRACEPARENT).unwrap();
+        assert_eq!(hex(&context.trace_id), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(hex(&context.span_id), "00f067aa0ba902b7");
+        assert!(context.sampled);
+        assert_eq!(context.to_traceparent(), REMOTE_TRACEPARENT);
+    }
+
+    #[test]
+    fn rejects_invalid_traceparent() {
+        assert!(parse_traceparent("").is_none());
+        assert!(
+            parse_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").is_none()
+        );
+        assert!(
+            parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none()
+        );
+        assert!(
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01").is_none()
+        );
+        assert!(
+            parse_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").is_none()
+        );
+        assert!(
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7").is_none()
+        );
+    }
+
+    #[test]
+    fn unsampled_flag_is_preserved() {
+        let context =
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap();
+        assert!(!context.sampled);
+        assert!(context.to_traceparent().ends_with("-00"));
+    }
+
+    #[test]
+    fn current_traceparent_is_none_without_layer() {
+        let span = tracing::info_span!("http_request");
+        let _enter = span.enter();
+        assert!(current_traceparent().is_none());
+    }
+
+    #[test]
+    fn exports_spans_joined_to_remote_trace() {
+        let (endpoint, collector) = spawn_collector();
+        let exporter = OtlpExporter::new(OtlpConfig {
+            endpoint,
+            service_name: "router-test".to_string(),
+        });
+        let subscriber = tracing_subscriber::registry().with(OtlpLayer::new(Arc::clone(&exporter)));
+
+        let injected = tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!(
+                "http_request",
+                route = "/v1/chat/completions",
+                traceparent = tracing::field::Empty,
+                status_code = tracing::field::Empty,
+            );
+            root.record(TRACEPARENT_FIELD, REMOTE_TRACEPARENT);
+            let _root = root.enter();
+            let injected = {
+                let upstream = tracing::info_span!("upstream_request", provider = "openai");
+                let _upstream = upstream.enter();
+                current_traceparent().unwrap()
+            };
+            root.record("status_code", 200);
+            injected
+        });
+
+        assert!(injected.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(!injected.contains("00f067aa0ba902b7"));
+
+        exporter.flush().unwrap();
+        let request = collector.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1"));
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        let payload: Value = serde_json::from_str(body).unwrap();
+
+        let resource = &payload["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "router-test"
+        );
+        let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+        assert_eq!(spans.len(), 2);
+
+        let upstream = spans
+            .iter()
+            .find(|s| s["name"] == "upstream_request")
+            .unwrap();
+        let root = spans.iter().find(|s| s["name"] == "http_request").unwrap();
+        assert_eq!(root["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(root["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(root["kind"], 2);
+        assert_eq!(upstream["traceId"], root["traceId"]);
+        assert_eq!(upstream["parentSpanId"], root["spanId"]);
+        assert_eq!(upstream["kind"], 3);
+        assert_eq!(
+            injected,
+            format!(
+                "00-{}-{}-01",
+                root["traceId"].as_str().unwrap(),
+                upstream["spanId"].as_str().unwrap()
+            )
+        );
+        assert!(root["attributes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|attr| attr["key"] == "status_code" && attr["value"]["intValue"] == "200"));
+    }
+
+    #[test]
+    fn upstream_spans_are_exported_at_info_level() {
+        use crate::http_client::send_upstream_request;
+        use crate::timeout::Timeouts;
+        use tracing::Instrument;
+        use tracing_subscriber::filter::LevelFilter;
+
+        let (upstream_url, upstream) = spawn_collector();
+        let (endpoint, collector) = spawn_collector();
+        let exporter = OtlpExporter::new(OtlpConfig {
+            endpoint,
+            service_name: "router-test".to_string(),
+        });
+        let subscriber = tracing_subscriber::registry()
+            .with(OtlpLayer::new(Arc::clone(&exporter)).with_filter(LevelFilter::INFO));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("http_request");
+            let upstream_span = tracing::info_span!(parent: &root, "upstream_request");
+            smol::block_on(
+                send_upstream_request(
+                    &upstream_url,
+                    "POST",
+                    &HashMap::new(),
+                    Some(b"{}"),
+                    None,
+                    &Timeouts::default(),
+                )
+                .instrument(upstream_span),
+            )
+            .unwrap();
+        });
+        upstream.join().unwrap();
+
+        exporter.flush().unwrap();
+        let request = collector.join().unwrap();
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        let payload: Value = serde_json::from_str(body).unwrap();
+        let spans = payload["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        let span = |name: &str| spans.iter().find(|s| s["name"] == name).unwrap();
+        assert_eq!(
+            span("upstream_request")["parentSpanId"],
+            span("http_request")["spanId"]
+        );
+        assert_eq!(
+            span("upstream_attempt")["parentSpanId"],
+            span("upstream_request")["spanId"]
+        );
+    }
+
+    #[test]
+    fn exporter_requests_are_not_exported() {
+        let (endpoint, collector) = spawn_collector();
+        let exporter = OtlpExporter::new(OtlpConfig {
+            endpoint,
+            service_name: "router-test".to_string(),
+        });
+        let subscriber = tracing_subscriber::registry().with(OtlpLayer::new(Arc::clone(&exporter)));
+        tracing::subscriber::with_default(subscriber, || {
+            drop(tracing::info_span!("http_request"));
+            exporter.flush().unwrap();
+        });
+        collector.join().unwrap();
+
+        assert!(exporter.queue.lock().unwrap().is_empty());
+        assert!(!EXPORTING.with(Cell::get));
+    }
+
+    #[test]
+    fn flush_gives_up_on_unresponsive_collector() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let collector = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 4096];
+            // 读取请求后不响应，直到导出方超时断开连接
+            while stream.read(&mut buffer).map(|n| n > 0).unwrap_or(false) {}
+        });
+        let exporter = Arc::new(OtlpExporter {
+            config: OtlpConfig {
+                endpoint: format!("http://{}/v1/traces", addr),
+                service_name: "router-test".to_string(),
+            },
+            queue: Mutex::new(Vec::new()),
+            timeouts: Timeouts {
+                first_byte: Some(Duration::from_millis(50)),
+                ..EXPORT_TIMEOUTS
+            },
+        });
+        let subscriber = tracing_subscriber::registry().with(OtlpLayer::new(Arc::clone(&exporter)));
+        tracing::subscriber::with_default(subscriber, || drop(tracing::info_span!("http_request")));
+
+        let err = exporter.flush().unwrap_err();
+        assert!(err.is_timeout(), "unexpected error: {}", err);
+        collector.join().unwrap();
+    }
+
+    #[test]
+    fn unsampled_traces_are_not_exported() {
+        let exporter = OtlpExporter::new(OtlpConfig {
+            endpoint: "http://127.0.0.1:9/v1/traces".to_string(),
+            service_name: "router-test".to_string(),
+        });
+        let subscriber = tracing_subscriber::registry().with(OtlpLayer::new(Arc::clone(&exporter)));
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("http_request", traceparent = tracing::field::Empty);
+            root.record(
+                TRACEPARENT_FIELD,
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+            );
+        });
+        assert!(exporter.queue.lock().unwrap().is_empty());
+    }
+}
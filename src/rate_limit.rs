@@ -6,7 +6,7 @@ use crate::config::ApiConfig;
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 /// 速率限制配置
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -35,6 +35,19 @@ pub struct RateLimiterSnapshot {
     pub routes: HashMap<String, usize>,
 }
 
+/// 单个令牌桶的状态，用于管理接口
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketSnapshot {
+    /// 路由路径
+    pub route: String,
+    /// 客户端 API Key（原文，展示前需要脱敏）
+    pub api_key: String,
+    /// 当前可用令牌数
+    pub tokens: f64,
+    /// 速率限制配置
+    pub settings: RateLimitSettings,
+}
+
 /// 速率限制器
 ///
 /// 使用 DashMap 实现并发安全的令牌桶存储
@@ -61,7 +74,7 @@ struct TokenBucket {
 }
 
 /// 全局速率限制器单例
-pub static RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(|| RateLimiter::new());
+pub static RATE_LIMITER: Lazy<RateLimiter> = Lazy::new(RateLimiter::new);
 
 /// 从环境变量读取每分钟请求数限制
 fn env_requests_per_minute() -> Option<u32> {
@@ -160,6 +173,17 @@ impl RateLimiter {
         }
     }
 
+    /// 返回下一个令牌可用前需要等待的时长（不消耗令牌）
+    ///
+    /// 令牌桶尚不存在时视为立即可用
+    pub fn time_until_available(&self, route: &str, api_key: &str) -> Duration {
+        let key = (route.to_string(), api_key.to_string());
+        match self.buckets.get_mut(&key) {
+            Some(mut bucket) => bucket.time_until_token(Instant::now()),
+            None => Duration::ZERO,
+        }
+    }
+
     pub fn snapshot(&self) -> RateLimiterSnapshot {
         let mut routes: HashMap<String, usize> = HashMap::new();
         for entry in self.buckets.iter() {
@@ -174,6 +198,43 @@ impl RateLimiter {
     }
 }
 
+impl RateLimiter {
+    /// 列出令牌桶，按路由与 Key 排序；`filter` 接收（路由，API Key）
+    pub fn buckets(&self, filter: impl Fn(&str, &str) -> bool) -> Vec<BucketSnapshot> {
+        let now = Instant::now();
+        let mut buckets: Vec<BucketSnapshot> = self
+            .buckets
+            .iter()
+            .filter(|entry| filter(&entry.key().0, &entry.key().1))
+            .map(|entry| {
+                let ((route, api_key), bucket) = entry.pair();
+                BucketSnapshot {
+                    route: route.clone(),
+                    api_key: api_key.clone(),
+                    tokens: bucket.available(now),
+                    settings: bucket.settings.clone(),
+                }
+            })
+            .collect();
+        buckets.sort_by(|a, b| (&a.route, &a.api_key).cmp(&(&b.route, &b.api_key)));
+        buckets
+    }
+
+    /// 删除匹配的令牌桶，下次请求时以满桶重新创建；返回删除的数量
+    pub fn reset(&self, filter: impl Fn(&str, &str) -> bool) -> usize {
+        let before = self.buckets.len();
+        self.buckets
+            .retain(|(route, api_key), _| !filter(route, api_key));
+        before.saturating_sub(self.buckets.len())
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TokenBucket {
     fn new(settings: RateLimitSettings, now: Instant) -> Self {
         let capacity = settings.burst as f64;
@@ -210,6 +271,23 @@ impl TokenBucket {
         self.last_refill = now;
     }
 
+    /// 按当前时间补充后的令牌数，不修改令牌桶
+    fn available(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        (self.tokens + elapsed * self.refill_per_second).min(self.capacity)
+    }
+
+    fn time_until_token(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else if self.refill_per_second > 0.0 {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_second)
+        } else {
+            Duration::from_secs(60)
+        }
+    }
+
     fn try_consume(&mut self, now: Instant) -> Result<(), u64> {
         self.refill(now);
         if self.tokens >= 1.0 {
@@ -228,20 +306,15 @@ impl TokenBucket {
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
     use crate::config::{EndpointConfig, RateLimitConfig};
-    use std::collections::HashMap;
 
     fn base_config() -> ApiConfig {
         ApiConfig {
             base_url: String::new(),
-            headers: HashMap::new(),
-            model_mapping: None,
-            endpoints: HashMap::new(),
-            port: 8000,
-            rate_limit: None,
-            stream_config: None,
+            ..ApiConfig::default()
         }
     }
 
@@ -295,6 +368,31 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn lists_and_resets_buckets_for_a_key() {
+        let limiter = RateLimiter::new();
+        let settings = RateLimitSettings {
+            requests_per_minute: 1,
+            burst: 1,
+        };
+        let _ = limiter.check("/route/b", "client-a", &settings);
+        let _ = limiter.check("/route/a", "client-a", &settings);
+        let _ = limiter.check("/route/a", "client-b", &settings);
+
+        let buckets = limiter.buckets(|_, key| key == "client-a");
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].route, "/route/a");
+        assert!(buckets[0].tokens < 1.0);
+        assert_eq!(buckets[0].settings, settings);
+
+        assert_eq!(limiter.reset(|_, key| key == "client-a"), 2);
+        assert_eq!(limiter.buckets(|_, _| true).len(), 1);
+        assert_eq!(
+            limiter.check("/route/a", "client-a", &settings),
+            RateLimitDecision::Allowed
+        );
+    }
+
     #[test]
     fn snapshot_counts_routes() {
         let limiter = RateLimiter::new();
@@ -436,6 +534,27 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn time_until_available_reports_refill_delay() {
+        let limiter = RateLimiter::new();
+        let settings = RateLimitSettings {
+            requests_per_minute: 60,
+            burst: 1,
+        };
+
+        assert_eq!(
+            limiter.time_until_available("/v1/test", "client"),
+            Duration::ZERO
+        );
+        assert!(matches!(
+            limiter.check("/v1/test", "client", &settings),
+            RateLimitDecision::Allowed
+        ));
+        let wait = limiter.time_until_available("/v1/test", "client");
+        assert!(wait > Duration::ZERO);
+        assert!(wait <= Duration::from_secs(1));
+    }
+
     #[test]
     fn rate_limit_decision_equality() {
         assert_eq!(RateLimitDecision::Allowed, RateLimitDecision::Allowed);
@@ -0,0 +1,297 @@
+//! 配置热加载模块
+//!
+//! 后台线程监听配置文件所在目录的文件系统事件（Linux 上为 inotify），事件合并后检查配置文件及其覆盖层
+//! 是否变化；无法监听时退回定时轮询，监听模式下同样按轮询间隔做兜底检查。
+//! 新配置通过校验后才原子替换到 [`ArcCell`] 中，失败时继续使用上一份有效配置，
+//! 并在 [`reload_status`]、`/health` 与 `/metrics` 中报告重新加载次数、时间与错误
+
+use crate::access_log::format_rfc3339;
+use crate::config::{self, ConfigReloadConfig, ConfigReloadMode};
+use crate::errors::RouterError;
+use crate::metrics::record_config_reload;
+use crate::secrets;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// 收到文件系统事件后等待的时间，合并编辑器保存时产生的多个事件
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// 可原子替换的共享值
+///
+/// 读取方克隆 `Arc` 后立即释放锁，替换只在交换指针时短暂持有写锁，
+/// 正在处理的请求继续使用替换前的值
+pub struct ArcCell<T> {
+    inner: RwLock<Option<Arc<T>>>,
+}
+
+impl<T> ArcCell<T> {
+    /// 创建空的单元
+    pub const fn empty() -> Self {
+        Self {
+            inner: RwLock::new(None),
+        }
+    }
+
+    /// 当前值
+    pub fn load(&self) -> Option<Arc<T>> {
+        self.inner.read().expect("配置缓存损坏").clone()
+    }
+
+    /// 替换为新值，返回旧值
+    pub fn store(&self, value: Arc<T>) -> Option<Arc<T>> {
+        self.inner.write().expect("配置缓存损坏").replace(value)
+    }
+
+    /// 取出当前值并清空
+    pub fn take(&self) -> Option<Arc<T>> {
+        self.inner.write().expect("配置缓存损坏").take()
+    }
+}
+
+/// 变更检测方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchMode {
+    /// 未启动后台检测，每次读取配置时检查文件修改时间
+    OnDemand = 0,
+    /// 监听文件系统事件
+    Watch = 1,
+    /// 定时轮询
+    Poll = 2,
+    /// 不自动重新加载
+    Off = 3,
+}
+
+impl WatchMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            WatchMode::OnDemand => "on-demand",
+            WatchMode::Watch => "watch",
+            WatchMode::Poll => "poll",
+            WatchMode::Off => "off",
+        }
+    }
+}
+
+static WATCH_MODE: AtomicU8 = AtomicU8::new(WatchMode::OnDemand as u8);
+
+/// 当前的变更检测方式
+pub fn watch_mode() -> WatchMode {
+    match WATCH_MODE.load(Ordering::Relaxed) {
+        1 => WatchMode::Watch,
+        2 => WatchMode::Poll,
+        3 => WatchMode::Off,
+        _ => WatchMode::OnDemand,
+    }
+}
+
+fn set_watch_mode(mode: WatchMode) {
+    WATCH_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// 配置加载状态
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReloadStatus {
+    /// 当前生效配置的来源文件
+    pub source: Option<PathBuf>,
+    /// 成功重新加载的次数，不含启动时的首次加载
+    pub reloads: u64,
+    /// 重新加载失败的次数
+    pub failures: u64,
+    /// 最近一次成功加载的时间
+    pub last_reload: Option<SystemTime>,
+    /// 最近一次失败的时间与原因，之后成功加载时清空
+    pub last_error: Option<(SystemTime, String)>,
+}
+
+impl ReloadStatus {
+    /// `/health` 与管理接口使用的 JSON 表示
+    pub fn to_json(&self) -> Value {
+        json!({
+            "source": self.source.as_ref().map(|path| path.display().to_string()),
+            "watcher": watch_mode().as_str(),
+            "reloads": self.reloads,
+            "failures": self.failures,
+            "lastReload": self.last_reload.map(format_rfc3339),
+            "lastError": self.last_error.as_ref().map(|(at, message)| json!({
+                "at": format_rfc3339(*at),
+                "message": message,
+            })),
+        })
+    }
+}
+
+static STATUS: Lazy<Mutex<ReloadStatus>> = Lazy::new(|| Mutex::new(ReloadStatus::default()));
+
+/// 当前的配置加载状态
+pub fn reload_status() -> ReloadStatus {
+    STATUS.lock().expect("配置加载状态损坏").clone()
+}
+
+/// 记录一次成功的配置加载
+pub(crate) fn record_success(source: &Path, initial: bool) {
+    let now = SystemTime::now();
+    let mut status = STATUS.lock().expect("配置加载状态损坏");
+    status.source = Some(source.to_path_buf());
+    status.last_reload = Some(now);
+    status.last_error = None;
+    if !initial {
+        status.reloads += 1;
+    }
+    record_config_reload(true, initial, unix_seconds(now));
+}
+
+/// 记录一次失败的重新加载
+pub(crate) fn record_failure(err: &RouterError) {
+    let now = SystemTime::now();
+    let mut status = STATUS.lock().expect("配置加载状态损坏");
+    status.failures += 1;
+    status.last_error = Some((now, secrets::redact(&err.to_string()).into_owned()));
+    record_config_reload(false, false, unix_seconds(now));
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// 按配置启动后台变更检测线程，只应在启动时调用一次
+pub fn spawn_watcher(settings: Option<&ConfigReloadConfig>) {
+    let settings = settings.cloned().unwrap_or_default();
+    if settings.mode == ConfigReloadMode::Off {
+        set_watch_mode(WatchMode::Off);
+        info!("配置热加载已关闭");
+        return;
+    }
+    let interval = Duration::from_secs(settings.poll_interval_secs.max(1));
+    let (sender, receiver) = mpsc::channel();
+    let watcher = match settings.mode {
+        ConfigReloadMode::Auto => match event_watcher(&config::watched_directories(), sender) {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                warn!(
+                    "无法监听配置文件变更，改为每 {} 秒轮询: {}",
+                    interval.as_secs(),
+                    err
+                );
+                None
+            }
+        },
+        _ => None,
+    };
+    let mode = if watcher.is_some() {
+        WatchMode::Watch
+    } else {
+        WatchMode::Poll
+    };
+
+    let spawned = thread::Builder::new()
+        .name("config-watcher".to_string())
+        .spawn(move || {
+            // 监听器随线程存活
+            let _watcher = watcher;
+            loop {
+                match receiver.recv_timeout(interval) {
+                    Ok(()) => {
+                        thread::sleep(DEBOUNCE);
+                        while receiver.try_recv().is_ok() {}
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // 轮询模式下没有事件来源
+                    Err(RecvTimeoutError::Disconnected) => thread::sleep(interval),
+                }
+                config::reload_if_changed();
+            }
+        });
+    match spawned {
+        Ok(_) => {
+            set_watch_mode(mode);
+            info!(
+                mode = mode.as_str(),
+                interval_secs = interval.as_secs(),
+                "配置热加载已启动"
+            );
+        }
+        Err(err) => warn!("无法启动配置热加载线程，改为按请求检查: {}", err),
+    }
+}
+
+/// 监听目录中文件的创建、修改、删除与重命名事件
+fn event_watcher(
+    directories: &[PathBuf],
+    sender: Sender<()>,
+) -> notify::Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        // 读取配置文件本身产生的打开、关闭等访问事件不触发检查
+        if let Ok(event) = event {
+            if !event.kind.is_access() && !event.kind.is_other() {
+                let _ = sender.send(());
+            }
+        }
+    })?;
+    for directory in directories {
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    }
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arc_cell_swaps_without_affecting_readers() {
+        let cell = ArcCell::empty();
+        assert!(cell.load().is_none());
+        cell.store(Arc::new(1));
+        let held = cell.load().unwrap();
+        assert_eq!(cell.store(Arc::new(2)).as_deref(), Some(&1));
+        assert_eq!(*held, 1);
+        assert_eq!(cell.load().as_deref(), Some(&2));
+        assert_eq!(cell.take().as_deref(), Some(&2));
+        assert!(cell.load().is_none());
+    }
+
+    #[test]
+    fn event_watcher_reports_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("router.json");
+        std::fs::write(&path, "{}").unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let _watcher = event_watcher(&[dir.path().to_path_buf()], sender).unwrap();
+
+        std::fs::write(&path, r#"{"port": 9000}"#).unwrap();
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+
+        // 编辑器常见的“写临时文件再重命名”同样触发
+        while receiver.try_recv().is_ok() {}
+        let temp = dir.path().join(".router.json.swp");
+        std::fs::write(&temp, r#"{"port": 9100}"#).unwrap();
+        std::fs::rename(&temp, &path).unwrap();
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn status_json_reports_last_error() {
+        let status = ReloadStatus {
+            source: Some(PathBuf::from("transformer/openai.json")),
+            reloads: 2,
+            failures: 1,
+            last_reload: Some(UNIX_EPOCH + Duration::from_secs(60)),
+            last_error: Some((UNIX_EPOCH + Duration::from_secs(120), "bad".to_string())),
+        };
+        let json = status.to_json();
+        assert_eq!(json["source"], "transformer/openai.json");
+        assert_eq!(json["reloads"], 2);
+        assert_eq!(json["lastReload"], "1970-01-01T00:01:00.000Z");
+        assert_eq!(json["lastError"]["message"], "bad");
+    }
+}
@@ -0,0 +1,512 @@
+//! 录制重放模块
+//!
+//! `api-router replay <capture.jsonl>` 逐条读取录制记录，将录制的转发计划重新发送到
+//! 上游（可用 `--upstream` 指向本地 mock 服务），并与录制的响应做结构化比较。
+//! JSON 响应按字段比较，流式响应按 `data:` 事件逐条比较；`id`、`created` 等每次请求
+//! 都会变化的字段不参与比较。录制时被脱敏的请求头需要通过 `--header` 重新提供，否则不发送
+
+use crate::capture::{CaptureRecord, CapturedResponse, REDACTED};
+use crate::http_client::send_http_request;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+
+/// 每次请求都会变化、不参与比较的字段
+const VOLATILE_FIELDS: &[&str] = &[
+    "id",
+    "created",
+    "created_at",
+    "system_fingerprint",
+    "total_duration",
+    "load_duration",
+    "prompt_eval_duration",
+    "eval_duration",
+];
+
+/// 单条记录最多输出的差异数
+const MAX_DIFFS: usize = 20;
+
+/// 重放命令的用法说明
+pub const USAGE: &str = "用法: api-router replay <capture.jsonl> [--upstream <URL>] [--header \"Name: value\"]... [--request-id <ID>]";
+
+/// 重放选项
+#[derive(Debug, Clone, Default)]
+pub struct ReplayOptions {
+    /// 录制文件路径
+    pub file: String,
+    /// 替换录制 URL 中的 scheme://host:port，用于指向 mock 服务
+    pub upstream: Option<String>,
+    /// 补充或覆盖的请求头（小写名称）
+    pub headers: HashMap<String, String>,
+    /// 只重放指定 request_id 的记录
+    pub request_id: Option<String>,
+}
+
+impl ReplayOptions {
+    /// 解析 `replay` 子命令之后的参数
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--upstream" => {
+                    let value = iter.next().ok_or("--upstream 缺少参数")?;
+                    options.upstream = Some(value.trim_end_matches('/').to_string());
+                }
+                "--header" => {
+                    let value = iter.next().ok_or("--header 缺少参数")?;
+                    let (name, value) = value
+                        .split_once(':')
+                        .ok_or_else(|| format!("无效的请求头 '{}'，应为 \"Name: value\"", value))?;
+                    options
+                        .headers
+                        .insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+                }
+                "--request-id" => {
+                    let value = iter.next().ok_or("--request-id 缺少参数")?;
+                    options.request_id = Some(value.clone());
+                }
+                other if other.starts_with("--") => {
+                    return Err(format!("未知参数 '{}'", other));
+                }
+                other if options.file.is_empty() => options.file = other.to_string(),
+                other => return Err(format!("多余的参数 '{}'", other)),
+            }
+        }
+        if options.file.is_empty() {
+            return Err("缺少录制文件路径".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// 单条记录的重放结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayOutcome {
+    /// 响应与录制一致
+    Match,
+    /// 响应存在差异
+    Diff(Vec<String>),
+    /// 记录无法重放
+    Skipped(String),
+    /// 重放请求失败
+    Failed(String),
+}
+
+/// 执行 `replay` 子命令，返回进程退出码
+///
+/// 全部一致时返回 0，存在差异或失败时返回 1，参数或文件错误时返回 2
+pub fn run(args: &[String]) -> i32 {
+    let options = match ReplayOptions::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            return 2;
+        }
+    };
+    let contents = match fs::read_to_string(&options.file) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("无法读取录制文件 {}: {}", options.file, err);
+            return 2;
+        }
+    };
+
+    let (mut matched, mut differed, mut skipped, mut failed) = (0, 0, 0, 0);
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: CaptureRecord = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(err) => {
+                println!("[FAIL] line {}: 无法解析录制记录: {}", index + 1, err);
+                failed += 1;
+                continue;
+            }
+        };
+        if let Some(id) = &options.request_id {
+            if &record.request_id != id {
+                continue;
+            }
+        }
+
+        let label = format!("{} {}", record.request_id, record.route);
+        match smol::block_on(replay_record(&record, &options)) {
+            ReplayOutcome::Match => {
+                println!("[MATCH] {}", label);
+                matched += 1;
+            }
+            ReplayOutcome::Diff(diffs) => {
+                println!("[DIFF] {}", label);
+                for diff in diffs {
+                    println!("    {}", diff);
+                }
+                differed += 1;
+            }
+            ReplayOutcome::Skipped(reason) => {
+                println!("[SKIP] {}: {}", label, reason);
+                skipped += 1;
+            }
+            ReplayOutcome::Failed(reason) => {
+                println!("[FAIL] {}: {}", label, reason);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "{} matched, {} differed, {} skipped, {} failed",
+        matched, differed, skipped, failed
+    );
+    if differed > 0 || failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// 重放单条录制记录并与录制的响应比较
+pub async fn replay_record(record: &CaptureRecord, options: &ReplayOptions) -> ReplayOutcome {
+    if let Some(error) = &record.response.error {
+        return ReplayOutcome::Skipped(format!("录制时上游请求失败: {}", error));
+    }
+    let Some(body) = &record.plan.body else {
+        return ReplayOutcome::Skipped("请求体未录制（非 UTF-8 内容）".to_string());
+    };
+
+    let url = match &options.upstream {
+        Some(upstream) => rebase_url(&record.plan.url, upstream),
+        None => record.plan.url.clone(),
+    };
+    let headers = replay_headers(&record.plan.headers, &options.headers);
+
+    match send_http_request(&url, &record.plan.method, &headers, Some(body.as_bytes())).await {
+        Ok(actual) => {
+            let diffs = diff_response(&record.response, &String::from_utf8_lossy(&actual));
+            if diffs.is_empty() {
+                ReplayOutcome::Match
+            } else {
+                ReplayOutcome::Diff(diffs)
+            }
+        }
+        Err(err) => ReplayOutcome::Failed(err.to_string()),
+    }
+}
+
+/// 将 URL 的 scheme://host:port 替换为指定的上游地址，保留路径与查询参数
+pub fn rebase_url(url: &str, upstream: &str) -> String {
+    let path_start = url
+        .find("://")
+        .and_then(|scheme_end| {
+            url[scheme_end + 3..]
+                .find('/')
+                .map(|offset| scheme_end + 3 + offset)
+        })
+        .unwrap_or(url.len());
+    format!("{}{}", upstream.trim_end_matches('/'), &url[path_start..])
+}
+
+/// 构造重放请求头：脱敏的请求头用 `overrides` 中的值替换，未提供时不发送
+fn replay_headers(
+    recorded: &std::collections::BTreeMap<String, String>,
+    overrides: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut headers: HashMap<String, String> = recorded
+        .iter()
+        .filter(|(_, value)| value.as_str() != REDACTED)
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    headers.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+    headers
+}
+
+/// 比较录制的响应与重放得到的响应体，返回差异描述
+pub fn diff_response(recorded: &CapturedResponse, actual: &str) -> Vec<String> {
+    let mut diffs = Vec::new();
+    if recorded.stream {
+        let expected = stream_events(&recorded.stream_body());
+        let actual = stream_events(actual);
+        if expected.len() != actual.len() {
+            diffs.push(format!(
+                "event count: {} != {}",
+                expected.len(),
+                actual.len()
+            ));
+        }
+        for (index, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+            diff_values(&format!("event[{}]", index), expected, actual, &mut diffs);
+        }
+    } else {
+        let expected = body_value(recorded.body.as_deref().unwrap_or_default());
+        diff_values("$", &expected, &body_value(actual), &mut diffs);
+    }
+    diffs.truncate(MAX_DIFFS);
+    diffs
+}
+
+/// 去掉可能存在的 HTTP 响应头
+fn strip_http_head(text: &str) -> &str {
+    if text.starts_with("HTTP/") {
+        if let Some(pos) = text.find("\r\n\r\n") {
+            return &text[pos + 4..];
+        }
+    }
+    text
+}
+
+/// 提取流式响应中的事件：SSE 的 `data:` 行，或 NDJSON 的 JSON 行
+fn stream_events(text: &str) -> Vec<Value> {
+    let text = strip_http_head(text);
+    let data_lines: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("data:"))
+        .map(str::trim)
+        .collect();
+    let lines = if data_lines.is_empty() {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with('{'))
+            .collect()
+    } else {
+        data_lines
+    };
+    lines
+        .into_iter()
+        .map(|line| normalize(parse_lenient(line)))
+        .collect()
+}
+
+/// 解析非流式响应体，兼容分块传输编码残留的长度行
+fn body_value(text: &str) -> Value {
+    let text = strip_http_head(text).trim();
+    let value = serde_json::from_str(text).ok().or_else(|| {
+        let start = text.find('{')?;
+        let end = text.rfind('}')?;
+        (start < end)
+            .then(|| serde_json::from_str(&text[start..=end]).ok())
+            .flatten()
+    });
+    normalize(value.unwrap_or_else(|| Value::String(text.to_string())))
+}
+
+fn parse_lenient(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+/// 递归移除易变字段
+fn normalize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(key, _)| !VOLATILE_FIELDS.contains(&key.as_str()))
+                .map(|(key, value)| (key, normalize(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(normalize).collect()),
+        other => other,
+    }
+}
+
+fn diff_values(path: &str, expected: &Value, actual: &Value, diffs: &mut Vec<String>) {
+    if diffs.len() >= MAX_DIFFS {
+        return;
+    }
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected_value) in expected {
+                let child = format!("{}.{}", path, key);
+                match actual.get(key) {
+                    Some(actual_value) => diff_values(&child, expected_value, actual_value, diffs),
+                    None => diffs.push(format!("{}: missing in replay", child)),
+                }
+            }
+            for key in actual.keys().filter(|key| !expected.contains_key(*key)) {
+                diffs.push(format!("{}.{}: only in replay", path, key));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            if expected.len() != actual.len() {
+                diffs.push(format!(
+                    "{}: length {} != {}",
+                    path,
+                    expected.len(),
+                    actual.len()
+                ));
+            }
+            for (index, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+                diff_values(&format!("{}[{}]", path, index), expected, actual, diffs);
+            }
+        }
+        (expected, actual) if expected != actual => {
+            diffs.push(format!("{}: {} != {}", path, expected, actual));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{CapturedChunk, CapturedPlan, CapturedRequest};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// 本地上游桩：接收一个请求，返回固定响应，并返回收到的原始请求
+    fn spawn_upstream(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            while !String::from_utf8_lossy(&request).contains("\r\n\r\n{") {
+                let n = stream.read(&mut buffer).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..n]);
+            }
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            );
+            stream.write_all(reply.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (format!("http://{}", addr), handle)
+    }
+
+    fn record(response: CapturedResponse) -> CaptureRecord {
+        let mut headers = HashMap::new();
+        headers.insert("authorization".to_string(), "Bearer sk-live".to_string());
+        headers.insert("content-type".to_string(), "application/json".to_string());
+        CaptureRecord {
+            timestamp: "2024-01-01T00:00:00.000Z".to_string(),
+            request_id: "req-1".to_string(),
+            route: "/v1/chat/completions".to_string(),
+            request: CapturedRequest::new("POST", "/v1/chat/completions", &headers, b"{}"),
+            plan: CapturedPlan::new(
+                "POST",
+                "https://api.example.com/v1/chat/completions",
+                &headers,
+                br#"{"model":"m"}"#,
+            ),
+            response,
+        }
+    }
+
+    #[test]
+    fn parses_replay_arguments() {
+        let options = ReplayOptions::parse(&args(&[
+            "capture.jsonl",
+            "--upstream",
+            "http://127.0.0.1:9000/",
+            "--header",
+            "Authorization: Bearer test",
+        ]))
+        .unwrap();
+        assert_eq!(options.file, "capture.jsonl");
+        assert_eq!(options.upstream.as_deref(), Some("http://127.0.0.1:9000"));
+        assert_eq!(options.headers["authorization"], "Bearer test");
+
+        assert!(ReplayOptions::parse(&[]).is_err());
+        assert!(ReplayOptions::parse(&args(&["a", "--bogus"])).is_err());
+    }
+
+    #[test]
+    fn rebases_url_onto_mock_upstream() {
+        assert_eq!(
+            rebase_url(
+                "https://api.example.com/v1/chat/completions?x=1",
+                "http://127.0.0.1:9000"
+            ),
+            "http://127.0.0.1:9000/v1/chat/completions?x=1"
+        );
+    }
+
+    #[test]
+    fn json_diff_ignores_volatile_fields() {
+        let recorded = CapturedResponse {
+            body: Some(r#"{"id":"a","created":1,"choices":[{"text":"hi"}]}"#.to_string()),
+            ..CapturedResponse::default()
+        };
+        assert!(diff_response(
+            &recorded,
+            r#"{"id":"b","created":2,"choices":[{"text":"hi"}]}"#
+        )
+        .is_empty());
+        assert_eq!(
+            diff_response(&recorded, r#"{"choices":[{"text":"bye"}],"extra":true}"#),
+            vec![
+                r#"$.choices[0].text: "hi" != "bye""#.to_string(),
+                "$.extra: only in replay".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_diff_compares_data_events() {
+        let recorded = CapturedResponse {
+            stream: true,
+            chunks: vec![
+                CapturedChunk {
+                    offset_ms: 1.0,
+                    data: "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\ndata: {\"id\":\"1\",\"delta\":\"a\"}\n\n".to_string(),
+                },
+                CapturedChunk {
+                    offset_ms: 2.0,
+                    data: "data: [DONE]\n\n".to_string(),
+                },
+            ],
+            ..CapturedResponse::default()
+        };
+        assert!(diff_response(
+            &recorded,
+            "data: {\"id\":\"2\",\"delta\":\"a\"}\n\ndata: [DONE]\n\n"
+        )
+        .is_empty());
+        let diffs = diff_response(&recorded, "data: {\"delta\":\"b\"}\n\n");
+        assert_eq!(diffs[0], "event count: 2 != 1");
+        assert_eq!(diffs[1], r#"event[0].delta: "a" != "b""#);
+    }
+
+    #[test]
+    fn replays_against_mock_upstream_with_header_override() {
+        let (upstream, handle) = spawn_upstream(r#"{"id":"new","answer":42}"#);
+        let record = record(CapturedResponse {
+            body: Some(r#"{"id":"old","answer":42}"#.to_string()),
+            ..CapturedResponse::default()
+        });
+        let options = ReplayOptions {
+            upstream: Some(upstream),
+            headers: HashMap::from([("authorization".to_string(), "Bearer test".to_string())]),
+            ..ReplayOptions::default()
+        };
+
+        let outcome = smol::block_on(replay_record(&record, &options));
+        assert_eq!(outcome, ReplayOutcome::Match);
+
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions HTTP/1.1"));
+        assert!(request.contains("authorization: Bearer test"));
+        assert!(!request.contains(REDACTED));
+        assert!(request.ends_with(r#"{"model":"m"}"#));
+    }
+
+    #[test]
+    fn skips_records_that_failed_upstream() {
+        let record = record(CapturedResponse {
+            error: Some("Upstream error: timeout".to_string()),
+            ..CapturedResponse::default()
+        });
+        let outcome = smol::block_on(replay_record(&record, &ReplayOptions::default()));
+        assert!(matches!(outcome, ReplayOutcome::Skipped(_)));
+    }
+}
@@ -0,0 +1,773 @@
+//! 响应缓存模块
+//!
+//! 开启 `responseCache` 配置后，确定性请求（embeddings，以及 temperature 为 0 的对话、补全与 Messages 请求）
+//! 的成功响应以「提供商 + 路由 + 模型映射后的请求体」的 SHA-256 为键缓存在内存 LRU 中，
+//! 超过条目数或总字节数上限时淘汰最久未使用的条目。请求体由类型化结构重新序列化，
+//! 字段顺序与空白已经规范化，JSON 对象的键按字典序排列。
+//!
+//! 配置了 `path` 时新条目同时追加写入 JSONL 文件，启动或持久化路径变化时从文件恢复未过期的条目并压缩文件。
+//! 流式响应按转发给客户端的原始字节缓存，只缓存完整结束的流，命中时以 SSE 原样重放。
+//!
+//! 开启 `embeddingItems` 后 embeddings 改为按单条输入缓存，键为（提供商、映射后模型、dimensions、文本）的
+//! SHA-256，与整体响应共用同一个 LRU 与持久化文件
+
+use crate::config::ResponseCacheConfig;
+use crate::http_client::StreamObserver;
+use crate::metrics::update_response_cache_size;
+use crate::models::EmbeddingRequest;
+use once_cell::sync::Lazy;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 标记响应是否来自缓存的响应头，取值 `hit` 或 `miss`；逐条缓存的 embeddings 部分命中时为 `partial`
+pub const CACHE_HEADER: &str = "x-router-cache";
+
+/// 可缓存的路由
+pub const CACHEABLE_ROUTES: &[&str] = &[
+    "/v1/chat/completions",
+    "/v1/completions",
+    "/v1/embeddings",
+    "/v1/messages",
+];
+
+/// 流式响应正常结束的标记（OpenAI 与 Anthropic 格式）
+const STREAM_END_MARKERS: &[&str] = &["data: [DONE]", "event: message_stop"];
+
+/// 全局响应缓存
+static CACHE: Lazy<Mutex<ResponseCache>> = Lazy::new(|| Mutex::new(ResponseCache::default()));
+
+/// 客户端 `Cache-Control` 请求头对缓存的要求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheDirective {
+    /// 优先使用缓存，未命中时缓存上游响应
+    Default,
+    /// `no-cache` 或 `max-age=0`：不使用缓存，用上游响应刷新缓存
+    Refresh,
+    /// `no-store`：既不使用也不写入缓存
+    Bypass,
+}
+
+impl CacheDirective {
+    /// 解析 `Cache-Control` 请求头
+    pub fn from_header(value: Option<&str>) -> Self {
+        let mut directive = Self::Default;
+        for token in value.unwrap_or_default().split(',') {
+            let (name, argument) = match token.split_once('=') {
+                Some((name, argument)) => (name, Some(argument.trim().trim_matches('"'))),
+                None => (token, None),
+            };
+            match (name.trim().to_ascii_lowercase().as_str(), argument) {
+                ("no-store", _) => return Self::Bypass,
+                ("no-cache", _) | ("max-age", Some("0")) => directive = Self::Refresh,
+                _ => {}
+            }
+        }
+        directive
+    }
+}
+
+/// 缓存的响应
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    /// 是否为流式（SSE）响应
+    pub stream: bool,
+    /// JSON 响应体，或流式响应转发给客户端的原始字节
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    /// 是否为可以缓存的成功响应
+    ///
+    /// 非流式响应必须是不含 `error` 字段的 JSON 对象（上游状态码由调用方检查，只存储 2xx 响应）；
+    /// 流式响应的上游状态码必须为 2xx 且流已正常结束
+    pub fn is_cacheable(&self) -> bool {
+        let Ok(text) = std::str::from_utf8(&self.body) else {
+            return false;
+        };
+        if !self.stream {
+            return is_success_json(&self.body);
+        }
+        // 流式转发保留上游的状态行
+        if text.starts_with("HTTP/") {
+            let status = text.split_whitespace().nth(1).unwrap_or_default();
+            if !status.starts_with('2') {
+                return false;
+            }
+        }
+        STREAM_END_MARKERS
+            .iter()
+            .any(|marker| text.contains(marker))
+    }
+}
+
+/// 单个请求的缓存查找
+#[derive(Debug, Clone)]
+pub struct CacheLookup {
+    key: String,
+    directive: CacheDirective,
+}
+
+impl CacheLookup {
+    /// 按提供商、路由与模型映射后的请求体计算缓存键
+    pub fn new(provider: &str, route: &str, body: &[u8], cache_control: Option<&str>) -> Self {
+        Self {
+            key: cache_key(provider, route, body),
+            directive: CacheDirective::from_header(cache_control),
+        }
+    }
+
+    /// 客户端允许时返回缓存的响应
+    pub fn cached(&self) -> Option<Arc<CachedResponse>> {
+        if self.directive != CacheDirective::Default {
+            return None;
+        }
+        CACHE.lock().ok()?.get(&self.key, unix_now())
+    }
+
+    /// 客户端允许且响应可缓存时写入缓存
+    pub fn store(&self, response: CachedResponse) {
+        if self.directive == CacheDirective::Bypass || !response.is_cacheable() {
+            return;
+        }
+        if let Ok(mut cache) = CACHE.lock() {
+            if let Err(err) = cache.insert(&self.key, response, unix_now()) {
+                tracing::warn!("Failed to persist cached response: {}", err);
+            }
+        }
+    }
+}
+
+/// 流式响应缓冲器
+///
+/// 包装另一个观察者，在转发分块的同时按原始字节累积完整的流式响应
+pub struct StreamBuffer<'a> {
+    inner: &'a mut (dyn StreamObserver + Send),
+    body: Option<Vec<u8>>,
+}
+
+impl<'a> StreamBuffer<'a> {
+    /// `enabled` 为 false 时仅转发分块，不做缓冲
+    pub fn new(inner: &'a mut (dyn StreamObserver + Send), enabled: bool) -> Self {
+        Self {
+            inner,
+            body: enabled.then(Vec::new),
+        }
+    }
+
+    /// 返回缓冲的流式响应
+    pub fn into_response(self) -> Option<CachedResponse> {
+        self.body.map(|body| CachedResponse { stream: true, body })
+    }
+}
+
+impl StreamObserver for StreamBuffer<'_> {
+    fn on_chunk(&mut self, chunk: &[u8]) {
+        self.inner.on_chunk(chunk);
+        if let Some(body) = self.body.as_mut() {
+            body.extend_from_slice(chunk);
+        }
+    }
+}
+
+/// 逐条缓存的 embedding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedEmbedding {
+    pub embedding: Vec<f32>,
+    /// 该条输入分摊到的 prompt token 数
+    pub prompt_tokens: u32,
+}
+
+/// 按单条输入查找的 embedding 缓存
+#[derive(Debug, Clone)]
+pub struct EmbeddingCache {
+    provider: String,
+    model: String,
+    dimensions: Option<u32>,
+    directive: CacheDirective,
+}
+
+impl EmbeddingCache {
+    /// 当前配置开启了逐条缓存且请求可以逐条缓存时返回
+    ///
+    /// `request` 为模型映射后的请求；base64 编码的响应与 `Cache-Control: no-store` 的请求不逐条缓存
+    pub fn new(
+        provider: &str,
+        request: &EmbeddingRequest,
+        cache_control: Option<&str>,
+    ) -> Option<Self> {
+        let enabled = caches_embedding_items();
+        let float = request
+            .encoding_format
+            .as_deref()
+            .is_none_or(|format| format == "float");
+        let directive = CacheDirective::from_header(cache_control);
+        (enabled && float && directive != CacheDirective::Bypass).then(|| Self {
+            provider: provider.to_string(),
+            model: request.model.clone(),
+            dimensions: request.dimensions,
+            directive,
+        })
+    }
+
+    fn key(&self, text: &str) -> String {
+        let dimensions = self
+            .dimensions
+            .map(|dimensions| dimensions.to_string())
+            .unwrap_or_default();
+        digest_hex(&[
+            b"embedding",
+            self.provider.as_bytes(),
+            self.model.as_bytes(),
+            dimensions.as_bytes(),
+            text.as_bytes(),
+        ])
+    }
+
+    /// 客户端允许时返回缓存的 embedding
+    pub fn get(&self, text: &str) -> Option<CachedEmbedding> {
+        if self.directive != CacheDirective::Default {
+            return None;
+        }
+        let cached = CACHE.lock().ok()?.get(&self.key(text), unix_now())?;
+        serde_json::from_slice(&cached.body).ok()
+    }
+
+    /// 写入单条 embedding
+    pub fn put(&self, text: &str, item: &CachedEmbedding) {
+        let Ok(body) = serde_json::to_vec(item) else {
+            return;
+        };
+        let response = CachedResponse {
+            stream: false,
+            body,
+        };
+        if let Ok(mut cache) = CACHE.lock() {
+            if let Err(err) = cache.insert(&self.key(text), response, unix_now()) {
+                tracing::warn!("Failed to persist cached embedding: {}", err);
+            }
+        }
+    }
+}
+
+/// 可逐条缓存的 embeddings 输入：单个字符串或非空的字符串数组（token 数组不逐条缓存）
+pub fn embedding_inputs(input: &Value) -> Option<Vec<String>> {
+    match input {
+        Value::String(text) => Some(vec![text.clone()]),
+        Value::Array(items) if !items.is_empty() => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        _ => None,
+    }
+}
+
+/// 按文本长度把批量请求的 prompt token 数分摊到每条输入，各条之和等于 `total`
+pub fn split_tokens(total: u32, texts: &[&str]) -> Vec<u32> {
+    let weights: Vec<u64> = texts
+        .iter()
+        .map(|text| text.chars().count().max(1) as u64)
+        .collect();
+    let sum: u64 = weights.iter().sum();
+    let mut shares: Vec<u32> = weights
+        .iter()
+        .map(|weight| (u64::from(total) * weight / sum.max(1)) as u32)
+        .collect();
+    let remainder = total - shares.iter().sum::<u32>();
+    for share in shares.iter_mut().take(remainder as usize) {
+        *share += 1;
+    }
+    shares
+}
+
+/// 是否为不含 `error` 字段的 JSON 对象
+fn is_success_json(body: &[u8]) -> bool {
+    matches!(
+        serde_json::from_slice::<Value>(body),
+        Ok(Value::Object(object)) if !object.contains_key("error")
+    )
+}
+
+/// 当前配置是否按单条输入缓存 embeddings
+pub fn caches_embedding_items() -> bool {
+    CACHE
+        .lock()
+        .map(|cache| cache.caches_embedding_items())
+        .unwrap_or(false)
+}
+
+/// 应用缓存配置，持久化路径变化时重新从文件加载
+pub fn configure(config: Option<&ResponseCacheConfig>) {
+    if let Ok(mut cache) = CACHE.lock() {
+        cache.configure(config);
+    }
+}
+
+/// 当前配置是否缓存指定路由
+pub fn should_cache(route: &str) -> bool {
+    CACHE
+        .lock()
+        .map(|cache| cache.should_cache(route))
+        .unwrap_or(false)
+}
+
+/// 计算缓存键：提供商、路由与请求体的 SHA-256（十六进制）
+pub fn cache_key(provider: &str, route: &str, body: &[u8]) -> String {
+    digest_hex(&[provider.as_bytes(), route.as_bytes(), body])
+}
+
+/// 以 NUL 分隔各部分后计算 SHA-256（十六进制）
+fn digest_hex(parts: &[&[u8]]) -> String {
+    let mut input = Vec::with_capacity(parts.iter().map(|part| part.len() + 1).sum());
+    for (index, part) in parts.iter().enumerate() {
+        if index > 0 {
+            input.push(0);
+        }
+        input.extend_from_slice(part);
+    }
+    digest(&SHA256, &input)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// 持久化文件中的一行
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedEntry {
+    key: String,
+    /// 写入缓存的 Unix 时间（秒）
+    stored_at: u64,
+    stream: bool,
+    body: String,
+}
+
+struct Entry {
+    response: Arc<CachedResponse>,
+    stored_at: u64,
+    /// 最近一次访问的序号
+    used: u64,
+}
+
+/// 内存 LRU 缓存与持久化文件
+#[derive(Default)]
+struct ResponseCache {
+    config: Option<ResponseCacheConfig>,
+    entries: HashMap<String, Entry>,
+    /// 访问序号到缓存键，序号最小的为最久未使用的条目
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+    bytes: u64,
+    file: Option<File>,
+}
+
+impl ResponseCache {
+    fn configure(&mut self, config: Option<&ResponseCacheConfig>) {
+        if self.config.as_ref() == config {
+            return;
+        }
+        let path_changed = self.config.as_ref().and_then(|c| c.path.as_ref())
+            != config.and_then(|c| c.path.as_ref());
+        self.config = config.cloned();
+        if config.is_none() || path_changed {
+            self.clear();
+            if let Some(path) = config.and_then(|c| c.path.as_deref()) {
+                if let Err(err) = self.load(Path::new(path), unix_now()) {
+                    tracing::warn!("Failed to load response cache from {}: {}", path, err);
+                }
+            }
+        } else {
+            self.evict();
+        }
+        self.publish_size();
+    }
+
+    fn should_cache(&self, route: &str) -> bool {
+        self.config
+            .as_ref()
+            .map(|config| {
+                CACHEABLE_ROUTES.contains(&route)
+                    && (config.routes.is_empty() || config.routes.iter().any(|r| r == route))
+            })
+            .unwrap_or(false)
+    }
+
+    fn caches_embedding_items(&self) -> bool {
+        self.should_cache("/v1/embeddings")
+            && self
+                .config
+                .as_ref()
+                .is_some_and(|config| config.embedding_items)
+    }
+
+    fn is_expired(&self, stored_at: u64, now: u64) -> bool {
+        match self.config.as_ref().map(|config| config.ttl_secs) {
+            Some(ttl) if ttl > 0 => now >= stored_at.saturating_add(ttl),
+            _ => false,
+        }
+    }
+
+    fn get(&mut self, key: &str, now: u64) -> Option<Arc<CachedResponse>> {
+        let stored_at = self.entries.get(key)?.stored_at;
+        if self.is_expired(stored_at, now) {
+            self.remove(key);
+            self.publish_size();
+            return None;
+        }
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.used);
+        entry.used = self.clock;
+        self.recency.insert(self.clock, key.to_string());
+        Some(Arc::clone(&entry.response))
+    }
+
+    /// 写入条目并追加到持久化文件；超过总字节数上限的单个响应不缓存
+    fn insert(&mut self, key: &str, response: CachedResponse, stored_at: u64) -> io::Result<()> {
+        if !self.put(key, response, stored_at) {
+            return Ok(());
+        }
+        self.publish_size();
+        self.persist(key)
+    }
+
+    fn put(&mut self, key: &str, response: CachedResponse, stored_at: u64) -> bool {
+        let Some(config) = &self.config else {
+            return false;
+        };
+        if response.body.len() as u64 > config.max_bytes {
+            return false;
+        }
+        self.remove(key);
+        self.clock += 1;
+        self.bytes += response.body.len() as u64;
+        self.recency.insert(self.clock, key.to_string());
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                response: Arc::new(response),
+                stored_at,
+                used: self.clock,
+            },
+        );
+        self.evict();
+        true
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+            self.bytes -= entry.response.body.len() as u64;
+        }
+    }
+
+    /// 淘汰最久未使用的条目，直到不超过条目数与字节数上限
+    fn evict(&mut self) {
+        let Some((max_entries, max_bytes)) = self
+            .config
+            .as_ref()
+            .map(|config| (config.max_entries, config.max_bytes))
+        else {
+            return;
+        };
+        while self.entries.len() > max_entries || self.bytes > max_bytes {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.response.body.len() as u64;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.bytes = 0;
+        self.file = None;
+    }
+
+    fn publish_size(&self) {
+        update_response_cache_size(self.entries.len(), self.bytes);
+    }
+
+    /// 从持久化文件恢复未过期的条目，文件中有过期、重复或被淘汰的行时重写文件
+    fn load(&mut self, path: &Path, now: u64) -> io::Result<()> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let mut lines = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            lines += 1;
+            let Ok(persisted) = serde_json::from_str::<PersistedEntry>(&line) else {
+                continue;
+            };
+            if self.is_expired(persisted.stored_at, now) {
+                continue;
+            }
+            let response = CachedResponse {
+                stream: persisted.stream,
+                body: persisted.body.into_bytes(),
+            };
+            self.put(&persisted.key, response, persisted.stored_at);
+        }
+        if lines > self.entries.len() {
+            self.compact(path)?;
+        }
+        Ok(())
+    }
+
+    /// 只保留当前条目重写持久化文件，按访问顺序写出
+    fn compact(&mut self, path: &Path) -> io::Result<()> {
+        let temp = path.with_extension("tmp");
+        let mut file = File::create(&temp)?;
+        for key in self.recency.values() {
+            if let Some(line) = self.persisted_line(key) {
+                file.write_all(line.as_bytes())?;
+            }
+        }
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+        self.file = None;
+        Ok(())
+    }
+
+    fn persist(&mut self, key: &str) -> io::Result<()> {
+        let Some(path) = self.config.as_ref().and_then(|c| c.path.clone()) else {
+            return Ok(());
+        };
+        let Some(line) = self.persisted_line(key) else {
+            return Ok(());
+        };
+        if self.file.is_none() {
+            let path = Path::new(&path);
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            self.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        match self.file.as_mut() {
+            Some(file) => {
+                file.write_all(line.as_bytes())?;
+                file.flush()
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn persisted_line(&self, key: &str) -> Option<String> {
+        let entry = self.entries.get(key)?;
+        let persisted = PersistedEntry {
+            key: key.to_string(),
+            stored_at: entry.stored_at,
+            stream: entry.response.stream,
+            body: String::from_utf8(entry.response.body.clone()).ok()?,
+        };
+        let mut line = serde_json::to_string(&persisted).ok()?;
+        line.push('\n');
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_entries: usize, max_bytes: u64) -> ResponseCacheConfig {
+        ResponseCacheConfig {
+            max_entries,
+            max_bytes,
+            ..ResponseCacheConfig::default()
+        }
+    }
+
+    fn json(body: &str) -> CachedResponse {
+        CachedResponse {
+            stream: false,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn parses_cache_control_directives() {
+        assert_eq!(CacheDirective::from_header(None), CacheDirective::Default);
+        assert_eq!(
+            CacheDirective::from_header(Some("max-age=60")),
+            CacheDirective::Default
+        );
+        assert_eq!(
+            CacheDirective::from_header(Some("No-Cache")),
+            CacheDirective::Refresh
+        );
+        assert_eq!(
+            CacheDirective::from_header(Some("max-age=0")),
+            CacheDirective::Refresh
+        );
+        assert_eq!(
+            CacheDirective::from_header(Some("no-cache, no-store")),
+            CacheDirective::Bypass
+        );
+    }
+
+    #[test]
+    fn keys_depend_on_provider_route_and_body() {
+        let key = cache_key("openai", "/v1/embeddings", br#"{"input":"a"}"#);
+        assert_eq!(key.len(), 64);
+        assert_eq!(
+            key,
+            cache_key("openai", "/v1/embeddings", br#"{"input":"a"}"#)
+        );
+        assert_ne!(
+            key,
+            cache_key("azure", "/v1/embeddings", br#"{"input":"a"}"#)
+        );
+        assert_ne!(
+            key,
+            cache_key("openai", "/v1/embeddings", br#"{"input":"b"}"#)
+        );
+
+        let embeddings = |model: &str, dimensions| EmbeddingCache {
+            provider: "openai".to_string(),
+            model: model.to_string(),
+            dimensions,
+            directive: CacheDirective::Default,
+        };
+        let item = embeddings("text-embedding-3-small", None).key("hello");
+        assert_ne!(
+            item,
+            embeddings("text-embedding-3-small", Some(256)).key("hello")
+        );
+        assert_ne!(
+            item,
+            embeddings("text-embedding-3-large", None).key("hello")
+        );
+        assert_ne!(
+            item,
+            embeddings("text-embedding-3-small", None).key("hello!")
+        );
+    }
+
+    #[test]
+    fn extracts_embedding_inputs_and_splits_tokens() {
+        assert_eq!(
+            embedding_inputs(&serde_json::json!("hello")),
+            Some(vec!["hello".to_string()])
+        );
+        assert_eq!(
+            embedding_inputs(&serde_json::json!(["a", "b"])),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(embedding_inputs(&serde_json::json!([[1, 2], [3]])), None);
+        assert_eq!(embedding_inputs(&serde_json::json!([])), None);
+
+        assert_eq!(split_tokens(10, &["abc", "abcdefg"]), vec![3, 7]);
+        assert_eq!(split_tokens(5, &["a", "b", "c"]), vec![2, 2, 1]);
+        assert_eq!(split_tokens(0, &["", "x"]), vec![0, 0]);
+    }
+
+    #[test]
+    fn only_successful_responses_and_complete_streams_are_cacheable() {
+        assert!(json(r#"{"data":[]}"#).is_cacheable());
+        assert!(!json(r#"{"error":{"message":"bad"}}"#).is_cacheable());
+        assert!(!json("upstream unavailable").is_cacheable());
+
+        let stream = |body: &str| CachedResponse {
+            stream: true,
+            body: body.as_bytes().to_vec(),
+        };
+        assert!(stream("HTTP/1.1 200 OK\r\n\r\ndata: {}\n\ndata: [DONE]\n\n").is_cacheable());
+        assert!(stream("event: message_stop\ndata: {}\n\n").is_cacheable());
+        assert!(!stream("HTTP/1.1 200 OK\r\n\r\ndata: {}\n\n").is_cacheable());
+        assert!(!stream("HTTP/1.1 429 Too Many Requests\r\n\r\ndata: [DONE]\n\n").is_cacheable());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries_within_caps() {
+        let mut cache = ResponseCache::default();
+        cache.configure(Some(&config(2, 1024)));
+        cache.insert("a", json(r#"{"v":1}"#), 0).unwrap();
+        cache.insert("b", json(r#"{"v":2}"#), 0).unwrap();
+        assert!(cache.get("a", 0).is_some());
+        cache.insert("c", json(r#"{"v":3}"#), 0).unwrap();
+        assert!(cache.get("b", 0).is_none());
+        assert!(cache.get("a", 0).is_some());
+        assert!(cache.get("c", 0).is_some());
+
+        // 字节数上限同样触发淘汰，超过上限的单个响应不缓存
+        cache.configure(Some(&config(10, 20)));
+        assert_eq!(cache.entries.len(), 2);
+        cache.insert("d", json(r#"{"v":"four"}"#), 0).unwrap();
+        assert_eq!(cache.bytes, 7 + 12);
+        assert!(cache.get("a", 0).is_none());
+        assert!(cache.get("c", 0).is_some());
+        cache
+            .insert("big", json(r#"{"v":"too large to cache"}"#), 0)
+            .unwrap();
+        assert!(cache.get("big", 0).is_none());
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn expired_entries_are_dropped() {
+        let mut cache = ResponseCache::default();
+        cache.configure(Some(&ResponseCacheConfig {
+            ttl_secs: 60,
+            ..ResponseCacheConfig::default()
+        }));
+        cache.insert("a", json("{}"), 1_000).unwrap();
+        assert!(cache.get("a", 1_059).is_some());
+        assert!(cache.get("a", 1_060).is_none());
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn persists_entries_and_compacts_on_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/cache.jsonl");
+        let persisted = ResponseCacheConfig {
+            path: Some(path.to_string_lossy().into_owned()),
+            ..ResponseCacheConfig::default()
+        };
+        let now = unix_now();
+
+        let mut cache = ResponseCache::default();
+        cache.configure(Some(&persisted));
+        cache.insert("a", json(r#"{"v":1}"#), now).unwrap();
+        cache.insert("a", json(r#"{"v":2}"#), now).unwrap();
+        let stream = CachedResponse {
+            stream: true,
+            body: b"data: {}\n\ndata: [DONE]\n\n".to_vec(),
+        };
+        cache.insert("s", stream.clone(), now).unwrap();
+        cache.insert("old", json("{}"), 0).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 4);
+
+        let mut restored = ResponseCache::default();
+        restored.configure(Some(&persisted));
+        assert_eq!(restored.get("a", now).unwrap().body, br#"{"v":2}"#);
+        assert_eq!(*restored.get("s", now).unwrap(), stream);
+        assert!(restored.get("old", now).is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+}
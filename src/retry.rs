@@ -0,0 +1,195 @@
+//! 上游重试策略模块
+//!
+//! 根据 [`RetryConfig`] 判断上游失败是否可以重试，并计算带抖动的指数退避时间。
+//! 只有尚未向客户端输出任何字节的失败才会被重试，具体执行见 `http_client`
+
+use crate::config::{ApiConfig, RetryConfig};
+use crate::errors::RouterError;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+/// 生效的重试策略
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 最大尝试次数（含首次请求）
+    pub max_attempts: u32,
+    /// 基础退避时间
+    pub base_delay: Duration,
+    /// 单次退避上限
+    pub max_delay: Duration,
+    retry_on_status: Vec<u16>,
+    retry_on_io_errors: bool,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &RetryConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts.max(1),
+            base_delay: Duration::from_millis(config.base_delay_ms),
+            max_delay: Duration::from_millis(config.max_delay_ms),
+            retry_on_status: config.retry_on_status.clone(),
+            retry_on_io_errors: config.retry_on_io_errors,
+        }
+    }
+
+    /// 上游状态码是否触发重试
+    pub fn is_retryable_status(&self, status: u16) -> bool {
+        self.retry_on_status.contains(&status)
+    }
+
+    /// 请求错误是否触发重试（仅限连接层面的 I/O 错误与建连超时）
+    ///
+    /// 只适用于确定未被上游处理的请求：建连失败，或复用的空闲连接在返回任何字节前被关闭；
+    /// 请求可能已被处理时（如读取响应中途断开）调用方不应重试
+    pub fn is_retryable_error(&self, error: &RouterError) -> bool {
+        match error {
+            RouterError::Io(err) => {
+                self.retry_on_io_errors
+                    && matches!(
+                        err.kind(),
+                        ErrorKind::ConnectionReset
+                            | ErrorKind::ConnectionAborted
+                            | ErrorKind::ConnectionRefused
+                            | ErrorKind::BrokenPipe
+                            | ErrorKind::NotConnected
+                            | ErrorKind::UnexpectedEof
+                            | ErrorKind::TimedOut
+                    )
+            }
+            RouterError::ConnectTimeout(_) | RouterError::TlsHandshakeTimeout(_) => {
+                self.retry_on_io_errors
+            }
+            _ => false,
+        }
+    }
+
+    /// 第 `attempt` 次尝试失败后的指数退避上界（`attempt` 从 1 开始）
+    pub fn backoff_ceiling(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        self.base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay)
+    }
+
+    /// 计算第 `attempt` 次尝试失败后的等待时间
+    ///
+    /// 退避使用 full jitter（在 0 到上界之间均匀取值）；上游给出 `Retry-After` 时至少等待该时长，
+    /// 超过 `max_delay` 时返回 None 表示放弃重试
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let ceiling = self.backoff_ceiling(attempt);
+        let jittered = Duration::from_millis(fastrand::u64(0..=ceiling.as_millis() as u64));
+        match retry_after {
+            Some(wait) if wait > self.max_delay => None,
+            Some(wait) => Some(wait.max(jittered)),
+            None => Some(jittered),
+        }
+    }
+}
+
+/// 解析 `Retry-After` 响应头（只支持秒数形式）
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// 解析路由生效的重试策略：端点配置优先，其次为全局配置
+pub fn resolve_retry_policy(route: &str, config: &ApiConfig) -> Option<RetryPolicy> {
+    config
+        .endpoints
+        .get(route)
+        .and_then(|endpoint| endpoint.retry.as_ref())
+        .or(config.retry.as_ref())
+        .map(RetryPolicy::from_config)
+        .filter(|policy| policy.max_attempts > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EndpointConfig;
+    use std::io;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::from_config(&RetryConfig {
+            base_delay_ms: 100,
+            max_delay_ms: 1000,
+            ..RetryConfig::default()
+        })
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_and_is_capped() {
+        let policy = policy();
+        assert_eq!(policy.backoff_ceiling(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_ceiling(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_ceiling(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff_ceiling(5), Duration::from_millis(1000));
+        assert_eq!(policy.backoff_ceiling(60), Duration::from_millis(1000));
+
+        for _ in 0..100 {
+            assert!(policy.delay(3, None).unwrap() <= Duration::from_millis(400));
+        }
+    }
+
+    #[test]
+    fn retry_after_sets_lower_bound_and_can_abort() {
+        let policy = policy();
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(1))),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(5))), None);
+        assert_eq!(parse_retry_after(" 2 "), Some(Duration::from_secs(2)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+
+    #[test]
+    fn classifies_retryable_failures() {
+        let policy = policy();
+        assert!(policy.is_retryable_status(429));
+        assert!(policy.is_retryable_status(529));
+        assert!(!policy.is_retryable_status(500));
+        assert!(policy.is_retryable_error(&RouterError::Io(io::Error::from(
+            ErrorKind::ConnectionReset
+        ))));
+        assert!(!policy.is_retryable_error(&RouterError::Io(io::Error::from(
+            ErrorKind::PermissionDenied
+        ))));
+        assert!(!policy.is_retryable_error(&RouterError::Upstream("bad".to_string())));
+        assert!(policy.is_retryable_error(&RouterError::ConnectTimeout("slow".to_string())));
+        assert!(!policy.is_retryable_error(&RouterError::FirstByteTimeout("slow".to_string())));
+
+        let no_io = RetryPolicy::from_config(&RetryConfig {
+            retry_on_io_errors: false,
+            ..RetryConfig::default()
+        });
+        assert!(!no_io.is_retryable_error(&RouterError::Io(io::Error::from(
+            ErrorKind::ConnectionReset
+        ))));
+    }
+
+    #[test]
+    fn endpoint_policy_overrides_global() {
+        let mut config = ApiConfig {
+            retry: Some(RetryConfig::default()),
+            ..ApiConfig::default()
+        };
+        config.endpoints.insert(
+            "/v1/embeddings".to_string(),
+            EndpointConfig {
+                retry: Some(RetryConfig {
+                    max_attempts: 1,
+                    ..RetryConfig::default()
+                }),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            resolve_retry_policy("/v1/chat/completions", &config)
+                .unwrap()
+                .max_attempts,
+            3
+        );
+        assert!(resolve_retry_policy("/v1/embeddings", &config).is_none());
+        assert!(resolve_retry_policy("/v1/chat/completions", &ApiConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,677 @@
+//! 准入调度模块
+//!
+//! 位于限流解析与路由转发之间：令牌桶耗尽时不再立即返回 429，
+//! 而是按优先级通道排队等待，并使用加权公平队列（WFQ）在各通道间分配容量。
+//!
+//! 同一路由的所有 Key 共用一个等待队列。配置了共享容量（`scheduler.requestsPerMinute`）时，
+//! 每个请求还要从该路由所有 Key 共享的令牌桶中取得令牌，一个 Key 的批量请求因此会为
+//! 其他 Key 的交互请求让行。
+
+use crate::config::ApiConfig;
+use crate::rate_limit::{RateLimitDecision, RateLimitSettings, RateLimiter};
+use dashmap::DashMap;
+use event_listener::Event;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 共享容量令牌桶使用的 Key（每条路由一个）
+const SHARED_BUCKET_KEY: &str = "";
+
+/// 请求所属的优先级通道
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriorityLane {
+    /// 通道名称
+    pub name: String,
+    /// WFQ 权重（至少为 1）
+    pub weight: u32,
+    /// 最长排队时间
+    pub max_wait: Duration,
+}
+
+/// 准入决策结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmissionDecision {
+    /// 允许通过，`waited` 为排队耗时
+    Admitted { waited: Duration },
+    /// 排队超时或不允许排队，需要等待指定秒数后重试
+    Rejected { retry_after_seconds: u64 },
+}
+
+/// 准入调度器快照，用于 /health 输出
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerSnapshot {
+    /// 按通道统计的排队请求数
+    pub queued: HashMap<String, usize>,
+}
+
+/// 准入调度器
+///
+/// 每条路由对应一个等待队列，队列内按虚拟完成时间（finish tag）排序；
+/// 自己的令牌桶有令牌的等待者中，finish tag 最小的一个可以尝试获取共享容量
+pub struct AdmissionScheduler {
+    queues: DashMap<String, RouteQueue>,
+    /// 各路由共享容量的令牌桶
+    shared: RateLimiter,
+    /// 有请求准入或离开队列时唤醒等待者
+    changed: Event,
+    next_ticket: AtomicU64,
+}
+
+/// 单条路由的等待队列
+#[derive(Debug, Default)]
+struct RouteQueue {
+    /// 当前虚拟时间（最近一次准入请求的 finish tag）
+    virtual_time: f64,
+    /// 每个通道最近分配的 finish tag
+    lane_finish: HashMap<String, f64>,
+    /// 排队中的请求
+    waiters: Vec<Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    api_key: String,
+    lane: String,
+    finish_tag: f64,
+}
+
+/// 排队凭证，离开作用域（包括客户端断开导致的 future 取消）时自动出队
+struct QueueTicket<'a> {
+    scheduler: &'a AdmissionScheduler,
+    route: String,
+    ticket: u64,
+    admitted: bool,
+}
+
+impl Drop for QueueTicket<'_> {
+    fn drop(&mut self) {
+        self.scheduler
+            .dequeue(&self.route, self.ticket, self.admitted);
+    }
+}
+
+/// 全局准入调度器单例
+pub static ADMISSION_SCHEDULER: Lazy<AdmissionScheduler> = Lazy::new(AdmissionScheduler::new);
+
+/// 解析请求所属的优先级通道
+///
+/// 优先级：Key 配置中固定的通道 > 请求头声明的通道 > 默认通道。
+/// 未配置 `scheduler` 时返回 None，表示保持立即返回 429 的行为
+pub fn resolve_priority_lane(
+    config: &ApiConfig,
+    api_key: &str,
+    headers: &HashMap<String, String>,
+) -> Option<PriorityLane> {
+    let scheduler = config.scheduler.as_ref()?;
+    let is_known = |name: &&str| scheduler.lanes.contains_key(*name);
+
+    let pinned = config
+        .keys
+        .get(api_key)
+        .and_then(|key| key.priority.as_deref())
+        .filter(is_known);
+    let requested = headers
+        .get(&scheduler.priority_header.to_ascii_lowercase())
+        .map(|value| value.trim())
+        .filter(is_known);
+    let name = pinned
+        .or(requested)
+        .unwrap_or(scheduler.default_priority.as_str());
+
+    let lane = scheduler.lanes.get(name).cloned().unwrap_or_default();
+    Some(PriorityLane {
+        name: name.to_string(),
+        weight: lane.weight.max(1),
+        max_wait: Duration::from_millis(lane.max_wait_ms),
+    })
+}
+
+/// 解析每条路由所有 Key 共享的容量，未配置时返回 None
+pub fn resolve_shared_capacity(config: &ApiConfig) -> Option<RateLimitSettings> {
+    let scheduler = config.scheduler.as_ref()?;
+    let requests_per_minute = scheduler.requests_per_minute.filter(|rpm| *rpm > 0)?;
+    Some(RateLimitSettings {
+        requests_per_minute,
+        burst: scheduler.burst.unwrap_or(requests_per_minute).max(1),
+    })
+}
+
+impl AdmissionScheduler {
+    /// 创建新的准入调度器
+    pub fn new() -> Self {
+        Self {
+            queues: DashMap::new(),
+            shared: RateLimiter::new(),
+            changed: Event::new(),
+            next_ticket: AtomicU64::new(0),
+        }
+    }
+
+    /// 为请求申请准入
+    ///
+    /// - 未指定通道时等价于一次 `RateLimiter::check`
+    /// - 队列为空且 Key 的令牌桶与共享容量都有令牌时立即放行
+    /// - 否则进入路由的队列，按 WFQ 顺序等待令牌，超过通道的最长等待时间后拒绝
+    pub async fn admit(
+        &self,
+        limiter: &RateLimiter,
+        route: &str,
+        api_key: &str,
+        settings: &RateLimitSettings,
+        capacity: Option<&RateLimitSettings>,
+        lane: Option<&PriorityLane>,
+    ) -> AdmissionDecision {
+        let lane = match lane {
+            Some(lane) => lane,
+            None => {
+                return match limiter.check(route, api_key, settings) {
+                    RateLimitDecision::Allowed => AdmissionDecision::Admitted {
+                        waited: Duration::ZERO,
+                    },
+                    RateLimitDecision::Limited {
+                        retry_after_seconds,
+                    } => AdmissionDecision::Rejected {
+                        retry_after_seconds,
+                    },
+                }
+            }
+        };
+
+        if !self.has_waiters(route) {
+            match self.acquire(limiter, route, api_key, settings, capacity) {
+                Ok(()) => {
+                    return AdmissionDecision::Admitted {
+                        waited: Duration::ZERO,
+                    }
+                }
+                Err(wait) if lane.max_wait.is_zero() => {
+                    return AdmissionDecision::Rejected {
+                        retry_after_seconds: retry_after_secs(wait),
+                    }
+                }
+                Err(_) => {}
+            }
+        } else if lane.max_wait.is_zero() {
+            // 已有请求在排队，不允许排队的通道直接让行
+            return AdmissionDecision::Rejected {
+                retry_after_seconds: retry_after_secs(
+                    self.time_until_available(limiter, route, api_key, capacity),
+                ),
+            };
+        }
+
+        let start = Instant::now();
+        let deadline = start + lane.max_wait;
+        let mut ticket = self.enqueue(route, api_key, lane);
+
+        loop {
+            // 先注册监听再检查队列，避免错过检查与等待之间发出的通知
+            let listener = self.changed.listen();
+            let wait = match self.try_admit_waiter(&ticket, limiter, api_key, settings, capacity) {
+                Ok(()) => {
+                    ticket.admitted = true;
+                    return AdmissionDecision::Admitted {
+                        waited: start.elapsed(),
+                    };
+                }
+                Err(wait) => wait,
+            };
+
+            let now = Instant::now();
+            if now >= deadline {
+                return AdmissionDecision::Rejected {
+                    retry_after_seconds: retry_after_secs(
+                        self.time_until_available(limiter, route, api_key, capacity),
+                    ),
+                };
+            }
+
+            // 等待令牌补充或队列变化（排在前面的请求准入或离开），最长等到截止时间
+            let timeout = wait.map_or(deadline - now, |wait| wait.min(deadline - now));
+            smol::future::or(listener, async {
+                smol::Timer::after(timeout).await;
+            })
+            .await;
+        }
+    }
+
+    /// 返回各通道排队情况
+    pub fn snapshot(&self) -> SchedulerSnapshot {
+        let mut queued: HashMap<String, usize> = HashMap::new();
+        for entry in self.queues.iter() {
+            for waiter in &entry.value().waiters {
+                *queued.entry(waiter.lane.clone()).or_insert(0) += 1;
+            }
+        }
+        SchedulerSnapshot { queued }
+    }
+
+    /// 从 Key 的令牌桶与路由的共享容量各取一个令牌，不足时返回需要等待的时间
+    fn acquire(
+        &self,
+        limiter: &RateLimiter,
+        route: &str,
+        api_key: &str,
+        settings: &RateLimitSettings,
+        capacity: Option<&RateLimitSettings>,
+    ) -> Result<(), Duration> {
+        let wait = self.time_until_available(limiter, route, api_key, capacity);
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        if let RateLimitDecision::Limited { .. } = limiter.check(route, api_key, settings) {
+            return Err(limiter.time_until_available(route, api_key));
+        }
+        if let Some(capacity) = capacity {
+            self.shared.check(route, SHARED_BUCKET_KEY, capacity);
+        }
+        Ok(())
+    }
+
+    fn time_until_available(
+        &self,
+        limiter: &RateLimiter,
+        route: &str,
+        api_key: &str,
+        capacity: Option<&RateLimitSettings>,
+    ) -> Duration {
+        let own = limiter.time_until_available(route, api_key);
+        match capacity {
+            Some(_) => own.max(self.shared.time_until_available(route, SHARED_BUCKET_KEY)),
+            None => own,
+        }
+    }
+
+    /// 排队中的请求尝试准入
+    ///
+    /// 只有 Key 的令牌桶有令牌的等待者参与 WFQ 排序，避免令牌耗尽的 Key 阻塞其他 Key。
+    /// 失败时返回需要等待的时间，None 表示等待队列变化
+    fn try_admit_waiter(
+        &self,
+        ticket: &QueueTicket<'_>,
+        limiter: &RateLimiter,
+        api_key: &str,
+        settings: &RateLimitSettings,
+        capacity: Option<&RateLimitSettings>,
+    ) -> Result<(), Option<Duration>> {
+        let route = ticket.route.as_str();
+        // 持有队列锁完成选择与取令牌，同一路由的准入按顺序进行
+        let queue = self.queues.get_mut(route);
+        let head = queue.as_ref().and_then(|queue| {
+            queue
+                .waiters
+                .iter()
+                .filter(|waiter| {
+                    limiter
+                        .time_until_available(route, &waiter.api_key)
+                        .is_zero()
+                })
+                .min_by(|a, b| {
+                    a.finish_tag
+                        .total_cmp(&b.finish_tag)
+                        .then(a.ticket.cmp(&b.ticket))
+                })
+                .map(|head| head.ticket)
+        });
+        match head {
+            Some(head) if head == ticket.ticket => self
+                .acquire(limiter, route, api_key, settings, capacity)
+                .map_err(Some),
+            Some(_) => Err(None),
+            None => Err(Some(limiter.time_until_available(route, api_key))),
+        }
+    }
+
+    fn has_waiters(&self, route: &str) -> bool {
+        self.queues
+            .get(route)
+            .map(|queue| !queue.waiters.is_empty())
+            .unwrap_or(false)
+    }
+
+    fn enqueue(&self, route: &str, api_key: &str, lane: &PriorityLane) -> QueueTicket<'_> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut queue = self.queues.entry(route.to_string()).or_default();
+            let last_finish = queue.lane_finish.get(&lane.name).copied().unwrap_or(0.0);
+            let finish_tag = queue.virtual_time.max(last_finish) + 1.0 / lane.weight as f64;
+            queue.lane_finish.insert(lane.name.clone(), finish_tag);
+            queue.waiters.push(Waiter {
+                ticket,
+                api_key: api_key.to_string(),
+                lane: lane.name.clone(),
+                finish_tag,
+            });
+        }
+        QueueTicket {
+            scheduler: self,
+            route: route.to_string(),
+            ticket,
+            admitted: false,
+        }
+    }
+
+    fn dequeue(&self, route: &str, ticket: u64, admitted: bool) {
+        if let Some(mut queue) = self.queues.get_mut(route) {
+            if let Some(pos) = queue.waiters.iter().position(|w| w.ticket == ticket) {
+                let waiter = queue.waiters.remove(pos);
+                if admitted {
+                    queue.virtual_time = queue.virtual_time.max(waiter.finish_tag);
+                }
+            }
+        }
+        // 队列空闲时丢弃虚拟时间状态，下一轮竞争重新开始计时
+        self.queues
+            .remove_if(route, |_, queue| queue.waiters.is_empty());
+        self.changed.notify(usize::MAX);
+    }
+}
+
+impl Default for AdmissionScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn retry_after_secs(wait: Duration) -> u64 {
+    (wait.as_secs_f64().ceil() as u64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{KeyConfig, PriorityLaneConfig, SchedulerConfig};
+    use std::sync::{Arc, Mutex};
+
+    fn lane(name: &str, weight: u32, max_wait_ms: u64) -> PriorityLane {
+        PriorityLane {
+            name: name.to_string(),
+            weight,
+            max_wait: Duration::from_millis(max_wait_ms),
+        }
+    }
+
+    fn scheduler_config() -> ApiConfig {
+        let mut lanes = HashMap::new();
+        lanes.insert(
+            "interactive".to_string(),
+            PriorityLaneConfig {
+                weight: 4,
+                max_wait_ms: 1000,
+            },
+        );
+        lanes.insert("batch".to_string(), PriorityLaneConfig::default());
+        let mut keys = HashMap::new();
+        keys.insert(
+            "sk-batch".to_string(),
+            KeyConfig {
+                alias: None,
+                priority: Some("batch".to_string()),
+            },
+        );
+        ApiConfig {
+            keys,
+            scheduler: Some(SchedulerConfig {
+                default_priority: "batch".to_string(),
+                lanes,
+                ..SchedulerConfig::default()
+            }),
+            ..ApiConfig::default()
+        }
+    }
+
+    #[test]
+    fn resolve_lane_is_none_without_scheduler() {
+        let config = ApiConfig::default();
+        assert!(resolve_priority_lane(&config, "key", &HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn resolve_lane_prefers_key_config_over_header() {
+        let config = scheduler_config();
+        let mut headers = HashMap::new();
+        headers.insert("x-router-priority".to_string(), "interactive".to_string());
+
+        let pinned = resolve_priority_lane(&config, "sk-batch", &headers).unwrap();
+        assert_eq!(pinned.name, "batch");
+
+        let requested = resolve_priority_lane(&config, "sk-other", &headers).unwrap();
+        assert_eq!(requested, lane("interactive", 4, 1000));
+    }
+
+    #[test]
+    fn resolve_lane_falls_back_to_default_for_unknown_header() {
+        let config = scheduler_config();
+        let mut headers = HashMap::new();
+        headers.insert("x-router-priority".to_string(), "urgent".to_string());
+
+        let resolved = resolve_priority_lane(&config, "sk-other", &headers).unwrap();
+        assert_eq!(resolved, lane("batch", 1, 0));
+    }
+
+    #[test]
+    fn admit_without_lane_rejects_immediately() {
+        let limiter = RateLimiter::new();
+        let scheduler = AdmissionScheduler::new();
+        let settings = RateLimitSettings {
+            requests_per_minute: 1,
+            burst: 1,
+        };
+
+        smol::block_on(async {
+            assert!(matches!(
+                scheduler
+                    .admit(&limiter, "/r", "k", &settings, None, None)
+                    .await,
+                AdmissionDecision::Admitted { .. }
+            ));
+            assert!(matches!(
+                scheduler
+                    .admit(&limiter, "/r", "k", &settings, None, None)
+                    .await,
+                AdmissionDecision::Rejected { .. }
+            ));
+        });
+    }
+
+    #[test]
+    fn admit_waits_for_refill_within_max_wait() {
+        let limiter = RateLimiter::new();
+        let scheduler = AdmissionScheduler::new();
+        let settings = RateLimitSettings {
+            requests_per_minute: 600,
+            burst: 1,
+        };
+        let interactive = lane("interactive", 1, 1000);
+
+        smol::block_on(async {
+            let _ = scheduler
+                .admit(&limiter, "/r", "k", &settings, None, Some(&interactive))
+                .await;
+            match scheduler
+                .admit(&limiter, "/r", "k", &settings, None, Some(&interactive))
+                .await
+            {
+                AdmissionDecision::Admitted { waited } => {
+                    assert!(waited >= Duration::from_millis(50))
+                }
+                other => panic!("expected admission, got {:?}", other),
+            }
+        });
+        assert!(scheduler.snapshot().queued.is_empty());
+    }
+
+    #[test]
+    fn admit_rejects_after_max_wait() {
+        let limiter = RateLimiter::new();
+        let scheduler = AdmissionScheduler::new();
+        let settings = RateLimitSettings {
+            requests_per_minute: 1,
+            burst: 1,
+        };
+        let short = lane("interactive", 1, 20);
+
+        smol::block_on(async {
+            let _ = scheduler
+                .admit(&limiter, "/r", "k", &settings, None, Some(&short))
+                .await;
+            assert_eq!(
+                scheduler
+                    .admit(&limiter, "/r", "k", &settings, None, Some(&short))
+                    .await,
+                AdmissionDecision::Rejected {
+                    retry_after_seconds: 60
+                }
+            );
+        });
+        assert!(scheduler.snapshot().queued.is_empty());
+    }
+
+    #[test]
+    fn weighted_lanes_are_admitted_before_batch() {
+        let limiter = RateLimiter::new();
+        let scheduler = AdmissionScheduler::new();
+        let settings = RateLimitSettings {
+            requests_per_minute: 600,
+            burst: 1,
+        };
+        let interactive = lane("interactive", 4, 2000);
+        let batch = lane("batch", 1, 2000);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        smol::block_on(async {
+            let _ = scheduler
+                .admit(&limiter, "/r", "k", &settings, None, Some(&batch))
+                .await;
+
+            let run = |name: &'static str, lane: &PriorityLane| {
+                let order = Arc::clone(&order);
+                let lane = lane.clone();
+                let scheduler = &scheduler;
+                let limiter = &limiter;
+                let settings = &settings;
+                async move {
+                    let decision = scheduler
+                        .admit(limiter, "/r", "k", settings, None, Some(&lane))
+                        .await;
+                    assert!(matches!(decision, AdmissionDecision::Admitted { .. }));
+                    order.lock().unwrap().push(name);
+                }
+            };
+
+            smol::future::zip(
+                smol::future::zip(run("batch-1", &batch), run("batch-2", &batch)),
+                smol::future::zip(
+                    run("interactive-1", &interactive),
+                    run("interactive-2", &interactive),
+                ),
+            )
+            .await;
+        });
+
+        let order = order.lock().unwrap();
+        assert_eq!(
+            *order,
+            vec!["interactive-1", "interactive-2", "batch-1", "batch-2"]
+        );
+    }
+
+    #[test]
+    fn shared_capacity_makes_batch_keys_yield_to_interactive_keys() {
+        let limiter = RateLimiter::new();
+        let scheduler = AdmissionScheduler::new();
+        let per_key = RateLimitSettings {
+            requests_per_minute: 6000,
+            burst: 10,
+        };
+        let shared = RateLimitSettings {
+            requests_per_minute: 600,
+            burst: 1,
+        };
+        let interactive = lane("interactive", 4, 2000);
+        let batch = lane("batch", 1, 2000);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        smol::block_on(async {
+            let _ = scheduler
+                .admit(
+                    &limiter,
+                    "/r",
+                    "sk-batch",
+                    &per_key,
+                    Some(&shared),
+                    Some(&batch),
+                )
+                .await;
+
+            let run = |name: &'static str, key: &'static str, lane: &PriorityLane| {
+                let order = Arc::clone(&order);
+                let lane = lane.clone();
+                let scheduler = &scheduler;
+                let limiter = &limiter;
+                let per_key = &per_key;
+                let shared = &shared;
+                async move {
+                    let decision = scheduler
+                        .admit(limiter, "/r", key, per_key, Some(shared), Some(&lane))
+                        .await;
+                    assert!(matches!(decision, AdmissionDecision::Admitted { .. }));
+                    order.lock().unwrap().push(name);
+                }
+            };
+
+            smol::future::zip(
+                smol::future::zip(
+                    run("batch-1", "sk-batch", &batch),
+                    run("batch-2", "sk-batch", &batch),
+                ),
+                run("interactive", "sk-ide", &interactive),
+            )
+            .await;
+        });
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["interactive", "batch-1", "batch-2"]
+        );
+        assert!(scheduler.snapshot().queued.is_empty());
+    }
+
+    #[test]
+    fn exhausted_key_does_not_block_other_keys() {
+        let limiter = RateLimiter::new();
+        let scheduler = AdmissionScheduler::new();
+        let settings = RateLimitSettings {
+            requests_per_minute: 1,
+            burst: 1,
+        };
+        let batch = lane("batch", 1, 200);
+        let interactive = lane("interactive", 1, 200);
+
+        smol::block_on(async {
+            let _ = scheduler
+                .admit(&limiter, "/r", "sk-a", &settings, None, Some(&batch))
+                .await;
+            let (waiting, other) = smol::future::zip(
+                scheduler.admit(&limiter, "/r", "sk-a", &settings, None, Some(&batch)),
+                async {
+                    smol::Timer::after(Duration::from_millis(20)).await;
+                    scheduler
+                        .admit(&limiter, "/r", "sk-b", &settings, None, Some(&interactive))
+                        .await
+                },
+            )
+            .await;
+            assert!(matches!(waiting, AdmissionDecision::Rejected { .. }));
+            match other {
+                AdmissionDecision::Admitted { waited } => {
+                    assert!(waited < Duration::from_millis(100))
+                }
+                other => panic!("expected admission, got {:?}", other),
+            }
+        });
+    }
+}
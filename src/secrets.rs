@@ -0,0 +1,304 @@
+//! 配置插值与机密脱敏模块
+//!
+//! 配置文件中的字符串（包括对象的键，如 `keys` 中的 API Key）支持以下插值写法：
+//! - `${NAME}`：环境变量，未设置时报错
+//! - `${NAME:-默认值}`：环境变量，未设置或为空时使用默认值
+//! - `${file:/run/secrets/openai}`：文件内容，去掉末尾换行
+//! - `$${`：字面量 `${`
+//!
+//! 插值得到的值被登记为机密，日志、访问日志、录制文件、错误上报与生效配置输出中出现时替换为 `[REDACTED]`
+
+use crate::capture::REDACTED;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::RwLock;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// 短于该长度的插值结果不登记为机密，避免端口号等短值在日志中被大面积替换
+const MIN_SECRET_LEN: usize = 6;
+
+/// 已登记的机密，按长度降序排列，保证较长的机密先被替换
+static SECRETS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// 展开字符串中的插值表达式，并登记插值得到的机密
+pub fn interpolate(text: &str) -> Result<String, String> {
+    interpolate_with(text, |name| std::env::var(name).ok())
+}
+
+/// 使用指定的环境变量查找函数展开插值表达式
+pub fn interpolate_with(
+    text: &str,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    if !text.contains("${") {
+        return Ok(text.to_string());
+    }
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(after) = tail.strip_prefix("$${") {
+            output.push_str("${");
+            rest = after;
+            continue;
+        }
+        let Some(expression) = tail.strip_prefix("${") else {
+            output.push('$');
+            rest = &tail[1..];
+            continue;
+        };
+        let end = expression
+            .find('}')
+            .ok_or_else(|| format!("插值表达式缺少 `}}`: {}", tail))?;
+        let value = resolve(&expression[..end], &env)?;
+        output.push_str(&value);
+        rest = &expression[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn resolve(expression: &str, env: &impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    if let Some(path) = expression.strip_prefix("file:") {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("无法读取机密文件 {}: {}", path, err))?;
+        let value = content.trim_end_matches(['\r', '\n']).to_string();
+        register(&value);
+        return Ok(value);
+    }
+    let (name, default) = match expression.split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (expression, None),
+    };
+    let valid_name = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if !valid_name {
+        return Err(format!("无效的环境变量名 `{}`", name));
+    }
+    match (env(name).filter(|value| !value.is_empty()), default) {
+        (Some(value), _) => {
+            register(&value);
+            Ok(value)
+        }
+        // 默认值写在配置文件中，不视为机密
+        (None, Some(default)) => Ok(default.to_string()),
+        (None, None) => Err(format!("环境变量 {} 未设置", name)),
+    }
+}
+
+/// 登记机密
+pub fn register(secret: &str) {
+    if secret.len() < MIN_SECRET_LEN {
+        return;
+    }
+    let mut secrets = SECRETS.write().expect("机密列表损坏");
+    if !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.to_string());
+        secrets.sort_by_key(|known| std::cmp::Reverse(known.len()));
+    }
+}
+
+/// 将文本中出现的机密替换为占位符
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().expect("机密列表损坏");
+    let mut redacted = Cow::Borrowed(text);
+    for secret in secrets.iter() {
+        if redacted.contains(secret.as_str()) {
+            redacted = Cow::Owned(redacted.replace(secret.as_str(), REDACTED));
+        }
+    }
+    redacted
+}
+
+/// 替换 JSON 中所有字符串（包括对象的键）里出现的机密
+pub fn redact_json(value: &mut Value) {
+    match value {
+        Value::String(text) => {
+            if let Cow::Owned(redacted) = redact(text) {
+                *text = redacted;
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        Value::Object(map) => {
+            if map.keys().any(|key| matches!(redact(key), Cow::Owned(_))) {
+                *map = std::mem::take(map)
+                    .into_iter()
+                    .map(|(key, child)| (redact(&key).into_owned(), child))
+                    .collect();
+            }
+            map.values_mut().for_each(redact_json);
+        }
+        _ => {}
+    }
+}
+
+/// 为日志输出脱敏的 [`MakeWriter`]，每条日志格式化完成后整体替换机密再写出
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+}
+
+/// 包装日志输出目标，使日志中不出现已登记的机密
+pub fn redacting<M>(inner: M) -> RedactingMakeWriter<M> {
+    RedactingMakeWriter { inner }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+/// 缓冲一条日志，释放时脱敏后写出
+pub struct RedactingWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return self.inner.flush();
+        }
+        let buffer = std::mem::take(&mut self.buffer);
+        match std::str::from_utf8(&buffer) {
+            Ok(text) => self.inner.write_all(redact(text).as_bytes())?,
+            Err(_) => self.inner.write_all(&buffer)?,
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for RedactingWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn expands_env_defaults_and_escapes() {
+        let vars = env(&[("OPENAI_KEY", "sk-interp-0001"), ("EMPTY", "")]);
+        assert_eq!(
+            interpolate_with("Bearer ${OPENAI_KEY}", &vars).unwrap(),
+            "Bearer sk-interp-0001"
+        );
+        assert_eq!(
+            interpolate_with("${UPSTREAM:-https://api.openai.com}/v1", &vars).unwrap(),
+            "https://api.openai.com/v1"
+        );
+        assert_eq!(
+            interpolate_with("${EMPTY:-fallback}", &vars).unwrap(),
+            "fallback"
+        );
+        assert_eq!(interpolate_with("${EMPTY:-}", &vars).unwrap(), "");
+        assert_eq!(
+            interpolate_with("literal $${HOME} and $5", &vars).unwrap(),
+            "literal ${HOME} and $5"
+        );
+        assert_eq!(
+            interpolate_with("${MISSING}", &vars).unwrap_err(),
+            "环境变量 MISSING 未设置"
+        );
+        assert!(interpolate_with("${OPENAI_KEY", &vars).is_err());
+        assert!(interpolate_with("${bad-name}", &vars).is_err());
+    }
+
+    #[test]
+    fn reads_secret_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anthropic");
+        std::fs::write(&path, "sk-ant-file-secret\n").unwrap();
+        let text = format!("${{file:{}}}", path.display());
+        assert_eq!(
+            interpolate_with(&text, env(&[])).unwrap(),
+            "sk-ant-file-secret"
+        );
+        assert_eq!(redact("key=sk-ant-file-secret"), "key=[REDACTED]");
+
+        let missing = format!("${{file:{}}}", dir.path().join("missing").display());
+        assert!(interpolate_with(&missing, env(&[]))
+            .unwrap_err()
+            .starts_with("无法读取机密文件"));
+    }
+
+    #[test]
+    fn redacts_registered_secrets_only() {
+        let vars = env(&[("REDACT_TEST_KEY", "sk-redact-test-42"), ("SHORT", "42")]);
+        interpolate_with("${REDACT_TEST_KEY} ${SHORT}", &vars).unwrap();
+        interpolate_with("${UNUSED:-sk-default-not-secret}", &vars).unwrap();
+        assert_eq!(
+            redact("auth sk-redact-test-42 port 42 sk-default-not-secret"),
+            "auth [REDACTED] port 42 sk-default-not-secret"
+        );
+
+        let mut json = serde_json::json!({
+            "headers": {"X-Custom": "Bearer sk-redact-test-42"},
+            "keys": {"sk-redact-test-42": {"alias": "team"}}
+        });
+        redact_json(&mut json);
+        assert_eq!(json["headers"]["X-Custom"], "Bearer [REDACTED]");
+        assert_eq!(json["keys"]["[REDACTED]"]["alias"], "team");
+    }
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn log_writer_redacts_secrets() {
+        register("sk-log-writer-secret");
+        let captured = Captured::default();
+        let sink = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(redacting(move || sink.clone()))
+            .with_ansi(false)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(
+                header = "Bearer sk-log-writer-secret",
+                "upstream config loaded"
+            );
+        });
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("upstream config loaded"), "{}", output);
+        assert!(output.contains("Bearer [REDACTED]"), "{}", output);
+        assert!(!output.contains("sk-log-writer-secret"));
+    }
+}
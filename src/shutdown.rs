@@ -0,0 +1,176 @@
+//! 优雅停机模块
+//!
+//! 收到 SIGTERM / SIGINT 后进入排空状态：`/health` 报告 `draining`，可选地继续接受连接一段时间
+//! 以便负载均衡摘除实例，随后停止接受新连接，在宽限期内等待进行中的请求（含流式响应）完成，随后关闭上游空闲连接并刷新日志与链路数据。
+//! 排空期间再次收到信号时立即退出
+
+use crate::access_log;
+use crate::config::{ApiConfig, ShutdownConfig};
+use crate::error_tracking;
+use crate::otel;
+use async_signal::{Signal, Signals};
+use once_cell::sync::Lazy;
+use smol::stream::StreamExt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// 排空期间检查进行中连接数的间隔
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// 停机时等待遥测数据发送的最长时间
+const TELEMETRY_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 触发优雅停机的信号
+const SHUTDOWN_SIGNALS: &[Signal] = &[
+    #[cfg(unix)]
+    Signal::Term,
+    Signal::Int,
+];
+
+/// 是否处于排空状态
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// 全局进行中连接计数
+pub static IN_FLIGHT: Lazy<InFlightTracker> = Lazy::new(InFlightTracker::default);
+
+/// 是否已开始停机排空
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// 进入排空状态
+pub fn begin_draining() {
+    DRAINING.store(true, Ordering::Relaxed);
+}
+
+/// 解析停机配置，未配置时使用默认值
+pub fn resolve_shutdown_config(config: &ApiConfig) -> ShutdownConfig {
+    config.shutdown.clone().unwrap_or_default()
+}
+
+/// 进行中连接计数，从接受连接起计到连接处理结束
+#[derive(Debug, Default)]
+pub struct InFlightTracker {
+    count: AtomicUsize,
+}
+
+impl InFlightTracker {
+    /// 登记一个连接，返回的守卫释放时计数减一
+    pub fn track(&self) -> InFlightGuard<'_> {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard { tracker: self }
+    }
+
+    /// 当前进行中的连接数
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// 等待全部连接结束，最多等待 `grace`；返回超时后仍未结束的连接数
+    pub async fn drain(&self, grace: Duration) -> usize {
+        let deadline = Instant::now() + grace;
+        loop {
+            let remaining = self.count();
+            if remaining == 0 || Instant::now() >= deadline {
+                return remaining;
+            }
+            smol::Timer::after(DRAIN_POLL_INTERVAL.min(deadline - Instant::now())).await;
+        }
+    }
+}
+
+/// 进行中连接守卫
+pub struct InFlightGuard<'a> {
+    tracker: &'a InFlightTracker,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.tracker.count.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 停机信号监听
+pub struct ShutdownSignal {
+    signals: Signals,
+}
+
+impl ShutdownSignal {
+    /// 注册 SIGTERM / SIGINT 处理；注册后这些信号不再直接终止进程
+    pub fn register() -> std::io::Result<Self> {
+        Ok(Self {
+            signals: Signals::new(SHUTDOWN_SIGNALS.iter().copied())?,
+        })
+    }
+
+    /// 等待下一个停机信号，返回信号名称
+    pub async fn recv(&mut self) -> std::io::Result<&'static str> {
+        match self.signals.next().await {
+            Some(Ok(signal)) => Ok(signal_name(signal)),
+            Some(Err(err)) => Err(err),
+            None => Err(std::io::Error::other("Signal stream closed")),
+        }
+    }
+}
+
+fn signal_name(signal: Signal) -> &'static str {
+    match signal {
+        #[cfg(unix)]
+        Signal::Term => "SIGTERM",
+        Signal::Int => "SIGINT",
+        _ => "signal",
+    }
+}
+
+/// 刷新访问日志、链路追踪与错误上报队列，会阻塞当前线程
+pub fn flush_telemetry() {
+    access_log::flush();
+    otel::flush_pending();
+    error_tracking::flush(TELEMETRY_FLUSH_TIMEOUT);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drain_waits_for_in_flight_connections() {
+        let tracker = InFlightTracker::default();
+        assert_eq!(smol::block_on(tracker.drain(Duration::from_secs(5))), 0);
+
+        let guard = tracker.track();
+        assert_eq!(tracker.count(), 1);
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                std::thread::sleep(Duration::from_millis(30));
+                drop(guard);
+            });
+            let started = Instant::now();
+            assert_eq!(smol::block_on(tracker.drain(Duration::from_secs(5))), 0);
+            assert!(started.elapsed() < Duration::from_secs(5));
+        });
+    }
+
+    #[test]
+    fn drain_gives_up_after_grace_period() {
+        let tracker = InFlightTracker::default();
+        let _guard = tracker.track();
+        let started = Instant::now();
+        assert_eq!(smol::block_on(tracker.drain(Duration::from_millis(100))), 1);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn resolves_shutdown_config() {
+        let defaults = resolve_shutdown_config(&ApiConfig::default());
+        assert_eq!(defaults.grace_period_secs, 30);
+        assert_eq!(defaults.drain_delay_secs, 0);
+        let config = ApiConfig {
+            shutdown: Some(ShutdownConfig {
+                grace_period_secs: 5,
+                drain_delay_secs: 2,
+            }),
+            ..ApiConfig::default()
+        };
+        assert_eq!(resolve_shutdown_config(&config).drain_delay_secs, 2);
+    }
+}
@@ -0,0 +1,140 @@
+//! 上游超时模块
+//!
+//! 将 [`TimeoutConfig`] 解析为各阶段的超时时长，并提供为 future 加上超时的辅助函数。
+//! 每种超时对应独立的 [`RouterError`] 变体，最终以 504 返回给客户端
+
+use crate::config::{ApiConfig, TimeoutConfig};
+use crate::errors::{RouterError, RouterResult};
+use std::future::Future;
+use std::time::Duration;
+
+/// 生效的上游超时设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// 建立 TCP 连接
+    pub connect: Option<Duration>,
+    /// TLS 握手
+    pub tls_handshake: Option<Duration>,
+    /// 发出请求后等待首字节
+    pub first_byte: Option<Duration>,
+    /// 收到首字节后的读取空闲间隔
+    pub idle_read: Option<Duration>,
+    /// 单次上游调用（含重试）的整体时长
+    pub total: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self::from_config(&TimeoutConfig::default())
+    }
+}
+
+impl Timeouts {
+    pub fn from_config(config: &TimeoutConfig) -> Self {
+        let millis = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+        Self {
+            connect: millis(config.connect_ms),
+            tls_handshake: millis(config.tls_handshake_ms),
+            first_byte: config.first_byte_ms.and_then(millis),
+            idle_read: config.idle_read_ms.and_then(millis),
+            total: config.total_ms.and_then(millis),
+        }
+    }
+}
+
+/// 解析路由生效的超时设置：端点配置优先，其次为全局配置，都未配置时使用默认值
+pub fn resolve_timeouts(route: &str, config: &ApiConfig) -> Timeouts {
+    config
+        .endpoints
+        .get(route)
+        .and_then(|endpoint| endpoint.timeouts.as_ref())
+        .or(config.timeouts.as_ref())
+        .map(Timeouts::from_config)
+        .unwrap_or_default()
+}
+
+/// 为 future 加上超时，`limit` 为 None 时不限制；超时时用 `on_timeout` 构造错误
+pub async fn with_timeout<T, F>(
+    limit: Option<Duration>,
+    future: F,
+    on_timeout: impl FnOnce(Duration) -> RouterError,
+) -> RouterResult<T>
+where
+    F: Future<Output = RouterResult<T>>,
+{
+    let Some(limit) = limit else {
+        return future.await;
+    };
+    smol::future::or(future, async {
+        smol::Timer::after(limit).await;
+        Err(on_timeout(limit))
+    })
+    .await
+}
+
+/// 超时错误信息
+pub fn timeout_message(stage: &str, limit: Duration) -> String {
+    format!("{} exceeded {}ms", stage, limit.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EndpointConfig;
+
+    #[test]
+    fn resolves_defaults_and_overrides() {
+        let defaults = resolve_timeouts("/v1/chat/completions", &ApiConfig::default());
+        assert_eq!(defaults.connect, Some(Duration::from_secs(10)));
+        assert_eq!(defaults.tls_handshake, Some(Duration::from_secs(10)));
+        assert_eq!(defaults.first_byte, None);
+
+        let mut config = ApiConfig {
+            timeouts: Some(TimeoutConfig {
+                first_byte_ms: Some(30_000),
+                ..TimeoutConfig::default()
+            }),
+            ..ApiConfig::default()
+        };
+        config.endpoints.insert(
+            "/v1/embeddings".to_string(),
+            EndpointConfig {
+                timeouts: Some(TimeoutConfig {
+                    connect_ms: 0,
+                    total_ms: Some(5_000),
+                    ..TimeoutConfig::default()
+                }),
+                ..Default::default()
+            },
+        );
+        let chat = resolve_timeouts("/v1/chat/completions", &config);
+        assert_eq!(chat.first_byte, Some(Duration::from_secs(30)));
+        let embeddings = resolve_timeouts("/v1/embeddings", &config);
+        assert_eq!(embeddings.connect, None);
+        assert_eq!(embeddings.first_byte, None);
+        assert_eq!(embeddings.total, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn with_timeout_maps_to_error() {
+        let result: RouterResult<()> = smol::block_on(with_timeout(
+            Some(Duration::from_millis(10)),
+            async {
+                smol::Timer::after(Duration::from_secs(5)).await;
+                Ok(())
+            },
+            |limit| RouterError::TotalTimeout(timeout_message("Upstream request", limit)),
+        ));
+        let err = result.unwrap_err();
+        assert_eq!(err.class(), "total_timeout");
+        assert_eq!(
+            err.to_string(),
+            "Total timeout: Upstream request exceeded 10ms"
+        );
+
+        let ok = smol::block_on(with_timeout(None, async { Ok(1) }, |_| {
+            RouterError::TotalTimeout(String::new())
+        }));
+        assert_eq!(ok.unwrap(), 1);
+    }
+}
@@ -0,0 +1,613 @@
+//! 上游 TLS 模块
+//!
+//! 根据 [`TlsConfig`] 为每个上游构造 rustls 客户端配置：额外 CA、mTLS 客户端证书、
+//! SPKI 证书固定与最低 TLS 版本。提供商覆盖按主机名或提供商名称匹配，
+//! 配置错误在加载时记录日志，并在连接该上游时以 [`RouterError::Tls`] 返回
+
+use crate::config::{TlsConfig, TlsProfileConfig};
+use crate::errors::{RouterError, RouterResult};
+use crate::tracing_util::extract_provider;
+use async_tls::TlsConnector;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use ring::digest::{digest, SHA256};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName,
+    SupportedProtocolVersion,
+};
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tracing::warn;
+
+/// SPKI 指纹前缀
+const PIN_PREFIX: &str = "sha256/";
+
+/// 当前生效的 TLS 设置
+static SETTINGS: Lazy<RwLock<Arc<TlsSettings>>> =
+    Lazy::new(|| RwLock::new(Arc::new(TlsSettings::from_config(None))));
+/// 最近一次应用的 TLS 配置，用于跳过重复配置
+static APPLIED: Lazy<RwLock<Option<Option<TlsConfig>>>> = Lazy::new(|| RwLock::new(None));
+
+/// 构造结果：连接器或可直接返回给调用方的错误信息
+type ConnectorResult = Result<TlsConnector, String>;
+
+/// 解析后的 TLS 设置
+pub struct TlsSettings {
+    default: ConnectorResult,
+    /// 提供商或主机 -> 连接器
+    overrides: HashMap<String, ConnectorResult>,
+}
+
+impl TlsSettings {
+    /// 由配置构造设置，每组配置的错误分别记录
+    pub fn from_config(config: Option<&TlsConfig>) -> Self {
+        let build = |name: &str, profile: &TlsProfileConfig| {
+            build_connector(profile).map_err(|err| {
+                warn!("Invalid TLS settings for {}: {}", name, err);
+                err.to_string()
+            })
+        };
+        let Some(config) = config else {
+            return Self {
+                default: Ok(TlsConnector::new()),
+                overrides: HashMap::new(),
+            };
+        };
+        Self {
+            default: build("default", &config.default),
+            overrides: config
+                .providers
+                .iter()
+                .map(|(name, profile)| (name.to_ascii_lowercase(), build(name, profile)))
+                .collect(),
+        }
+    }
+
+    /// 为目标主机选择连接器：主机名覆盖优先，其次为提供商覆盖，最后为默认设置
+    pub fn connector_for(&self, host: &str) -> RouterResult<TlsConnector> {
+        let host = host.to_ascii_lowercase();
+        self.overrides
+            .get(&host)
+            .or_else(|| self.overrides.get(extract_provider(&host)))
+            .unwrap_or(&self.default)
+            .clone()
+            .map_err(RouterError::Tls)
+    }
+}
+
+/// 应用 TLS 配置，配置未变化时不做任何事
+pub fn configure(config: Option<&TlsConfig>) {
+    if let Ok(applied) = APPLIED.read() {
+        if applied.as_ref().map(Option::as_ref) == Some(config) {
+            return;
+        }
+    }
+    let settings = TlsSettings::from_config(config);
+    if let (Ok(mut current), Ok(mut applied)) = (SETTINGS.write(), APPLIED.write()) {
+        *current = Arc::new(settings);
+        *applied = Some(config.cloned());
+    }
+}
+
+/// 按当前设置为目标主机选择连接器
+pub fn connector_for(host: &str) -> RouterResult<TlsConnector> {
+    SETTINGS
+        .read()
+        .map_err(|_| RouterError::Tls("TLS settings lock poisoned".to_string()))?
+        .connector_for(host)
+}
+
+/// 将 TLS 握手失败转换为带排查提示的错误
+pub fn handshake_error(host: &str, err: std::io::Error) -> RouterError {
+    let message = err.to_string();
+    let hint = if message.contains("UnknownIssuer") {
+        Some("the server certificate is not trusted; add its CA to tls.caFiles")
+    } else if message.contains("CertExpired") || message.contains("CertNotValidYet") {
+        Some("the server certificate is outside its validity period")
+    } else if message.contains("NotValidForName") {
+        Some("the server certificate does not cover this host name")
+    } else if message.contains("CertificateRequired") || message.contains("HandshakeFailure") {
+        Some("the server may require a client certificate; set tls.clientCert and tls.clientKey")
+    } else if message.contains("ProtocolVersion") {
+        Some("the server does not support tls.minVersion")
+    } else {
+        None
+    };
+    match hint {
+        Some(hint) => RouterError::Tls(format!(
+            "TLS handshake with {} failed: {} ({})",
+            host, message, hint
+        )),
+        None => RouterError::Tls(format!("TLS handshake with {} failed: {}", host, message)),
+    }
+}
+
+/// 按一组 TLS 设置构造连接器
+pub fn build_connector(profile: &TlsProfileConfig) -> RouterResult<TlsConnector> {
+    let versions = protocol_versions(profile.min_version.as_deref())?;
+    let roots = root_store(profile)?;
+    let builder = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .map_err(|e| RouterError::Tls(format!("Unsupported TLS versions: {}", e)))?;
+
+    let webpki = WebPkiVerifier::new(roots, None);
+    let verifier: Arc<dyn ServerCertVerifier> = if profile.spki_pins.is_empty() {
+        Arc::new(webpki)
+    } else {
+        let pins = profile
+            .spki_pins
+            .iter()
+            .map(|pin| parse_pin(pin))
+            .collect::<RouterResult<Vec<_>>>()?;
+        Arc::new(PinnedVerifier {
+            inner: webpki,
+            pins,
+        })
+    };
+    let builder = builder.with_custom_certificate_verifier(verifier);
+
+    let config = match (&profile.client_cert, &profile.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = load_certificates(cert_path)?;
+            let key = load_private_key(key_path)?;
+            builder.with_single_cert(certs, key).map_err(|e| {
+                RouterError::Tls(format!(
+                    "Client certificate {} does not match key {}: {}",
+                    cert_path, key_path, e
+                ))
+            })?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => {
+            return Err(RouterError::Tls(
+                "tls.clientCert and tls.clientKey must be set together".to_string(),
+            ))
+        }
+    };
+    Ok(TlsConnector::from(config))
+}
+
+fn protocol_versions(
+    min_version: Option<&str>,
+) -> RouterResult<&'static [&'static SupportedProtocolVersion]> {
+    static TLS12_AND_UP: &[&SupportedProtocolVersion] =
+        &[&rustls::version::TLS13, &rustls::version::TLS12];
+    static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+    match min_version.map(str::trim) {
+        None | Some("1.2") => Ok(TLS12_AND_UP),
+        Some("1.3") => Ok(TLS13_ONLY),
+        Some("1.0") | Some("1.1") => Err(RouterError::Tls(
+            "tls.minVersion below 1.2 is not supported; use \"1.2\" or \"1.3\"".to_string(),
+        )),
+        Some(other) => Err(RouterError::Tls(format!(
+            "Invalid tls.minVersion \"{}\"; use \"1.2\" or \"1.3\"",
+            other
+        ))),
+    }
+}
+
+fn root_store(profile: &TlsProfileConfig) -> RouterResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    if profile.include_default_roots {
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+    }
+    for path in &profile.ca_files {
+        for (index, cert) in load_certificates(path)?.iter().enumerate() {
+            roots.add(cert).map_err(|e| {
+                RouterError::Tls(format!(
+                    "CA certificate #{} in {} is not a valid trust anchor: {:?}",
+                    index + 1,
+                    path,
+                    e
+                ))
+            })?;
+        }
+    }
+    if roots.is_empty() {
+        return Err(RouterError::Tls(
+            "No trusted CA certificates: set tls.caFiles or enable tls.includeDefaultRoots"
+                .to_string(),
+        ));
+    }
+    Ok(roots)
+}
+
+fn read_pem(path: &str) -> RouterResult<Vec<rustls_pemfile::Item>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| RouterError::Tls(format!("Cannot open {}: {}", path, e)))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|e| RouterError::Tls(format!("Cannot parse PEM file {}: {}", path, e)))
+}
+
+pub(crate) fn load_certificates(path: &str) -> RouterResult<Vec<Certificate>> {
+    let certs: Vec<Certificate> = read_pem(path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            rustls_pemfile::Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect();
+    if certs.is_empty() {
+        return Err(RouterError::Tls(format!(
+            "No CERTIFICATE blocks found in {}",
+            path
+        )));
+    }
+    Ok(certs)
+}
+
+pub(crate) fn load_private_key(path: &str) -> RouterResult<PrivateKey> {
+    read_pem(path)?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(der)
+            | rustls_pemfile::Item::RSAKey(der)
+            | rustls_pemfile::Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            RouterError::Tls(format!(
+                "No PKCS#8, RSA or EC private key found in {}",
+                path
+            ))
+        })
+}
+
+/// 解析 `sha256/<base64>` 形式的 SPKI 指纹
+pub(crate) fn parse_pin(pin: &str) -> RouterResult<[u8; 32]> {
+    let invalid = || {
+        RouterError::Tls(format!(
+            "Invalid tls.spkiPins entry \"{}\"; expected sha256/<base64 of SHA-256 over the DER SubjectPublicKeyInfo>",
+            pin
+        ))
+    };
+    let encoded = pin.trim().strip_prefix(PIN_PREFIX).ok_or_else(invalid)?;
+    BASE64
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(invalid)
+}
+
+/// 计算证书的 SPKI 指纹（`sha256/<base64>`）
+pub fn spki_pin(cert_der: &[u8]) -> Option<String> {
+    spki_sha256(cert_der).map(|hash| format!("{}{}", PIN_PREFIX, BASE64.encode(hash)))
+}
+
+fn spki_sha256(cert_der: &[u8]) -> Option<[u8; 32]> {
+    let spki = subject_public_key_info(cert_der)?;
+    digest(&SHA256, spki).as_ref().try_into().ok()
+}
+
+/// 从 X.509 证书 DER 中取出完整的 SubjectPublicKeyInfo 元素
+fn subject_public_key_info(cert_der: &[u8]) -> Option<&[u8]> {
+    let certificate = DerElement::parse(cert_der)?;
+    let mut tbs = DerElement::parse(certificate.contents)?.contents;
+    // 可选的 [0] version
+    if tbs.first() == Some(&0xa0) {
+        tbs = DerElement::parse(tbs)?.rest;
+    }
+    // serialNumber、signature、issuer、validity、subject
+    for _ in 0..5 {
+        tbs = DerElement::parse(tbs)?.rest;
+    }
+    let spki = DerElement::parse(tbs)?;
+    (spki.tag == 0x30).then_some(spki.raw)
+}
+
+/// 一个 DER 元素
+struct DerElement<'a> {
+    tag: u8,
+    /// 含标签与长度的完整编码
+    raw: &'a [u8],
+    contents: &'a [u8],
+    /// 其后的剩余字节
+    rest: &'a [u8],
+}
+
+impl<'a> DerElement<'a> {
+    fn parse(input: &'a [u8]) -> Option<Self> {
+        let tag = *input.first()?;
+        let first = *input.get(1)? as usize;
+        let (length, header) = if first < 0x80 {
+            (first, 2)
+        } else {
+            let count = first & 0x7f;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let bytes = input.get(2..2 + count)?;
+            let length = bytes
+                .iter()
+                .fold(0usize, |acc, byte| (acc << 8) | *byte as usize);
+            (length, 2 + count)
+        };
+        let end = header.checked_add(length)?;
+        let raw = input.get(..end)?;
+        Some(Self {
+            tag,
+            raw,
+            contents: &raw[header..],
+            rest: &input[end..],
+        })
+    }
+}
+
+/// 在常规证书链校验之外要求证书链中至少一张证书匹配 SPKI 指纹
+struct PinnedVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let matched = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|cert| spki_sha256(&cert.0))
+            .any(|hash| self.pins.contains(&hash));
+        if matched {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(format!(
+                "SPKI pin mismatch: server presented {}, none of which is listed in tls.spkiPins",
+                spki_pin(&end_entity.0).unwrap_or_else(|| "an unparseable certificate".to_string())
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_temp(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn profile() -> TlsProfileConfig {
+        TlsProfileConfig::default()
+    }
+
+    fn error_message(profile: &TlsProfileConfig) -> String {
+        match build_connector(profile) {
+            Err(RouterError::Tls(message)) => message,
+            Err(other) => panic!("unexpected error {:?}", other),
+            Ok(_) => panic!("expected TLS error"),
+        }
+    }
+
+    #[test]
+    fn builds_default_and_custom_ca_connectors() {
+        assert!(build_connector(&profile()).is_ok());
+
+        let ca = rcgen::generate_simple_self_signed(vec!["ca.internal".to_string()]).unwrap();
+        let ca_file = write_temp(&ca.serialize_pem().unwrap());
+        let custom = TlsProfileConfig {
+            ca_files: vec![ca_file.path().display().to_string()],
+            include_default_roots: false,
+            min_version: Some("1.3".to_string()),
+            ..profile()
+        };
+        assert!(build_connector(&custom).is_ok());
+    }
+
+    #[test]
+    fn reports_actionable_configuration_errors() {
+        let missing = error_message(&TlsProfileConfig {
+            ca_files: vec!["/nonexistent/ca.pem".to_string()],
+            ..profile()
+        });
+        assert!(missing.contains("/nonexistent/ca.pem"));
+
+        let empty = write_temp("not a pem file\n");
+        let no_certs = error_message(&TlsProfileConfig {
+            ca_files: vec![empty.path().display().to_string()],
+            ..profile()
+        });
+        assert!(no_certs.contains("No CERTIFICATE blocks"));
+
+        assert!(error_message(&TlsProfileConfig {
+            include_default_roots: false,
+            ..profile()
+        })
+        .contains("tls.caFiles"));
+        assert!(error_message(&TlsProfileConfig {
+            min_version: Some("1.1".to_string()),
+            ..profile()
+        })
+        .contains("below 1.2"));
+        assert!(error_message(&TlsProfileConfig {
+            client_cert: Some("cert.pem".to_string()),
+            ..profile()
+        })
+        .contains("must be set together"));
+        assert!(error_message(&TlsProfileConfig {
+            spki_pins: vec!["md5/abc".to_string()],
+            ..profile()
+        })
+        .contains("tls.spkiPins"));
+    }
+
+    #[test]
+    fn loads_client_certificate_and_key() {
+        let cert = rcgen::generate_simple_self_signed(vec!["client".to_string()]).unwrap();
+        let cert_file = write_temp(&cert.serialize_pem().unwrap());
+        let key_file = write_temp(&cert.serialize_private_key_pem());
+        let mut mtls = TlsProfileConfig {
+            client_cert: Some(cert_file.path().display().to_string()),
+            client_key: Some(key_file.path().display().to_string()),
+            ..profile()
+        };
+        assert!(build_connector(&mtls).is_ok());
+
+        mtls.client_key = Some(cert_file.path().display().to_string());
+        assert!(error_message(&mtls).contains("No PKCS#8"));
+    }
+
+    #[test]
+    fn computes_spki_pins() {
+        let cert = rcgen::generate_simple_self_signed(vec!["pinned".to_string()]).unwrap();
+        let der = cert.serialize_der().unwrap();
+        let spki = subject_public_key_info(&der).unwrap();
+        let expected = cert.get_key_pair().public_key_der();
+        assert_eq!(spki, expected.as_slice());
+
+        let pin = spki_pin(&der).unwrap();
+        assert!(pin.starts_with("sha256/"));
+        assert_eq!(parse_pin(&pin).unwrap(), spki_sha256(&der).unwrap());
+        assert!(subject_public_key_info(b"\x30\x05garbage").is_none());
+    }
+
+    /// 私有 CA 及其签发的服务端、客户端证书
+    struct TestPki {
+        ca_file: tempfile::NamedTempFile,
+        client_cert: tempfile::NamedTempFile,
+        client_key: tempfile::NamedTempFile,
+        server_config: Arc<rustls::ServerConfig>,
+        server_pin: String,
+    }
+
+    fn test_pki() -> TestPki {
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new());
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+        let server = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let client = rcgen::generate_simple_self_signed(vec!["router".to_string()]).unwrap();
+        let server_der = server.serialize_der_with_signer(&ca).unwrap();
+        let client_pem = client.serialize_pem_with_signer(&ca).unwrap();
+
+        let mut client_roots = RootCertStore::empty();
+        client_roots
+            .add(&Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(rustls::server::AllowAnyAuthenticatedClient::new(
+                client_roots,
+            ))
+            .with_single_cert(
+                vec![Certificate(server_der.clone())],
+                PrivateKey(server.serialize_private_key_der()),
+            )
+            .unwrap();
+
+        TestPki {
+            ca_file: write_temp(&ca.serialize_pem().unwrap()),
+            client_cert: write_temp(&client_pem),
+            client_key: write_temp(&client.serialize_private_key_pem()),
+            server_config: Arc::new(server_config),
+            server_pin: spki_pin(&server_der).unwrap(),
+        }
+    }
+
+    /// 与本地 TLS 服务端握手并交换一次数据
+    fn handshake(pki: &TestPki, profile: &TlsProfileConfig) -> RouterResult<Vec<u8>> {
+        use smol::io::{AsyncReadExt, AsyncWriteExt};
+        let connector = build_connector(profile)?;
+        let acceptor = async_tls::TlsAcceptor::from(pki.server_config.clone());
+        smol::block_on(async {
+            let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = smol::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                if let Ok(mut tls) = acceptor.accept(stream).await {
+                    let mut buffer = [0u8; 4];
+                    if tls.read_exact(&mut buffer).await.is_ok() {
+                        let _ = tls.write_all(b"pong").await;
+                        let _ = tls.flush().await;
+                    }
+                }
+            });
+            let stream = smol::net::TcpStream::connect(addr).await?;
+            let mut tls = connector
+                .connect("localhost", stream)
+                .await
+                .map_err(|e| handshake_error("localhost", e))?;
+            tls.write_all(b"ping").await?;
+            tls.flush().await?;
+            let mut response = vec![0u8; 4];
+            tls.read_exact(&mut response).await?;
+            server.await;
+            Ok(response)
+        })
+    }
+
+    #[test]
+    fn connects_with_private_ca_client_certificate_and_pin() {
+        let pki = test_pki();
+        let path = |file: &tempfile::NamedTempFile| file.path().display().to_string();
+        let mut pinned = TlsProfileConfig {
+            ca_files: vec![path(&pki.ca_file)],
+            include_default_roots: false,
+            client_cert: Some(path(&pki.client_cert)),
+            client_key: Some(path(&pki.client_key)),
+            spki_pins: vec![pki.server_pin.clone()],
+            min_version: Some("1.3".to_string()),
+        };
+        assert_eq!(handshake(&pki, &pinned).unwrap(), b"pong");
+
+        pinned.spki_pins = vec![format!("sha256/{}", BASE64.encode([0u8; 32]))];
+        let err = handshake(&pki, &pinned).unwrap_err();
+        assert_eq!(err.class(), "tls_error");
+        assert!(err.to_string().contains("SPKI pin mismatch"));
+        assert!(err.to_string().contains(&pki.server_pin));
+
+        let untrusted = TlsProfileConfig {
+            client_cert: Some(path(&pki.client_cert)),
+            client_key: Some(path(&pki.client_key)),
+            ..profile()
+        };
+        let err = handshake(&pki, &untrusted).unwrap_err();
+        assert!(err.to_string().contains("add its CA to tls.caFiles"));
+    }
+
+    #[test]
+    fn provider_overrides_select_connector() {
+        let config = TlsConfig {
+            default: profile(),
+            providers: HashMap::from([(
+                "vllm.internal".to_string(),
+                TlsProfileConfig {
+                    ca_files: vec!["/nonexistent/private-ca.pem".to_string()],
+                    ..profile()
+                },
+            )]),
+        };
+        let settings = TlsSettings::from_config(Some(&config));
+        assert!(settings.connector_for("api.openai.com").is_ok());
+        let err = settings.connector_for("VLLM.internal").err().unwrap();
+        assert_eq!(err.class(), "tls_error");
+        assert!(err.to_string().contains("/nonexistent/private-ca.pem"));
+    }
+}
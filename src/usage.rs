@@ -0,0 +1,442 @@
+//! Token 用量提取模块
+//!
+//! 从上游响应中解析 token 用量，兼容 OpenAI、Anthropic、Gemini、Ollama 与 Cohere 的格式。
+//! 非流式响应直接解析响应体；流式响应逐行解析 SSE `data:` 事件或 NDJSON 行，
+//! 并合并各事件中的用量（例如 Anthropic 在 `message_start` 与 `message_delta` 中分别给出）
+
+use crate::config::ModelPrice;
+use crate::http_client::StreamObserver;
+use serde_json::Value;
+
+/// 单个 SSE 行的最大缓冲长度，超过后丢弃以避免无界增长
+const MAX_LINE_BYTES: usize = 1024 * 1024;
+
+/// 一次请求的 token 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    /// 提示词（输入）token 数
+    pub prompt_tokens: u64,
+    /// 生成（输出）token 数
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// 合并另一份用量，逐项取最大值（流式事件中的用量是累计值）
+    pub fn merge(&mut self, other: TokenUsage) {
+        self.prompt_tokens = self.prompt_tokens.max(other.prompt_tokens);
+        self.completion_tokens = self.completion_tokens.max(other.completion_tokens);
+    }
+
+    /// 按价格表估算费用（美元）
+    pub fn cost(&self, price: &ModelPrice) -> f64 {
+        (self.prompt_tokens as f64 * price.input_per_million
+            + self.completion_tokens as f64 * price.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// 从非流式响应体中提取用量
+///
+/// 响应体可能采用分块传输编码，先去掉各分块的长度行再解析；仍无法解析时退回到
+/// 首个 `{` 与最后一个 `}` 之间的内容
+pub fn extract_usage(body: &[u8]) -> Option<TokenUsage> {
+    let value = serde_json::from_slice::<Value>(body)
+        .ok()
+        .or_else(|| serde_json::from_slice(&decode_chunked(body)?).ok())
+        .or_else(|| {
+            let start = body.iter().position(|b| *b == b'{')?;
+            let end = body.iter().rposition(|b| *b == b'}')?;
+            (start < end)
+                .then(|| serde_json::from_slice(&body[start..=end]).ok())
+                .flatten()
+        })?;
+    usage_from_value(&value)
+}
+
+/// 解码分块传输编码的正文，格式不符时返回 None
+fn decode_chunked(body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|window| window == b"\r\n")?;
+        let size_line = std::str::from_utf8(&rest[..line_end]).ok()?;
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).ok()?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Some(decoded);
+        }
+        let chunk = rest.get(..size)?;
+        decoded.extend_from_slice(chunk);
+        rest = rest[size..].strip_prefix(b"\r\n")?;
+    }
+}
+
+/// 为流式请求设置 `stream_options.include_usage`，返回该选项是否由路由器开启
+///
+/// OpenAI 兼容接口只有在该选项开启时才会在流末尾追加携带 usage 的事件，
+/// 否则流式请求无法统计 token 用量与费用；客户端显式设置的其他选项保持不变。
+/// 客户端已自行开启时返回 `false`，此时用量事件应原样转发
+pub fn request_stream_usage(stream: Option<bool>, stream_options: &mut Option<Value>) -> bool {
+    if !stream.unwrap_or(false) {
+        return false;
+    }
+    let options = stream_options.get_or_insert_with(|| Value::Object(Default::default()));
+    let Some(options) = options.as_object_mut() else {
+        return false;
+    };
+    let requested = options.get("include_usage") == Some(&Value::Bool(true));
+    options.insert("include_usage".to_string(), Value::Bool(true));
+    !requested
+}
+
+/// 是否为仅携带用量的 SSE 事件行（`include_usage` 在流末尾追加的 `choices` 为空的事件）
+fn is_usage_only_event(line: &[u8]) -> bool {
+    let Some(payload) = trim_ascii(line).strip_prefix(b"data:").map(trim_ascii) else {
+        return false;
+    };
+    if !payload.starts_with(b"{") {
+        return false;
+    }
+    serde_json::from_slice::<Value>(payload).is_ok_and(|value| {
+        value
+            .get("choices")
+            .and_then(Value::as_array)
+            .is_some_and(Vec::is_empty)
+            && value.get("usage").is_some_and(Value::is_object)
+    })
+}
+
+/// 从转发给客户端的流中去掉路由器注入 `include_usage` 后上游追加的用量事件
+///
+/// 按行缓冲，未以换行结尾的部分留到下一个分块或 [`UsageEventFilter::finish`] 再输出；
+/// 用量事件行及其后作为事件分隔的空行一并丢弃
+#[derive(Debug, Default)]
+pub struct UsageEventFilter {
+    line: Vec<u8>,
+    skip_separator: bool,
+}
+
+impl UsageEventFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 过滤一个分块，返回可以转发的字节
+    pub fn filter(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(chunk.len() + self.line.len());
+        for byte in chunk {
+            self.line.push(*byte);
+            if *byte == b'\n' {
+                let line = std::mem::take(&mut self.line);
+                self.emit_line(&line, &mut output);
+            }
+        }
+        output
+    }
+
+    /// 返回缓冲中尚未以换行结尾的剩余字节
+    pub fn finish(self) -> Vec<u8> {
+        self.line
+    }
+
+    fn emit_line(&mut self, line: &[u8], output: &mut Vec<u8>) {
+        if std::mem::take(&mut self.skip_separator) && trim_ascii(line).is_empty() {
+            return;
+        }
+        if is_usage_only_event(line) {
+            self.skip_separator = true;
+            return;
+        }
+        output.extend_from_slice(line);
+    }
+}
+
+/// 去掉完整流式响应体中的用量事件（如写入响应缓存的流式响应）
+pub fn strip_usage_events(body: &[u8]) -> Vec<u8> {
+    let mut filter = UsageEventFilter::new();
+    let mut output = filter.filter(body);
+    output.extend(filter.finish());
+    output
+}
+
+/// 从单个 JSON 对象中提取用量
+fn usage_from_value(value: &Value) -> Option<TokenUsage> {
+    // OpenAI / Anthropic / Cohere v2：顶层 usage
+    if let Some(usage) = value.get("usage").and_then(usage_from_object) {
+        return Some(usage);
+    }
+    // Anthropic 流式 message_start：message.usage
+    if let Some(usage) = value.pointer("/message/usage").and_then(usage_from_object) {
+        return Some(usage);
+    }
+    // Gemini 原生格式：usageMetadata
+    if let Some(meta) = value.get("usageMetadata") {
+        return Some(TokenUsage {
+            prompt_tokens: read_u64(meta, "promptTokenCount"),
+            completion_tokens: read_u64(meta, "candidatesTokenCount"),
+        });
+    }
+    // Cohere v1：meta.billed_units（流式 stream-end 事件位于 response.meta）
+    if let Some(units) = value
+        .pointer("/meta/billed_units")
+        .or_else(|| value.pointer("/response/meta/billed_units"))
+    {
+        return usage_from_object(units);
+    }
+    // Ollama 原生格式：prompt_eval_count / eval_count
+    if value.get("prompt_eval_count").is_some() || value.get("eval_count").is_some() {
+        return Some(TokenUsage {
+            prompt_tokens: read_u64(value, "prompt_eval_count"),
+            completion_tokens: read_u64(value, "eval_count"),
+        });
+    }
+    None
+}
+
+/// 解析 OpenAI（prompt/completion）或 Anthropic/Cohere（input/output）风格的用量对象
+fn usage_from_object(usage: &Value) -> Option<TokenUsage> {
+    if !usage.is_object() {
+        return None;
+    }
+    if let Some(units) = usage.get("billed_units") {
+        return usage_from_object(units);
+    }
+    let prompt = usage
+        .get("prompt_tokens")
+        .or_else(|| usage.get("input_tokens"))
+        .and_then(Value::as_u64);
+    let completion = usage
+        .get("completion_tokens")
+        .or_else(|| usage.get("output_tokens"))
+        .and_then(Value::as_u64);
+    if prompt.is_none() && completion.is_none() {
+        return None;
+    }
+    Some(TokenUsage {
+        prompt_tokens: prompt.unwrap_or(0),
+        completion_tokens: completion.unwrap_or(0),
+    })
+}
+
+fn read_u64(value: &Value, field: &str) -> u64 {
+    value.get(field).and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// 流式响应用量追踪器
+///
+/// 逐行解析 SSE 事件与 NDJSON 行，非 JSON 行（HTTP 头、心跳、分块长度等）会被忽略
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    line: Vec<u8>,
+    usage: Option<TokenUsage>,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 返回目前累计的用量，处理尚未以换行结尾的最后一行
+    pub fn finish(mut self) -> Option<TokenUsage> {
+        let line = std::mem::take(&mut self.line);
+        self.process_line(&line);
+        self.usage
+    }
+
+    fn process_line(&mut self, line: &[u8]) {
+        let line = trim_ascii(line);
+        let payload = line.strip_prefix(b"data:").map(trim_ascii).unwrap_or(line);
+        if !payload.starts_with(b"{") {
+            return;
+        }
+        if let Some(found) = serde_json::from_slice::<Value>(payload)
+            .ok()
+            .as_ref()
+            .and_then(usage_from_value)
+        {
+            self.usage
+                .get_or_insert_with(TokenUsage::default)
+                .merge(found);
+        }
+    }
+}
+
+impl StreamObserver for UsageTracker {
+    fn on_chunk(&mut self, chunk: &[u8]) {
+        for byte in chunk {
+            if *byte == b'\n' {
+                let line = std::mem::take(&mut self.line);
+                self.process_line(&line);
+            } else if self.line.len() < MAX_LINE_BYTES {
+                self.line.push(*byte);
+            }
+        }
+    }
+}
+
+fn trim_ascii(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map(|i| i + 1)
+        .unwrap_or(start);
+    &bytes[start..end.max(start)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt: u64, completion: u64) -> Option<TokenUsage> {
+        Some(TokenUsage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+        })
+    }
+
+    #[test]
+    fn extracts_openai_usage() {
+        let body = br#"{"id":"x","choices":[],"usage":{"prompt_tokens":12,"completion_tokens":34,"total_tokens":46}}"#;
+        assert_eq!(extract_usage(body), usage(12, 34));
+    }
+
+    #[test]
+    fn extracts_anthropic_usage() {
+        let body =
+            br#"{"id":"msg","type":"message","usage":{"input_tokens":10,"output_tokens":20}}"#;
+        assert_eq!(extract_usage(body), usage(10, 20));
+    }
+
+    #[test]
+    fn extracts_gemini_ollama_and_cohere_usage() {
+        let gemini =
+            br#"{"candidates":[],"usageMetadata":{"promptTokenCount":5,"candidatesTokenCount":7}}"#;
+        assert_eq!(extract_usage(gemini), usage(5, 7));
+
+        let ollama = br#"{"model":"llama3","done":true,"prompt_eval_count":26,"eval_count":290}"#;
+        assert_eq!(extract_usage(ollama), usage(26, 290));
+
+        let cohere =
+            br#"{"text":"hi","meta":{"billed_units":{"input_tokens":3,"output_tokens":9}}}"#;
+        assert_eq!(extract_usage(cohere), usage(3, 9));
+    }
+
+    #[test]
+    fn extracts_usage_from_chunked_body() {
+        let body = b"4a\r\n{\"usage\":{\"prompt_tokens\":1,\"completion_tokens\":2}}\r\n0\r\n\r\n";
+        assert_eq!(extract_usage(body), usage(1, 2));
+    }
+
+    #[test]
+    fn extracts_usage_from_body_split_across_chunks() {
+        let body = b"11\r\n{\"usage\":{\"prompt\r\n1e\r\n_tokens\":1,\"completion_tokens\"\r\n4;ext=1\r\n:2}}\r\n0\r\n\r\n";
+        assert_eq!(extract_usage(body), usage(1, 2));
+        assert_eq!(decode_chunked(b"5\r\nabc"), None);
+    }
+
+    #[test]
+    fn stream_usage_is_requested_only_for_streaming() {
+        let mut options = None;
+        assert!(!request_stream_usage(Some(false), &mut options));
+        assert_eq!(options, None);
+
+        assert!(request_stream_usage(Some(true), &mut options));
+        assert_eq!(options, Some(serde_json::json!({"include_usage": true})));
+
+        let mut options = Some(serde_json::json!({"include_usage": false, "other": 1}));
+        assert!(request_stream_usage(Some(true), &mut options));
+        assert_eq!(
+            options,
+            Some(serde_json::json!({"include_usage": true, "other": 1}))
+        );
+
+        // 客户端自行开启时用量事件属于客户端，不需要过滤
+        assert!(!request_stream_usage(Some(true), &mut options));
+    }
+
+    #[test]
+    fn usage_event_filter_drops_usage_only_events() {
+        let stream = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}],\"usage\":null}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":1}}\n\n",
+            "data: [DONE]\n\n"
+        )
+        .as_bytes();
+        let expected = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}],\"usage\":null}\n\n",
+            "data: [DONE]\n\n"
+        )
+        .as_bytes();
+
+        let mut filter = UsageEventFilter::new();
+        let mut output = Vec::new();
+        for chunk in stream.chunks(7) {
+            output.extend(filter.filter(chunk));
+        }
+        output.extend(filter.finish());
+        assert_eq!(output, expected);
+        assert_eq!(strip_usage_events(stream), expected);
+        assert_eq!(strip_usage_events(b"data: partial"), b"data: partial");
+    }
+
+    #[test]
+    fn missing_usage_returns_none() {
+        assert_eq!(extract_usage(br#"{"data":[]}"#), None);
+        assert_eq!(extract_usage(br#"{"usage":null}"#), None);
+        assert_eq!(extract_usage(b"not json"), None);
+    }
+
+    #[test]
+    fn tracker_reads_openai_final_chunk_across_reads() {
+        let mut tracker = UsageTracker::new();
+        tracker.on_chunk(b"data: {\"choices\":[{\"delta\":{}}],\"usage\":null}\n\n");
+        tracker.on_chunk(b"data: {\"choices\":[],\"usage\":{\"prompt_to");
+        tracker.on_chunk(b"kens\":8,\"completion_tokens\":16}}\n\ndata: [DONE]\n\n");
+        assert_eq!(tracker.finish(), usage(8, 16));
+    }
+
+    #[test]
+    fn tracker_merges_anthropic_events() {
+        let mut tracker = UsageTracker::new();
+        tracker.on_chunk(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n");
+        tracker.on_chunk(b": heartbeat\n\n");
+        tracker.on_chunk(b"event: message_delta\ndata: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":15}}\n\n");
+        assert_eq!(tracker.finish(), usage(25, 15));
+    }
+
+    #[test]
+    fn tracker_reads_ndjson_without_trailing_newline() {
+        let mut tracker = UsageTracker::new();
+        tracker.on_chunk(
+            b"{\"done\":false}\n{\"done\":true,\"prompt_eval_count\":4,\"eval_count\":6}",
+        );
+        assert_eq!(tracker.finish(), usage(4, 6));
+    }
+
+    #[test]
+    fn tracker_without_usage_returns_none() {
+        let mut tracker = UsageTracker::new();
+        tracker.on_chunk(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\ndata: {\"choices\":[]}\n\n",
+        );
+        assert_eq!(tracker.finish(), None);
+    }
+
+    #[test]
+    fn cost_uses_per_million_prices() {
+        let price = ModelPrice {
+            input_per_million: 2.0,
+            output_per_million: 8.0,
+        };
+        let usage = TokenUsage {
+            prompt_tokens: 500_000,
+            completion_tokens: 250_000,
+        };
+        assert!((usage.cost(&price) - 3.0).abs() < 1e-9);
+    }
+}
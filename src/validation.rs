@@ -0,0 +1,761 @@
+//! 配置校验模块
+//!
+//! 展开字符串中的插值表达式（见 [`secrets`](crate::secrets)）后，在加载配置时一次性报告全部问题，每个问题带有 JSON 路径（如 `$.endpoints["/v1/chat/completions"].method`）：
+//! - 未知字段与数值范围：按 [`config_schema`] 生成的 JSON Schema 检查原始 JSON，拼写错误的字段给出建议
+//! - 类型错误：反序列化失败时定位到具体字段
+//! - 语义检查：URL、路由、HTTP 方法、请求头、代理与 TLS 设置、字段之间的约束
+//!
+//! 发布的 JSON Schema 位于 `schema/config.schema.json`，由 `api-router print-config-schema` 生成
+
+use crate::config::{
+    AccessLogSink, ApiConfig, CircuitBreakerConfig, EndpointConfig, FallbackConfig, HedgeConfig,
+    RetryConfig, TlsProfileConfig,
+};
+use crate::handlers::parser::anonymize_key;
+use crate::handlers::router::PROXY_ROUTES;
+use crate::proxy::ProxyEndpoint;
+use crate::response_cache::CACHEABLE_ROUTES;
+use crate::secrets;
+use crate::url_parser::Url;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// 端点允许覆盖的 HTTP 方法
+const ENDPOINT_METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE"];
+
+/// 支持的最低 TLS 版本
+const TLS_VERSIONS: &[&str] = &["1.2", "1.3"];
+
+/// 单个配置问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// JSON 路径，根为 `$`
+    pub path: String,
+    /// 问题描述
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// 将问题列表拼接为一行，用于错误信息
+pub fn join_issues(issues: &[ConfigIssue]) -> String {
+    issues
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// 配置格式的 JSON Schema
+pub fn config_schema() -> Value {
+    serde_json::to_value(schemars::schema_for!(ApiConfig)).unwrap_or(Value::Null)
+}
+
+/// 解析 JSON 配置文本，展开插值表达式后校验，存在问题时返回全部问题
+pub fn parse_config(text: &str) -> Result<ApiConfig, Vec<ConfigIssue>> {
+    let raw: Value = serde_json::from_str(text).map_err(|err| {
+        vec![ConfigIssue {
+            path: "$".to_string(),
+            message: format!("JSON 语法错误: {}", err),
+        }]
+    })?;
+    parse_config_value(raw)
+}
+
+/// 展开 JSON 中所有字符串与对象键里的插值表达式，见 [`secrets`](crate::secrets)
+fn interpolate_value(value: &mut Value, path: &str, issues: &mut Vec<ConfigIssue>) {
+    match value {
+        Value::String(text) => {
+            if let Some(expanded) = expand(text, path, issues) {
+                *text = expanded;
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                interpolate_value(item, &index_path(path, index), issues);
+            }
+        }
+        Value::Object(map) => {
+            let entries: Vec<_> = std::mem::take(map).into_iter().collect();
+            for (key, mut child) in entries {
+                let child_path = field_path(path, &key);
+                interpolate_value(&mut child, &child_path, issues);
+                let key = expand(&key, &child_path, issues).unwrap_or(key);
+                map.insert(key, child);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 展开插值表达式，校验已解析（或由多个配置层合并）的 JSON 并转换为配置
+pub fn parse_config_value(mut raw: Value) -> Result<ApiConfig, Vec<ConfigIssue>> {
+    let mut issues = Vec::new();
+    interpolate_value(&mut raw, "$", &mut issues);
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+    let schema = config_schema();
+    SchemaWalker {
+        definitions: schema
+            .get("definitions")
+            .and_then(Value::as_object)
+            .unwrap_or(&Map::new()),
+        issues: &mut issues,
+    }
+    .walk(&raw, &schema, "$");
+
+    match serde_path_to_error::deserialize::<_, ApiConfig>(raw) {
+        Ok(config) => {
+            issues.extend(validate(&config));
+            if issues.is_empty() {
+                Ok(config)
+            } else {
+                Err(issues)
+            }
+        }
+        Err(err) => {
+            let path = segments_to_path(err.path());
+            // 数值越界时 Schema 已报告同一路径，不再重复
+            if !issues.iter().any(|issue| issue.path == path) {
+                issues.push(ConfigIssue {
+                    path,
+                    message: err.into_inner().to_string(),
+                });
+            }
+            Err(issues)
+        }
+    }
+}
+
+fn segments_to_path(path: &serde_path_to_error::Path) -> String {
+    let mut json_path = "$".to_string();
+    for segment in path.iter() {
+        match segment {
+            serde_path_to_error::Segment::Seq { index } => {
+                json_path = index_path(&json_path, *index);
+            }
+            serde_path_to_error::Segment::Map { key } => {
+                json_path = field_path(&json_path, key);
+            }
+            serde_path_to_error::Segment::Enum { variant } => {
+                json_path = field_path(&json_path, variant);
+            }
+            serde_path_to_error::Segment::Unknown => json_path.push_str(".?"),
+        }
+    }
+    json_path
+}
+
+/// 拼接字段路径：标识符形式的键用 `.key`，其余用 `["key"]`
+fn field_path(parent: &str, key: &str) -> String {
+    let identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if identifier {
+        format!("{}.{}", parent, key)
+    } else {
+        format!(
+            "{}[{}]",
+            parent,
+            serde_json::to_string(key).unwrap_or_default()
+        )
+    }
+}
+
+fn index_path(parent: &str, index: usize) -> String {
+    format!("{}[{}]", parent, index)
+}
+
+fn expand(text: &str, path: &str, issues: &mut Vec<ConfigIssue>) -> Option<String> {
+    match secrets::interpolate(text) {
+        Ok(expanded) => Some(expanded),
+        Err(message) => {
+            issues.push(ConfigIssue {
+                path: path.to_string(),
+                message,
+            });
+            None
+        }
+    }
+}
+
+/// 按 JSON Schema 检查未知字段与数值范围
+struct SchemaWalker<'a> {
+    definitions: &'a Map<String, Value>,
+    issues: &'a mut Vec<ConfigIssue>,
+}
+
+/// 展开 `$ref` 以及 `Option` / 带描述字段生成的 `anyOf` / `allOf` 包装
+pub(crate) fn resolve_schema<'s>(
+    definitions: &'s Map<String, Value>,
+    schema: &'s Value,
+) -> &'s Value {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.trim_start_matches("#/definitions/");
+        if let Some(definition) = definitions.get(name) {
+            return resolve_schema(definitions, definition);
+        }
+    }
+    for combinator in ["allOf", "anyOf"] {
+        if let Some(options) = schema.get(combinator).and_then(Value::as_array) {
+            if let Some(option) = options
+                .iter()
+                .find(|option| option.get("type").and_then(Value::as_str) != Some("null"))
+            {
+                return resolve_schema(definitions, option);
+            }
+        }
+    }
+    schema
+}
+
+impl SchemaWalker<'_> {
+    fn walk(&mut self, value: &Value, schema: &Value, path: &str) {
+        let schema = resolve_schema(self.definitions, schema);
+        if let Some(number) = value.as_f64() {
+            if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < minimum {
+                    self.issue(path, format!("不能小于 {}", format_number(minimum)));
+                }
+            }
+            if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > maximum {
+                    self.issue(path, format!("不能大于 {}", format_number(maximum)));
+                }
+            }
+        }
+
+        match value {
+            Value::Object(map) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                let additional = schema.get("additionalProperties");
+                for (key, child) in map {
+                    let child_path = field_path(path, key);
+                    if let Some(property) = properties.and_then(|properties| properties.get(key)) {
+                        self.walk(child, property, &child_path);
+                        continue;
+                    }
+                    match additional {
+                        Some(Value::Bool(false)) => {
+                            let message = match properties.and_then(|p| suggest(key, p)) {
+                                Some(known) => format!("未知字段 `{}`，是否为 `{}`？", key, known),
+                                None => format!("未知字段 `{}`", key),
+                            };
+                            self.issue(&child_path, message);
+                        }
+                        Some(additional @ Value::Object(_)) => {
+                            self.walk(child, additional, &child_path)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(item_schema) = schema.get("items") {
+                    for (index, item) in items.iter().enumerate() {
+                        self.walk(item, item_schema, &index_path(path, index));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn issue(&mut self, path: &str, message: String) {
+        self.issues.push(ConfigIssue {
+            path: path.to_string(),
+            message,
+        });
+    }
+}
+
+fn format_number(number: f64) -> String {
+    if number.fract() == 0.0 {
+        format!("{}", number as i64)
+    } else {
+        number.to_string()
+    }
+}
+
+/// 为拼错的字段找出最接近的已知字段（忽略大小写或编辑距离不超过 2）
+fn suggest<'p>(key: &str, properties: &'p Map<String, Value>) -> Option<&'p str> {
+    let lowered = key.to_ascii_lowercase();
+    properties
+        .keys()
+        .map(|known| {
+            let distance = if known.to_ascii_lowercase() == lowered {
+                0
+            } else {
+                edit_distance(&lowered, &known.to_ascii_lowercase())
+            };
+            (distance, known.as_str())
+        })
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, known)| known)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// 对已反序列化的配置做语义检查
+pub fn validate(config: &ApiConfig) -> Vec<ConfigIssue> {
+    let mut validator = Validator::default();
+    validator.check(config);
+    validator.issues
+}
+
+/// 按键排序遍历 HashMap，保证报告顺序稳定
+fn sorted<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+#[derive(Default)]
+struct Validator {
+    issues: Vec<ConfigIssue>,
+}
+
+impl Validator {
+    fn issue(&mut self, path: &str, message: impl Into<String>) {
+        self.issues.push(ConfigIssue {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn check(&mut self, config: &ApiConfig) {
+        self.url("$.baseUrl", &config.base_url);
+        self.headers("$.headers", &config.headers);
+        if let Some(mapping) = &config.model_mapping {
+            for (client, upstream) in sorted(mapping) {
+                if client.trim().is_empty() || upstream.trim().is_empty() {
+                    self.issue(&field_path("$.modelMapping", client), "模型名不能为空");
+                }
+            }
+        }
+        for (route, endpoint) in sorted(&config.endpoints) {
+            let path = field_path("$.endpoints", route);
+            if !PROXY_ROUTES.contains(&route.as_str()) {
+                self.issue(
+                    &path,
+                    format!("不支持的路由，可选值: {}", PROXY_ROUTES.join(", ")),
+                );
+            }
+            self.endpoint(&path, endpoint);
+        }
+        self.keys(config);
+        if let Some(scheduler) = &config.scheduler {
+            if !scheduler.lanes.is_empty()
+                && !scheduler.lanes.contains_key(&scheduler.default_priority)
+            {
+                self.issue(
+                    "$.scheduler.defaultPriority",
+                    format!("`{}` 不是已配置的通道", scheduler.default_priority),
+                );
+            }
+        }
+        if let Some(buckets) = config
+            .metrics
+            .as_ref()
+            .and_then(|m| m.latency_buckets.as_ref())
+        {
+            if buckets.iter().any(|bucket| *bucket <= 0.0)
+                || buckets.windows(2).any(|pair| pair[0] >= pair[1])
+            {
+                self.issue("$.metrics.latencyBuckets", "桶上界必须为正数且严格递增");
+            }
+        }
+        if let Some(capture) = &config.capture {
+            for (index, route) in capture.routes.iter().enumerate() {
+                if !PROXY_ROUTES.contains(&route.as_str()) {
+                    self.issue(&index_path("$.capture.routes", index), "不支持的路由");
+                }
+            }
+        }
+        if let Some(access_log) = &config.access_log {
+            if access_log.sink == AccessLogSink::File && access_log.path.trim().is_empty() {
+                self.issue("$.accessLog.path", "sink 为 file 时不能为空");
+            }
+        }
+        if let Some(alerting) = &config.alerting {
+            for (index, webhook) in alerting.webhooks.iter().enumerate() {
+                let path = index_path("$.alerting.webhooks", index);
+                self.url(&field_path(&path, "url"), &webhook.url);
+                self.headers(&field_path(&path, "headers"), &webhook.headers);
+            }
+        }
+        if let Some(breaker) = &config.circuit_breaker {
+            self.circuit_breaker("$.circuitBreaker", breaker);
+        }
+        if let Some(retry) = &config.retry {
+            self.retry("$.retry", retry);
+        }
+        if let Some(hedge) = &config.hedge {
+            self.hedge("$.hedge", hedge);
+        }
+        if let Some(proxy) = &config.proxy {
+            if let Some(url) = &proxy.url {
+                self.proxy_url("$.proxy.url", url);
+            }
+            for (provider, url) in sorted(&proxy.providers) {
+                if !url.eq_ignore_ascii_case("direct") {
+                    self.proxy_url(&field_path("$.proxy.providers", provider), url);
+                }
+            }
+        }
+        if let Some(tls) = &config.tls {
+            self.tls_profile("$.tls", &tls.default);
+            for (provider, profile) in sorted(&tls.providers) {
+                self.tls_profile(&field_path("$.tls.providers", provider), profile);
+            }
+        }
+        if let Some(server_tls) = &config.server_tls {
+            if server_tls.cert_file.trim().is_empty() {
+                self.issue("$.serverTls.certFile", "不能为空");
+            }
+            if server_tls.key_file.trim().is_empty() {
+                self.issue("$.serverTls.keyFile", "不能为空");
+            }
+        }
+        if let Some(listen) = &config.listen {
+            for (index, address) in listen.tcp.iter().enumerate() {
+                self.listen_address(&index_path("$.listen.tcp", index), address);
+            }
+            for (index, unix) in listen.unix.iter().enumerate() {
+                let path = index_path("$.listen.unix", index);
+                if unix.path.trim().is_empty() {
+                    self.issue(&field_path(&path, "path"), "不能为空");
+                }
+                #[cfg(unix)]
+                if let Some(Err(err)) = unix.mode.as_deref().map(crate::listener::parse_mode) {
+                    self.issue(&field_path(&path, "mode"), err.to_string());
+                }
+            }
+        }
+        if let Some(admin) = &config.admin {
+            if admin
+                .token
+                .as_deref()
+                .is_some_and(|token| token.trim().is_empty())
+            {
+                self.issue("$.admin.token", "不能为空");
+            }
+            if let Some(address) = &admin.listen {
+                self.listen_address("$.admin.listen", address);
+            }
+        }
+        if let Some(cache) = &config.response_cache {
+            if cache
+                .path
+                .as_deref()
+                .is_some_and(|path| path.trim().is_empty())
+            {
+                self.issue("$.responseCache.path", "不能为空");
+            }
+            for (index, route) in cache.routes.iter().enumerate() {
+                if !CACHEABLE_ROUTES.contains(&route.as_str()) {
+                    self.issue(
+                        &index_path("$.responseCache.routes", index),
+                        format!("不支持的路由，可选值: {}", CACHEABLE_ROUTES.join(", ")),
+                    );
+                }
+            }
+        }
+    }
+
+    fn listen_address(&mut self, path: &str, address: &str) {
+        let valid = address
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        if !valid {
+            self.issue(path, "应为 host:port 形式，IPv6 地址写作 [::1]:8000");
+        }
+    }
+
+    fn endpoint(&mut self, path: &str, endpoint: &EndpointConfig) {
+        if let Some(upstream_path) = &endpoint.upstream_path {
+            if !upstream_path.starts_with('/') {
+                self.issue(&field_path(path, "upstreamPath"), "必须以 / 开头");
+            }
+        }
+        if let Some(method) = &endpoint.method {
+            if !ENDPOINT_METHODS.contains(&method.to_ascii_uppercase().as_str()) {
+                self.issue(
+                    &field_path(path, "method"),
+                    format!(
+                        "不支持的 HTTP 方法 `{}`，可选值: {}",
+                        method,
+                        ENDPOINT_METHODS.join(", ")
+                    ),
+                );
+            }
+        }
+        self.headers(&field_path(path, "headers"), &endpoint.headers);
+        if let Some(breaker) = &endpoint.circuit_breaker {
+            self.circuit_breaker(&field_path(path, "circuitBreaker"), breaker);
+        }
+        if let Some(retry) = &endpoint.retry {
+            self.retry(&field_path(path, "retry"), retry);
+        }
+        if let Some(hedge) = &endpoint.hedge {
+            self.hedge(&field_path(path, "hedge"), hedge);
+        }
+    }
+
+    /// Key 本身是凭据，路径中使用脱敏后的 Key
+    fn keys(&mut self, config: &ApiConfig) {
+        for (key, key_config) in sorted(&config.keys) {
+            let path = field_path("$.keys", &anonymize_key(key));
+            if key.trim().is_empty() {
+                self.issue(&path, "API Key 不能为空");
+            }
+            let Some(priority) = &key_config.priority else {
+                continue;
+            };
+            match &config.scheduler {
+                Some(scheduler) if scheduler.lanes.contains_key(priority) => {}
+                Some(_) => self.issue(
+                    &field_path(&path, "priority"),
+                    format!("`{}` 不是 scheduler.lanes 中的通道", priority),
+                ),
+                None => self.issue(
+                    &field_path(&path, "priority"),
+                    "未配置 scheduler，priority 不会生效",
+                ),
+            }
+        }
+    }
+
+    fn url(&mut self, path: &str, url: &str) {
+        if url.trim().is_empty() {
+            self.issue(path, "不能为空");
+        } else if let Err(err) = Url::parse(url) {
+            self.issue(path, format!("无效的 URL: {}", err));
+        }
+    }
+
+    fn fallback(&mut self, path: &str, fallback: &FallbackConfig) {
+        self.url(&field_path(path, "baseUrl"), &fallback.base_url);
+        self.headers(&field_path(path, "headers"), &fallback.headers);
+    }
+
+    /// 请求头名称必须是合法的 token，值不能包含换行
+    fn headers(&mut self, path: &str, headers: &HashMap<String, String>) {
+        for (name, value) in sorted(headers) {
+            let valid_name = !name.is_empty()
+                && name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+            if !valid_name {
+                self.issue(&field_path(path, name), "无效的请求头名称");
+            } else if value.contains(['\r', '\n']) {
+                self.issue(&field_path(path, name), "请求头的值不能包含换行");
+            }
+        }
+    }
+
+    fn circuit_breaker(&mut self, path: &str, breaker: &CircuitBreakerConfig) {
+        if breaker.failure_rate_threshold <= 0.0 {
+            self.issue(&field_path(path, "failureRateThreshold"), "必须大于 0");
+        }
+        if let Some(fallback) = &breaker.fallback {
+            self.fallback(&field_path(path, "fallback"), fallback);
+        }
+    }
+
+    fn retry(&mut self, path: &str, retry: &RetryConfig) {
+        if retry.base_delay_ms > retry.max_delay_ms {
+            self.issue(
+                &field_path(path, "baseDelayMs"),
+                format!("不能大于 maxDelayMs（{}）", retry.max_delay_ms),
+            );
+        }
+        let status_path = field_path(path, "retryOnStatus");
+        for (index, status) in retry.retry_on_status.iter().enumerate() {
+            if !(100..=599).contains(status) {
+                self.issue(&index_path(&status_path, index), "不是有效的 HTTP 状态码");
+            }
+        }
+    }
+
+    fn hedge(&mut self, path: &str, hedge: &HedgeConfig) {
+        if hedge.delay_ms.is_none() && hedge.percentile.is_none() {
+            self.issue(path, "需要配置 delayMs 或 percentile");
+        }
+        if let Some(alternate) = &hedge.alternate {
+            self.fallback(&field_path(path, "alternate"), alternate);
+        }
+    }
+
+    fn proxy_url(&mut self, path: &str, url: &str) {
+        if let Err(err) = ProxyEndpoint::parse(url) {
+            self.issue(path, err.to_string());
+        }
+    }
+
+    fn tls_profile(&mut self, path: &str, profile: &TlsProfileConfig) {
+        if let Some(version) = &profile.min_version {
+            if !TLS_VERSIONS.contains(&version.as_str()) {
+                self.issue(
+                    &field_path(path, "minVersion"),
+                    format!("不支持的 TLS 版本 `{}`，可选值: 1.2, 1.3", version),
+                );
+            }
+        }
+        for (index, pin) in profile.spki_pins.iter().enumerate() {
+            if crate::tls::parse_pin(pin).is_err() {
+                self.issue(
+                    &index_path(&field_path(path, "spkiPins"), index),
+                    "应为 sha256/<base64> 形式的 SPKI 指纹",
+                );
+            }
+        }
+        if profile.client_cert.is_some() != profile.client_key.is_some() {
+            self.issue(path, "clientCert 与 clientKey 必须同时配置");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issues(text: &str) -> Vec<String> {
+        parse_config(text)
+            .err()
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_bundled_transformer_configs() {
+        for entry in std::fs::read_dir("transformer").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).unwrap();
+            if let Err(issues) = parse_config(&text) {
+                panic!("{}: {}", path.display(), join_issues(&issues));
+            }
+        }
+    }
+
+    #[test]
+    fn reports_all_unknown_fields_with_paths_and_suggestions() {
+        let reported = issues(
+            r#"{
+                "baseUrl": "https://api.example.com",
+                "prot": 8000,
+                "endpoints": {
+                    "/v1/chat/completions": {"upstreampath": "/chat", "streamsupport": true}
+                },
+                "tls": {"caFile": ["/etc/ca.pem"], "providers": {"openai": {"minversion": "1.3"}}},
+                "alerting": {"webhooks": [{"url": "https://hooks.example.com", "fromat": "slack"}]}
+            }"#,
+        );
+        assert_eq!(
+            reported,
+            vec![
+                "$.alerting.webhooks[0].fromat: 未知字段 `fromat`，是否为 `format`？",
+                "$.endpoints[\"/v1/chat/completions\"].streamsupport: 未知字段 `streamsupport`，是否为 `streamSupport`？",
+                "$.endpoints[\"/v1/chat/completions\"].upstreampath: 未知字段 `upstreampath`，是否为 `upstreamPath`？",
+                "$.prot: 未知字段 `prot`，是否为 `port`？",
+                "$.tls.caFile: 未知字段 `caFile`，是否为 `caFiles`？",
+                "$.tls.providers.openai.minversion: 未知字段 `minversion`，是否为 `minVersion`？",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_ranges_and_semantic_errors_together() {
+        let reported = issues(
+            r#"{
+                "baseUrl": "ftp://files.example.com",
+                "rateLimit": {"requestsPerMinute": 60, "burst": 0},
+                "endpoints": {
+                    "/v1/chat/completion": {"method": "FETCH", "upstreamPath": "chat"},
+                    "/v1/embeddings": {"hedge": {"minSamples": 5}}
+                },
+                "keys": {"sk-team-secret": {"priority": "interactive"}},
+                "circuitBreaker": {"failureRateThreshold": 1.5},
+                "retry": {"baseDelayMs": 5000, "maxDelayMs": 100, "retryOnStatus": [429, 1000]},
+                "proxy": {"url": "ftp://proxy:21"},
+                "tls": {"minVersion": "1.1", "clientCert": "/etc/client.pem"},
+                "listen": {"tcp": ["8000"]},
+                "admin": {"token": " ", "listen": "localhost"},
+                "responseCache": {"maxEntries": 0, "routes": ["/v1/audio/transcriptions"]}
+            }"#,
+        );
+        let expected_paths = [
+            "$.circuitBreaker.failureRateThreshold",
+            "$.rateLimit.burst",
+            "$.responseCache.maxEntries",
+            "$.baseUrl",
+            "$.endpoints[\"/v1/chat/completion\"]",
+            "$.endpoints[\"/v1/chat/completion\"].upstreamPath",
+            "$.endpoints[\"/v1/chat/completion\"].method",
+            "$.endpoints[\"/v1/embeddings\"].hedge",
+            "$.keys[\"sk-t***et\"].priority",
+            "$.retry.baseDelayMs",
+            "$.retry.retryOnStatus[1]",
+            "$.proxy.url",
+            "$.tls.minVersion",
+            "$.tls",
+            "$.listen.tcp[0]",
+            "$.admin.token",
+            "$.admin.listen",
+            "$.responseCache.routes[0]",
+        ];
+        assert_eq!(reported.len(), expected_paths.len(), "{:#?}", reported);
+        for (issue, path) in reported.iter().zip(expected_paths) {
+            assert!(issue.starts_with(&format!("{}: ", path)), "{}", issue);
+        }
+        assert!(!reported
+            .iter()
+            .any(|issue| issue.contains("sk-team-secret")));
+    }
+
+    #[test]
+    fn reports_type_errors_with_path() {
+        assert_eq!(
+            issues(r#"{"baseUrl": "https://x", "endpoints": {"/v1/embeddings": {"streamSupport": "yes"}}}"#),
+            vec!["$.endpoints[\"/v1/embeddings\"].streamSupport: invalid type: string \"yes\", expected a boolean"]
+        );
+        assert!(issues("{\"baseUrl\": ").len() == 1);
+    }
+
+    #[test]
+    fn published_schema_is_up_to_date() {
+        let published: Value =
+            serde_json::from_str(include_str!("../schema/config.schema.json")).unwrap();
+        assert_eq!(
+            published,
+            config_schema(),
+            "run `cargo run -- print-config-schema > schema/config.schema.json`"
+        );
+    }
+}
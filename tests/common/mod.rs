@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 pub mod fixtures;
 pub mod http;
 pub mod mock_provider;
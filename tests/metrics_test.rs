@@ -6,7 +6,7 @@ use std::time::Duration;
 
 fn start_test_server(port: u16) -> Child {
     Command::new("cargo")
-        .args(&["run", "--", "qwen", &port.to_string()])
+        .args(["run", "--", "qwen", &port.to_string()])
         .spawn()
         .expect("failed to start server")
 }
@@ -96,6 +96,7 @@ fn metrics_endpoint_returns_prometheus_format() {
     assert!(response.contains("status=\"200\""));
 
     server.kill().expect("failed to kill server");
+    let _ = server.wait();
 }
 
 #[test]
@@ -125,6 +126,7 @@ fn metrics_tracks_request_counts() {
     assert!(response.contains("route=\"/health\""));
 
     server.kill().expect("failed to kill server");
+    let _ = server.wait();
 }
 
 #[test]
@@ -151,6 +153,7 @@ fn metrics_tracks_latency() {
     assert!(response.contains("request_latency_seconds_count"));
 
     server.kill().expect("failed to kill server");
+    let _ = server.wait();
 }
 
 #[test]
@@ -174,6 +177,9 @@ fn metrics_tracks_404_errors() {
 
     assert!(response.contains("requests_total"));
     assert!(response.contains("status=\"404\""));
+    assert!(response.contains(r#"route="/unknown",method="GET",status="404""#));
+    assert!(!response.contains("/nonexistent"));
 
     server.kill().expect("failed to kill server");
+    let _ = server.wait();
 }